@@ -16,6 +16,7 @@ pub use jni::{
 pub use ndk;
 
 use super::{
+  main_pipe::{MainPipe, WebViewMessage},
   ASSET_LOADER_DOMAIN, EVAL_CALLBACKS, IPC, ON_LOAD_HANDLER, REQUEST_HANDLER, TITLE_CHANGE_HANDLER,
   URL_LOADING_OVERRIDE, WITH_ASSET_LOADER,
 };
@@ -125,6 +126,10 @@ fn handle_request(
 
     request_builder = request_builder.uri(&url);
 
+    let is_main_frame = env
+      .call_method(&request, "isForMainFrame", "()Z", &[])?
+      .z()?;
+
     let method = env
       .call_method(&request, "getMethod", "()Ljava/lang/String;", &[])?
       .l()
@@ -174,6 +179,7 @@ fn handle_request(
         webview_id,
         final_request,
         is_document_start_script_enabled != 0,
+        is_main_frame,
       )
     };
     if let Some(response) = response {
@@ -184,7 +190,8 @@ fn handle_request(
       } else if status_code > 599 {
         Some("statusCode can't be greater than 599.")
       } else if status_code > 299 && status_code < 400 {
-        Some("statusCode can't be in the [300, 399] range.")
+        // redirects of the main frame are followed by the request handler
+        Some("statusCode can't be in the [300, 399] range, only main frame redirects are followed.")
       } else {
         None
       };
@@ -333,7 +340,10 @@ pub unsafe fn ipc(mut env: JNIEnv, _: JClass, url: JString, body: JString) {
       let url = url.to_string_lossy().to_string();
       let body = body.to_string_lossy().to_string();
       if let Some(ipc) = IPC.get() {
-        (ipc.handler)(Request::builder().uri(url).body(body).unwrap())
+        let request = Request::builder().uri(url).body(body).unwrap();
+        if let Some(reply) = ipc.dispatcher.dispatch(request) {
+          MainPipe::send(WebViewMessage::Eval(reply, None));
+        }
       }
     }
     (Err(e), _) | (_, Err(e)) => {
@@ -3,24 +3,23 @@
 // SPDX-License-Identifier: MIT
 
 use super::{PageLoadEvent, WebViewAttributes, RGBA};
-use crate::{RequestAsyncResponder, Result};
-use base64::{engine::general_purpose, Engine};
-use crossbeam_channel::*;
-use html5ever::{interface::QualName, namespace_url, ns, tendril::TendrilSink, LocalName};
-use http::{
-  header::{HeaderValue, CONTENT_SECURITY_POLICY, CONTENT_TYPE},
-  Request, Response as HttpResponse,
+use crate::{
+  buffer_response,
+  custom_protocol_redirect::{self, Redirect},
+  custom_protocol_url::UrlStyle,
+  ipc::IpcDispatcher,
+  RequestAsyncResponder, ResponseBody, Result,
 };
+use crossbeam_channel::*;
+use http::{Request, Response as HttpResponse};
 use jni::{
   errors::Result as JniResult,
   objects::{GlobalRef, JClass, JObject},
   JNIEnv,
 };
-use kuchiki::NodeRef;
 use ndk::looper::{FdEvent, ThreadLooper};
 use once_cell::sync::OnceCell;
 use raw_window_handle::HasWindowHandle;
-use sha2::{Digest, Sha256};
 use std::{
   borrow::Cow,
   collections::HashMap,
@@ -61,8 +60,8 @@ macro_rules! define_static_handlers {
 }
 
 define_static_handlers! {
-  IPC =  UnsafeIpc { handler: Box<dyn Fn(Request<String>)> };
-  REQUEST_HANDLER = UnsafeRequestHandler { handler:  Box<dyn Fn(&str, Request<Vec<u8>>, bool) -> Option<HttpResponse<Cow<'static, [u8]>>>> };
+  IPC =  UnsafeIpc { dispatcher: IpcDispatcher };
+  REQUEST_HANDLER = UnsafeRequestHandler { handler:  Box<dyn Fn(&str, Request<Vec<u8>>, bool, bool) -> Option<HttpResponse<Cow<'static, [u8]>>>> };
   TITLE_CHANGE_HANDLER = UnsafeTitleHandler { handler: Box<dyn Fn(String)> };
   URL_LOADING_OVERRIDE = UnsafeUrlLoadingOverride { handler: Box<dyn Fn(String) -> bool> };
   ON_LOAD_HANDLER = UnsafeOnPageLoadHandler { handler: Box<dyn Fn(PageLoadEvent, String)> };
@@ -130,6 +129,13 @@ pub unsafe fn android_setup(
 
 pub(crate) struct InnerWebView {
   id: String,
+  url_style: UrlStyle,
+}
+
+impl Drop for InnerWebView {
+  fn drop(&mut self) {
+    custom_protocol_redirect::forget(&self.id);
+  }
 }
 
 impl InnerWebView {
@@ -151,6 +157,8 @@ impl InnerWebView {
       html,
       initialization_scripts,
       ipc_handler,
+      ipc_router,
+      ipc_policy,
       #[cfg(any(debug_assertions, feature = "devtools"))]
       devtools,
       custom_protocols,
@@ -169,22 +177,27 @@ impl InnerWebView {
       https_scheme,
     } = pl_attrs;
 
-    let scheme = if https_scheme { "https" } else { "http" };
+    let url_style = UrlStyle::workaround(https_scheme);
 
-    let url = if let Some(mut url) = url {
-      if let Some(pos) = url.find("://") {
-        let name = &url[..pos];
-        let is_custom_protocol = custom_protocols.iter().any(|(n, _)| n == name);
-        if is_custom_protocol {
-          url = url.replace(&format!("{name}://"), &format!("{scheme}://{name}."))
-        }
-      }
-
-      Some(url)
+    // `window.ipc` is the Java bridge object, which is wrapped to add `invoke` and binary messages
+    let initialization_scripts = if ipc_handler.is_some() || ipc_router.is_some() {
+      std::iter::once(format!(
+        "(function () {{ var bridge = window.ipc; {} }})()",
+        crate::ipc::ipc_script("function (s) { bridge.postMessage(s) }", false)
+      ))
+      .chain(initialization_scripts)
+      .collect()
     } else {
-      None
+      initialization_scripts
     };
 
+    let url = url.map(|url| {
+      custom_protocols
+        .iter()
+        .find_map(|(name, _)| url_style.to_platform(&url, name))
+        .unwrap_or(url)
+    });
+
     let id = attributes
       .id
       .map(|id| id.to_string())
@@ -212,90 +225,81 @@ impl InnerWebView {
 
     REQUEST_HANDLER.get_or_init(move || {
       UnsafeRequestHandler::new(Box::new(
-        move |webview_id: &str, mut request, is_document_start_script_enabled| {
+        move |webview_id: &str, mut request, is_document_start_script_enabled, is_main_frame| {
           let uri = request.uri().to_string();
-          if let Some(custom_protocol) = custom_protocols.iter().find(|(name, _)| {
-            uri.starts_with(&format!("{scheme}://{}.", name))
+          if let Some((custom_uri, custom_protocol)) = custom_protocols.iter().find_map(|p| {
+            url_style
+              .to_custom(&uri, &p.0)
+              .map(|custom_uri| (custom_uri, p))
           }) {
-            let uri_res = uri
-              .replace(
-                &format!("{scheme}://{}.", custom_protocol.0),
-                &format!("{}://", custom_protocol.0),
-              )
-              .parse();
-
-            if let Ok(uri) = uri_res {
+            if let Ok(uri) = custom_uri.parse() {
               *request.uri_mut() = uri;
             }
 
+            // without `addDocumentStartJavaScript`, the scripts are injected into the documents with a nonce
+            let nonce = (!is_document_start_script_enabled).then(crate::html::ScriptNonce::new);
+            if let Some(nonce) = &nonce {
+              request.extensions_mut().insert(nonce.clone());
+            }
+
             let (tx, rx) = channel();
             let initialization_scripts = initialization_scripts.clone();
-            let responder: Box<dyn FnOnce(HttpResponse<Cow<'static, [u8]>>)> =
-              Box::new(move |mut response| {
-                if !is_document_start_script_enabled {
+            let responder: Box<dyn FnOnce(HttpResponse<ResponseBody>)> =
+              Box::new(move |response| {
+                // the Android WebView reads the response from a Java stream, we hand it the whole body at once
+                let mut response = buffer_response(response);
+                if let Some(nonce) = nonce {
                   #[cfg(feature = "tracing")]
                   tracing::info!("`addDocumentStartJavaScript` is not supported; injecting initialization scripts via custom protocol handler");
-                  let should_inject_scripts = response
-                    .headers()
-                    .get(CONTENT_TYPE)
-                    // Content-Type must begin with the media type, but is case-insensitive.
-                    // It may also be followed by any number of semicolon-delimited key value pairs.
-                    // We don't care about these here.
-                    // source: https://httpwg.org/specs/rfc9110.html#rfc.section.8.3.1
-                    .and_then(|content_type| content_type.to_str().ok())
-                    .map(|content_type_str| {
-                      content_type_str.to_lowercase().starts_with("text/html")
-                    })
-                    .unwrap_or_default();
-
-                  if should_inject_scripts && !initialization_scripts.is_empty() {
-                    let mut document = kuchiki::parse_html()
-                      .one(String::from_utf8_lossy(response.body()).into_owned());
-                    let csp = response.headers_mut().get_mut(CONTENT_SECURITY_POLICY);
-                    let mut hashes = Vec::new();
-                    with_html_head(&mut document, |head| {
-                      // iterate in reverse order since we are prepending each script to the head tag
-                      for script in initialization_scripts.iter().rev() {
-                        let script_el = NodeRef::new_element(
-                          QualName::new(None, ns!(html), "script".into()),
-                          None,
-                        );
-                        script_el.append(NodeRef::new_text(script));
-                        head.prepend(script_el);
-                        if csp.is_some() {
-                          hashes.push(hash_script(script));
-                        }
-                      }
-                    });
-
-                    if let Some(csp) = csp {
-                      let csp_string = csp.to_str().unwrap().to_string();
-                      let csp_string = if csp_string.contains("script-src") {
-                        csp_string
-                          .replace("script-src", &format!("script-src {}", hashes.join(" ")))
-                      } else {
-                        format!("{} script-src {}", csp_string, hashes.join(" "))
-                      };
-                      *csp = HeaderValue::from_str(&csp_string).unwrap();
-                    }
-
-                    *response.body_mut() = document.to_string().into_bytes().into();
-                  }
+                  crate::html::inject_scripts_with_nonce(
+                    &mut response,
+                    &initialization_scripts,
+                    &nonce,
+                  );
                 }
 
                 tx.send(response).unwrap();
               });
 
-            (custom_protocol.1)(webview_id, request, RequestAsyncResponder { responder });
-            return Some(rx.recv().unwrap());
+            let responder = RequestAsyncResponder {
+              responder,
+              // the WebView doesn't notify about cancelled requests
+              cancellation: Default::default(),
+            };
+            (custom_protocol.1)(webview_id, request, responder);
+            let response = rx.recv().unwrap();
+            if !is_main_frame {
+              return Some(response);
+            }
+
+            // the Android WebView can't redirect intercepted requests, so the target is loaded instead
+            return Some(
+              match custom_protocol_redirect::follow(webview_id, &custom_uri, &response) {
+                None => response,
+                Some(Redirect::To(target)) => {
+                  let target = custom_protocols
+                    .iter()
+                    .find_map(|(name, _)| url_style.to_platform(&target, name))
+                    .unwrap_or(target);
+                  MainPipe::send(WebViewMessage::LoadUrl(target, None));
+                  custom_protocol_redirect::no_content()
+                }
+                Some(Redirect::TooMany) => {
+                  #[cfg(feature = "tracing")]
+                  tracing::warn!("too many redirects loading {custom_uri}");
+                  custom_protocol_redirect::too_many_redirects()
+                }
+              },
+            );
           }
           None
         },
       ))
     });
 
-    if let Some(i) = ipc_handler {
-      IPC.get_or_init(move || UnsafeIpc::new(Box::new(i)));
+    let ipc_policy = ipc_policy.map(|policy| policy.resolve(url_style));
+    if let Some(dispatcher) = IpcDispatcher::new(ipc_handler, ipc_router, ipc_policy) {
+      IPC.get_or_init(move || UnsafeIpc::new(dispatcher));
     }
 
     if let Some(i) = attributes.document_title_changed_handler {
@@ -310,7 +314,7 @@ impl InnerWebView {
       ON_LOAD_HANDLER.get_or_init(move || UnsafeOnPageLoadHandler::new(h));
     }
 
-    Ok(Self { id })
+    Ok(Self { id, url_style })
   }
 
   pub fn print(&self) -> crate::Result<()> {
@@ -327,6 +331,10 @@ impl InnerWebView {
     rx.recv().map_err(Into::into)
   }
 
+  pub fn custom_protocol_url_style(&self) -> UrlStyle {
+    self.url_style
+  }
+
   pub fn eval(&self, js: &str, callback: Option<impl Fn(String) + Send + 'static>) -> Result<()> {
     MainPipe::send(WebViewMessage::Eval(
       js.into(),
@@ -430,26 +438,6 @@ pub fn platform_webview_version() -> Result<String> {
   rx.recv().unwrap()
 }
 
-fn with_html_head<F: FnOnce(&NodeRef)>(document: &mut NodeRef, f: F) {
-  if let Ok(ref node) = document.select_first("head") {
-    f(node.as_node())
-  } else {
-    let node = NodeRef::new_element(
-      QualName::new(None, ns!(html), LocalName::from("head")),
-      None,
-    );
-    f(&node);
-    document.prepend(node)
-  }
-}
-
-fn hash_script(script: &str) -> String {
-  let mut hasher = Sha256::new();
-  hasher.update(script);
-  let hash = hasher.finalize();
-  format!("'sha256-{}'", general_purpose::STANDARD.encode(hash))
-}
-
 /// Finds a class in the project scope.
 pub fn find_class<'a>(
   env: &mut JNIEnv<'a>,
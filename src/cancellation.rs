@@ -0,0 +1,212 @@
+// Copyright 2020-2024 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+use std::{
+  fmt,
+  future::Future,
+  pin::Pin,
+  sync::{
+    atomic::{AtomicU8, Ordering},
+    Arc, Mutex,
+  },
+  task::{Context, Poll, Waker},
+};
+
+const PENDING: u8 = 0;
+const FINISHED: u8 = 1;
+const CANCELLED: u8 = 2;
+
+/// Signals that the webview cancelled an in-flight custom protocol request,
+/// for example because the page navigated away or a `fetch` was aborted.
+///
+/// Get it from [`RequestAsyncResponder::cancellation`](crate::RequestAsyncResponder::cancellation)
+/// and clone it to wherever the response is computed.
+///
+/// ## Platform-specific
+///
+/// - **Linux**: Fires when WebKit releases the request without it being answered.
+/// - **macOS / iOS**: Fires when WebKit stops the `WKURLSchemeTask`.
+/// - **Windows / Android**: Unsupported, never fires.
+#[derive(Clone, Default)]
+pub struct CancellationToken(Arc<Inner>);
+
+#[derive(Default)]
+struct Inner {
+  state: AtomicU8,
+  callbacks: Mutex<Vec<Box<dyn FnOnce() + Send>>>,
+}
+
+impl CancellationToken {
+  pub(crate) fn new() -> Self {
+    Self::default()
+  }
+
+  /// Whether the request was cancelled.
+  pub fn is_cancelled(&self) -> bool {
+    self.0.state.load(Ordering::Acquire) == CANCELLED
+  }
+
+  /// Registers a callback called when the request is cancelled, right away if it already was.
+  ///
+  /// The callback is called on the thread that detected the cancellation, which is usually the main thread,
+  /// so it should not block. It is never called once the request is answered.
+  pub fn on_cancel<F: FnOnce() + Send + 'static>(&self, callback: F) {
+    let mut callbacks = self.0.callbacks.lock().unwrap();
+    if self.is_cancelled() {
+      drop(callbacks);
+      callback();
+    } else {
+      callbacks.push(Box::new(callback));
+    }
+  }
+
+  /// Returns a future that completes when the request is cancelled.
+  ///
+  /// The future never completes if the request is answered first.
+  pub fn cancelled(&self) -> Cancelled {
+    Cancelled {
+      token: self.clone(),
+      waker: None,
+    }
+  }
+
+  /// Marks the request as cancelled and calls the registered callbacks,
+  /// unless it was already answered.
+  pub(crate) fn cancel(&self) {
+    if self
+      .0
+      .state
+      .compare_exchange(PENDING, CANCELLED, Ordering::AcqRel, Ordering::Acquire)
+      .is_ok()
+    {
+      let callbacks = std::mem::take(&mut *self.0.callbacks.lock().unwrap());
+      for callback in callbacks {
+        callback();
+      }
+    }
+  }
+
+  /// Marks the request as answered, returns `false` if it was cancelled before.
+  pub(crate) fn finish(&self) -> bool {
+    let finished = self
+      .0
+      .state
+      .compare_exchange(PENDING, FINISHED, Ordering::AcqRel, Ordering::Acquire)
+      .is_ok();
+    if finished {
+      // the callbacks can never be called anymore
+      self.0.callbacks.lock().unwrap().clear();
+    }
+    finished
+  }
+}
+
+impl fmt::Debug for CancellationToken {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("CancellationToken")
+      .field("cancelled", &self.is_cancelled())
+      .finish()
+  }
+}
+
+/// Future returned by [`CancellationToken::cancelled`].
+#[derive(Debug)]
+pub struct Cancelled {
+  token: CancellationToken,
+  waker: Option<Arc<Mutex<Option<Waker>>>>,
+}
+
+impl Future for Cancelled {
+  type Output = ();
+
+  fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+    if self.token.is_cancelled() {
+      return Poll::Ready(());
+    }
+
+    match &self.waker {
+      Some(waker) => *waker.lock().unwrap() = Some(cx.waker().clone()),
+      None => {
+        let waker = Arc::new(Mutex::new(Some(cx.waker().clone())));
+        let waker_ = waker.clone();
+        self.token.on_cancel(move || {
+          if let Some(waker) = waker_.lock().unwrap().take() {
+            waker.wake();
+          }
+        });
+        self.waker = Some(waker);
+      }
+    }
+
+    if self.token.is_cancelled() {
+      Poll::Ready(())
+    } else {
+      Poll::Pending
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::sync::atomic::AtomicUsize;
+
+  use super::*;
+
+  #[test]
+  fn cancel_calls_callbacks_once() {
+    let token = CancellationToken::new();
+    let calls = Arc::new(AtomicUsize::new(0));
+
+    let calls_ = calls.clone();
+    token.on_cancel(move || {
+      calls_.fetch_add(1, Ordering::SeqCst);
+    });
+    assert!(!token.is_cancelled());
+
+    token.cancel();
+    token.cancel();
+    assert!(token.is_cancelled());
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+    // registered after the fact
+    let calls_ = calls.clone();
+    token.on_cancel(move || {
+      calls_.fetch_add(1, Ordering::SeqCst);
+    });
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+    assert!(!token.finish());
+  }
+
+  #[test]
+  fn finished_requests_are_not_cancelled() {
+    let token = CancellationToken::new();
+    token.on_cancel(|| panic!("the request was answered"));
+    assert!(token.finish());
+    token.cancel();
+    assert!(!token.is_cancelled());
+  }
+
+  #[test]
+  fn cancelled_future_wakes_up() {
+    struct CountingWaker(AtomicUsize);
+
+    impl std::task::Wake for CountingWaker {
+      fn wake(self: Arc<Self>) {
+        self.0.fetch_add(1, Ordering::SeqCst);
+      }
+    }
+
+    let token = CancellationToken::new();
+    let mut cancelled = token.cancelled();
+    let counter = Arc::new(CountingWaker(AtomicUsize::new(0)));
+    let waker = Waker::from(counter.clone());
+    let mut cx = Context::from_waker(&waker);
+
+    assert!(Pin::new(&mut cancelled).poll(&mut cx).is_pending());
+    assert!(Pin::new(&mut cancelled).poll(&mut cx).is_pending());
+    token.cancel();
+    assert_eq!(counter.0.load(Ordering::SeqCst), 1);
+    assert!(Pin::new(&mut cancelled).poll(&mut cx).is_ready());
+  }
+}
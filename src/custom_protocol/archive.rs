@@ -0,0 +1,373 @@
+// Copyright 2020-2024 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+use std::{
+  borrow::Cow,
+  fs::File,
+  io::{self, Cursor, Read, Seek, SeekFrom},
+  path::{Path, PathBuf},
+  sync::{Arc, Mutex},
+};
+
+use http::{
+  header::{ALLOW, CONTENT_TYPE},
+  Method, Request, Response, StatusCode,
+};
+use zip::{CompressionMethod, ZipArchive};
+
+use super::{
+  buffer,
+  cache::{Cache, Validators},
+  directory::error_status,
+  mime, range, status_response,
+};
+use crate::ResponseBody;
+
+/// Serves the entries of a zip archive, read lazily from disk.
+///
+/// Only the central directory is read when opening the archive, entries are read
+/// when they are requested. Request paths are percent-decoded and resolved relative
+/// to the root of the archive, or to the directory set with [`Archive::with_root`].
+/// Any path that points outside of it through `..` segments is refused with `403 Forbidden`.
+///
+/// `Range` requests are supported, see [`range::apply`]. Entries stored without compression
+/// are streamed straight from the archive file, compressed entries are decompressed into memory.
+/// Conditional requests are supported when a [`Cache`] is set with [`Archive::with_cache`],
+/// using validators computed from the entry checksum and size.
+///
+/// # Examples
+///
+/// Serving two versions of a frontend from the same archive:
+///
+/// ```no_run
+/// use wry::{custom_protocol::Archive, WebViewBuilder};
+///
+/// let v1 = Archive::open("frontend.zip")?.with_root("v1");
+/// let v2 = v1.clone().with_root("v2");
+/// let builder = WebViewBuilder::new()
+///   .with_custom_protocol("v1".into(), move |_webview_id, request| v1.handle(&request))
+///   .with_custom_protocol("v2".into(), move |_webview_id, request| v2.handle(&request))
+///   .with_url("v2://localhost");
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct Archive {
+  path: PathBuf,
+  archive: Arc<Mutex<ZipArchive<File>>>,
+  root: String,
+  index: Option<String>,
+  cache: Option<Cache>,
+}
+
+impl Archive {
+  /// Opens the zip archive at `path`, using `index.html` as the index file.
+  pub fn open<P: Into<PathBuf>>(path: P) -> io::Result<Self> {
+    let path = path.into();
+    let archive = ZipArchive::new(File::open(&path)?).map_err(io::Error::from)?;
+    Ok(Self {
+      path,
+      archive: Arc::new(Mutex::new(archive)),
+      root: String::new(),
+      index: Some("index.html".into()),
+      cache: None,
+    })
+  }
+
+  /// Serves the entries inside the `root` directory of the archive instead of the whole archive.
+  pub fn with_root<S: AsRef<str>>(mut self, root: S) -> Self {
+    let root = root.as_ref().trim_matches('/');
+    self.root = if root.is_empty() {
+      String::new()
+    } else {
+      format!("{root}/")
+    };
+    self
+  }
+
+  /// Sets the entry served for requests targeting a directory, such as `/`.
+  pub fn with_index<S: Into<String>>(mut self, index: S) -> Self {
+    self.index = Some(index.into());
+    self
+  }
+
+  /// Disables the index entry, requests targeting a directory will answer `404 Not Found`.
+  pub fn without_index(mut self) -> Self {
+    self.index = None;
+    self
+  }
+
+  /// Enables conditional requests and caching headers, using validators computed from the entry checksum and size.
+  pub fn with_cache(mut self, cache: Cache) -> Self {
+    self.cache = Some(cache);
+    self
+  }
+
+  /// The path of the archive file.
+  pub fn path(&self) -> &Path {
+    &self.path
+  }
+
+  /// Resolves a request path to the name of an entry inside the served directory of the archive.
+  ///
+  /// Fails with [`io::ErrorKind::NotFound`] if the entry does not exist and with
+  /// [`io::ErrorKind::PermissionDenied`] if the path escapes the served directory.
+  pub fn resolve(&self, path: &str) -> io::Result<String> {
+    let decoded = percent_encoding::percent_decode_str(path)
+      .decode_utf8()
+      .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    if decoded.contains('\0') {
+      return Err(io::Error::new(
+        io::ErrorKind::InvalidInput,
+        "path contains a nul byte",
+      ));
+    }
+
+    let mut segments = Vec::new();
+    for segment in decoded.split(['/', '\\']) {
+      match segment {
+        "" | "." => {}
+        ".." => {
+          segments.pop().ok_or_else(|| {
+            io::Error::new(
+              io::ErrorKind::PermissionDenied,
+              "path escapes the root directory",
+            )
+          })?;
+        }
+        segment => segments.push(segment),
+      }
+    }
+    let relative = segments.join("/");
+
+    let archive = self.archive.lock().unwrap();
+    if !relative.is_empty() && !decoded.ends_with('/') {
+      let name = format!("{}{relative}", self.root);
+      if archive.index_for_name(&name).is_some() {
+        return Ok(name);
+      }
+    }
+
+    self
+      .index
+      .as_ref()
+      .map(|index| match relative.as_str() {
+        "" => format!("{}{index}", self.root),
+        relative => format!("{}{relative}/{index}", self.root),
+      })
+      .filter(|name| archive.index_for_name(name).is_some())
+      .ok_or_else(|| io::ErrorKind::NotFound.into())
+  }
+
+  /// Answers the request with the entry it targets, read into memory.
+  ///
+  /// See [`Self::serve`] for more information.
+  pub fn handle<T>(&self, request: &Request<T>) -> Response<Cow<'static, [u8]>> {
+    buffer(self.serve(request)).unwrap_or_else(|e| status_response(error_status(&e)))
+  }
+
+  /// Answers the request with the entry it targets, streamed from the archive when it is not compressed.
+  ///
+  /// Only `GET` and `HEAD` requests are accepted, other methods get `405 Method Not Allowed`.
+  pub fn serve<T>(&self, request: &Request<T>) -> Response<ResponseBody> {
+    let method = request.method();
+    if method != Method::GET && method != Method::HEAD {
+      let mut response = status_response(StatusCode::METHOD_NOT_ALLOWED);
+      response
+        .headers_mut()
+        .insert(ALLOW, "GET, HEAD".parse().unwrap());
+      return response.map(Into::into);
+    }
+
+    let result = self.resolve(request.uri().path()).and_then(|name| {
+      let mut archive = self.archive.lock().unwrap();
+      let mut entry = archive.by_name(&name).map_err(io::Error::from)?;
+
+      let mut response = Response::builder()
+        .header(CONTENT_TYPE, mime::from_path(&name))
+        .body(())
+        .unwrap();
+
+      if let Some(cache) = &self.cache {
+        let validators = Validators {
+          etag: Some(format!("\"{:x}-{:x}\"", entry.crc32(), entry.size())),
+          last_modified: None,
+        };
+        if let Some(not_modified) = cache.not_modified(request, &validators) {
+          return Ok(not_modified.map(Into::into));
+        }
+        cache.apply(&validators, response.headers_mut());
+      }
+
+      if entry.compression() == CompressionMethod::Stored {
+        let start = entry.data_start();
+        let len = entry.size();
+        drop(entry);
+        drop(archive);
+
+        let mut file = File::open(&self.path)?;
+        file.seek(SeekFrom::Start(start))?;
+        let entry = StoredEntry {
+          file,
+          start,
+          len,
+          position: 0,
+        };
+        range::apply(request, response.map(|()| entry))
+      } else {
+        let mut content = Vec::with_capacity(entry.size().try_into().unwrap_or_default());
+        entry.read_to_end(&mut content)?;
+        drop(entry);
+        drop(archive);
+
+        range::apply(request, response.map(|()| Cursor::new(content)))
+      }
+    });
+
+    result.unwrap_or_else(|e| status_response(error_status(&e)).map(Into::into))
+  }
+}
+
+/// An entry stored without compression, read straight from the archive file.
+struct StoredEntry {
+  file: File,
+  start: u64,
+  len: u64,
+  position: u64,
+}
+
+impl Read for StoredEntry {
+  fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+    let remaining = self.len - self.position;
+    let count = buf.len().min(remaining.try_into().unwrap_or(usize::MAX));
+    let count = self.file.read(&mut buf[..count])?;
+    self.position += count as u64;
+    Ok(count)
+  }
+}
+
+impl Seek for StoredEntry {
+  fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+    let position = match pos {
+      SeekFrom::Start(offset) => Some(offset),
+      SeekFrom::End(offset) => self.len.checked_add_signed(offset),
+      SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+    }
+    .ok_or_else(|| {
+      io::Error::new(
+        io::ErrorKind::InvalidInput,
+        "invalid seek to a negative or overflowing position",
+      )
+    })?;
+
+    self.file.seek(SeekFrom::Start(self.start + position))?;
+    self.position = position;
+    Ok(position)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::{fs, io::Write};
+
+  use http::header::{CONTENT_RANGE, RANGE};
+  use zip::{write::SimpleFileOptions, ZipWriter};
+
+  use super::{super::fixture, *};
+
+  fn archive(name: &str) -> PathBuf {
+    let path = fixture(&format!("archive-{name}"), &[]).join("frontend.zip");
+    let mut writer = ZipWriter::new(File::create(&path).unwrap());
+    let stored = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+    let deflated = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    for (name, options, content) in [
+      ("index.html", stored, "<h1>index</h1>"),
+      ("app.js", deflated, "console.log('compressed')"),
+      ("v2/index.html", stored, "<h1>v2</h1>"),
+      ("v2/sub/my file.css", deflated, "body {}"),
+    ] {
+      writer.start_file(name, options).unwrap();
+      writer.write_all(content.as_bytes()).unwrap();
+    }
+    writer.finish().unwrap();
+
+    path
+  }
+
+  fn get(archive: &Archive, uri: &str) -> Response<Cow<'static, [u8]>> {
+    archive.handle(&Request::get(uri).body(()).unwrap())
+  }
+
+  #[test]
+  fn serves_entries() {
+    let path = archive("serve");
+    let archive = Archive::open(&path).unwrap();
+
+    let response = get(&archive, "app://localhost/");
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[CONTENT_TYPE], "text/html");
+    assert_eq!(&response.body()[..], b"<h1>index</h1>");
+
+    let response = get(&archive, "app://localhost/app.js");
+    assert_eq!(response.headers()[CONTENT_TYPE], "text/javascript");
+    assert_eq!(&response.body()[..], b"console.log('compressed')");
+
+    let response = get(&archive, "app://localhost/v2");
+    assert_eq!(&response.body()[..], b"<h1>v2</h1>");
+
+    let response = get(&archive, "app://localhost/missing.js");
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    let _ = fs::remove_dir_all(path.parent().unwrap());
+  }
+
+  #[test]
+  fn serves_ranges() {
+    let path = archive("range");
+    let archive = Archive::open(&path).unwrap();
+
+    for (uri, expected) in [
+      ("app://localhost/index.html", &b"h1>i"[..]),
+      ("app://localhost/app.js", &b"onso"[..]),
+    ] {
+      let request = Request::get(uri)
+        .header(RANGE, "bytes=1-4")
+        .body(())
+        .unwrap();
+      let response = archive.handle(&request);
+      assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT, "{uri}");
+      assert!(response.headers()[CONTENT_RANGE]
+        .to_str()
+        .unwrap()
+        .starts_with("bytes 1-4/"));
+      assert_eq!(&response.body()[..], expected, "{uri}");
+    }
+
+    let _ = fs::remove_dir_all(path.parent().unwrap());
+  }
+
+  #[test]
+  fn scopes_paths_to_root() {
+    let path = archive("scope");
+    let archive = Archive::open(&path).unwrap().with_root("v2");
+
+    let response = get(&archive, "app://localhost/");
+    assert_eq!(&response.body()[..], b"<h1>v2</h1>");
+
+    let response = get(&archive, "app://localhost/sub/my%20file.css");
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[CONTENT_TYPE], "text/css");
+
+    for uri in [
+      "app://localhost/../index.html",
+      "app://localhost/sub/../../app.js",
+      "app://localhost/%2e%2e/index.html",
+      "app://localhost/..%2fapp.js",
+    ] {
+      let response = get(&archive, uri);
+      assert_eq!(response.status(), StatusCode::FORBIDDEN, "{uri}");
+    }
+
+    let _ = fs::remove_dir_all(path.parent().unwrap());
+  }
+}
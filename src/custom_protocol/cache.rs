@@ -0,0 +1,394 @@
+// Copyright 2020-2024 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+//! Conditional requests and caching headers for custom protocol responses.
+//!
+//! [`Cache`] sets `ETag`, `Last-Modified` and `Cache-Control` on responses and answers
+//! requests carrying matching `If-None-Match` or `If-Modified-Since` headers with `304 Not Modified`,
+//! so the webview can reuse what it already has instead of receiving every asset again.
+//!
+//! ```no_run
+//! use wry::{custom_protocol::{cache::Cache, Directory}, WebViewBuilder};
+//!
+//! // validators are computed from the file metadata
+//! let assets = Directory::new("dist").with_cache(Cache::new());
+//!
+//! // validators are computed by hashing the response body
+//! let generated = Cache::new().with_cache_control("max-age=60").wrap(|_webview_id, _request| {
+//!   wry::http::Response::new(b"generated".to_vec().into())
+//! });
+//!
+//! let builder = WebViewBuilder::new()
+//!   .with_custom_protocol("app".into(), move |_webview_id, request| assets.handle(&request))
+//!   .with_custom_protocol("generated".into(), generated);
+//! ```
+
+use std::{
+  borrow::Cow,
+  collections::hash_map::DefaultHasher,
+  fs::Metadata,
+  hash::{Hash, Hasher},
+  time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use http::{
+  header::{CACHE_CONTROL, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED},
+  HeaderMap, HeaderValue, Method, Request, Response, StatusCode,
+};
+
+use crate::WebViewId;
+
+/// The validators identifying a version of a resource.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Validators {
+  /// The entity tag, including its quotes, for example `"abc"`.
+  pub etag: Option<String>,
+  /// The last modification time.
+  pub last_modified: Option<SystemTime>,
+}
+
+impl Validators {
+  /// Computes validators from the size and modification time of a file.
+  pub fn from_metadata(metadata: &Metadata) -> Self {
+    let modified = metadata.modified().ok();
+    let nanos = modified
+      .and_then(|m| m.duration_since(UNIX_EPOCH).ok())
+      .map(|d| d.as_nanos())
+      .unwrap_or_default();
+    Self {
+      etag: Some(format!("\"{:x}-{:x}\"", nanos, metadata.len())),
+      last_modified: modified,
+    }
+  }
+
+  /// Computes an entity tag by hashing the given content.
+  pub fn from_bytes(content: &[u8]) -> Self {
+    let mut hasher = DefaultHasher::new();
+    content.hash(&mut hasher);
+    Self {
+      etag: Some(format!("\"{:x}-{:x}\"", hasher.finish(), content.len())),
+      last_modified: None,
+    }
+  }
+
+  /// Whether a request with the given headers already has this version of the resource.
+  ///
+  /// `If-None-Match` takes precedence over `If-Modified-Since`, as required by RFC 9110.
+  pub fn matches(&self, headers: &HeaderMap) -> bool {
+    if let Some(if_none_match) = headers.get(IF_NONE_MATCH) {
+      let Some(etag) = &self.etag else {
+        return false;
+      };
+      let Ok(if_none_match) = if_none_match.to_str() else {
+        return false;
+      };
+      return if_none_match.trim() == "*"
+        || if_none_match
+          .split(',')
+          .any(|tag| weak_eq(tag.trim(), etag));
+    }
+
+    if let (Some(last_modified), Some(since)) = (
+      self.last_modified,
+      headers
+        .get(IF_MODIFIED_SINCE)
+        .and_then(|s| s.to_str().ok())
+        .and_then(parse_http_date),
+    ) {
+      // HTTP dates only have a precision of one second
+      return truncate_to_secs(last_modified) <= since;
+    }
+
+    false
+  }
+
+  fn apply(&self, headers: &mut HeaderMap) {
+    if let Some(etag) = self
+      .etag
+      .as_ref()
+      .and_then(|e| HeaderValue::from_str(e).ok())
+    {
+      headers.insert(ETAG, etag);
+    }
+    if let Some(last_modified) = self.last_modified {
+      headers.insert(
+        LAST_MODIFIED,
+        HeaderValue::from_str(&format_http_date(last_modified)).unwrap(),
+      );
+    }
+  }
+}
+
+/// An opt-in caching layer for custom protocol responses.
+///
+/// By default responses get `Cache-Control: no-cache`, which lets the webview keep them
+/// but makes it revalidate them with a conditional request each time they are used.
+#[derive(Debug, Clone)]
+pub struct Cache {
+  cache_control: Option<HeaderValue>,
+}
+
+impl Default for Cache {
+  fn default() -> Self {
+    Self {
+      cache_control: Some(HeaderValue::from_static("no-cache")),
+    }
+  }
+}
+
+impl Cache {
+  /// Creates a cache layer using `Cache-Control: no-cache`.
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Sets the `Cache-Control` header value, such as `max-age=3600` or `no-store`.
+  ///
+  /// # Panics
+  ///
+  /// Panics if the value is not a valid header value.
+  pub fn with_cache_control<S: AsRef<str>>(mut self, cache_control: S) -> Self {
+    self.cache_control =
+      Some(HeaderValue::from_str(cache_control.as_ref()).expect("invalid Cache-Control value"));
+    self
+  }
+
+  /// Leaves the `Cache-Control` header to the handler.
+  pub fn without_cache_control(mut self) -> Self {
+    self.cache_control = None;
+    self
+  }
+
+  /// Returns a `304 Not Modified` response if the request already has the version
+  /// of the resource described by `validators`.
+  ///
+  /// Only `GET` and `HEAD` requests are considered.
+  pub fn not_modified<T>(
+    &self,
+    request: &Request<T>,
+    validators: &Validators,
+  ) -> Option<Response<Cow<'static, [u8]>>> {
+    self.not_modified_for(request.method(), request.headers(), validators)
+  }
+
+  fn not_modified_for(
+    &self,
+    method: &Method,
+    headers: &HeaderMap,
+    validators: &Validators,
+  ) -> Option<Response<Cow<'static, [u8]>>> {
+    if (method != Method::GET && method != Method::HEAD) || !validators.matches(headers) {
+      return None;
+    }
+
+    let mut response = Response::new(Cow::Borrowed(&[][..]));
+    *response.status_mut() = StatusCode::NOT_MODIFIED;
+    self.apply(validators, response.headers_mut());
+    Some(response)
+  }
+
+  /// Sets the validators and `Cache-Control` headers on a successful response.
+  pub fn apply(&self, validators: &Validators, headers: &mut HeaderMap) {
+    validators.apply(headers);
+    if let Some(cache_control) = &self.cache_control {
+      headers.insert(CACHE_CONTROL, cache_control.clone());
+    }
+  }
+
+  /// Wraps a custom protocol handler, computing an `ETag` by hashing the body of its successful responses.
+  ///
+  /// The handler still runs for every request, but the body is only sent when it changed.
+  /// Responses that already have an `ETag` keep it.
+  pub fn wrap<F>(
+    self,
+    handler: F,
+  ) -> impl Fn(WebViewId, Request<Vec<u8>>) -> Response<Cow<'static, [u8]>> + 'static
+  where
+    F: Fn(WebViewId, Request<Vec<u8>>) -> Response<Cow<'static, [u8]>> + 'static,
+  {
+    move |webview_id, request| {
+      let method = request.method().clone();
+      let headers = request.headers().clone();
+      let mut response = handler(webview_id, request);
+      if response.status() != StatusCode::OK {
+        return response;
+      }
+
+      let validators = match response.headers().get(ETAG).and_then(|e| e.to_str().ok()) {
+        Some(etag) => Validators {
+          etag: Some(etag.to_string()),
+          last_modified: None,
+        },
+        None => Validators::from_bytes(response.body()),
+      };
+
+      if let Some(not_modified) = self.not_modified_for(&method, &headers, &validators) {
+        return not_modified;
+      }
+
+      self.apply(&validators, response.headers_mut());
+      response
+    }
+  }
+}
+
+/// Weak comparison of two entity tags, ignoring the `W/` prefix.
+fn weak_eq(a: &str, b: &str) -> bool {
+  let opaque = |tag: &str| tag.strip_prefix("W/").unwrap_or(tag).to_string();
+  opaque(a) == opaque(b)
+}
+
+fn truncate_to_secs(time: SystemTime) -> SystemTime {
+  let secs = time
+    .duration_since(UNIX_EPOCH)
+    .map(|d| d.as_secs())
+    .unwrap_or_default();
+  UNIX_EPOCH + Duration::from_secs(secs)
+}
+
+const DAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+const MONTHS: [&str; 12] = [
+  "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// Formats a time as an IMF-fixdate, such as `Sun, 06 Nov 1994 08:49:37 GMT`.
+pub(crate) fn format_http_date(time: SystemTime) -> String {
+  let secs = time
+    .duration_since(UNIX_EPOCH)
+    .map(|d| d.as_secs())
+    .unwrap_or_default();
+  let days = secs / 86400;
+  let (year, month, day) = civil_from_days(days as i64);
+  let rem = secs % 86400;
+  format!(
+    "{}, {:02} {} {} {:02}:{:02}:{:02} GMT",
+    DAYS[(days % 7) as usize],
+    day,
+    MONTHS[month as usize - 1],
+    year,
+    rem / 3600,
+    rem % 3600 / 60,
+    rem % 60
+  )
+}
+
+/// Parses an IMF-fixdate, such as `Sun, 06 Nov 1994 08:49:37 GMT`.
+pub(crate) fn parse_http_date(date: &str) -> Option<SystemTime> {
+  let (_, date) = date.trim().split_once(", ")?;
+  let mut parts = date.split(' ');
+  let day: u32 = parts.next()?.parse().ok()?;
+  let month = parts.next()?;
+  let month = MONTHS.iter().position(|m| *m == month)? as u32 + 1;
+  let year: i64 = parts.next()?.parse().ok()?;
+  let mut time = parts.next()?.split(':').map(|t| t.parse::<u64>().ok());
+  let (hours, minutes, seconds) = (time.next()??, time.next()??, time.next()??);
+  if parts.next()? != "GMT" || day == 0 || day > 31 || hours > 23 || minutes > 59 || seconds > 60 {
+    return None;
+  }
+
+  let days = u64::try_from(days_from_civil(year, month, day)).ok()?;
+  Some(UNIX_EPOCH + Duration::from_secs(days * 86400 + hours * 3600 + minutes * 60 + seconds))
+}
+
+// Conversions between days since the unix epoch and civil dates,
+// from http://howardhinnant.github.io/date_algorithms.html
+pub(crate) fn civil_from_days(days: i64) -> (i64, u32, u32) {
+  let z = days + 719468;
+  let era = z.div_euclid(146097);
+  let doe = z.rem_euclid(146097);
+  let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+  let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+  let mp = (5 * doy + 2) / 153;
+  let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+  let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+  let year = yoe + era * 400 + i64::from(month <= 2);
+  (year, month, day)
+}
+
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+  let year = year - i64::from(month <= 2);
+  let era = year.div_euclid(400);
+  let yoe = year.rem_euclid(400);
+  let month = i64::from(month);
+  let doy = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + i64::from(day) - 1;
+  let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+  era * 146097 + doe - 719468
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn http_dates() {
+    let time = UNIX_EPOCH + Duration::from_secs(784111777);
+    assert_eq!(format_http_date(time), "Sun, 06 Nov 1994 08:49:37 GMT");
+    assert_eq!(parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT"), Some(time));
+    assert_eq!(
+      format_http_date(UNIX_EPOCH + Duration::from_secs(951782400)),
+      "Tue, 29 Feb 2000 00:00:00 GMT"
+    );
+    assert_eq!(parse_http_date("Sunday, 06-Nov-94 08:49:37 GMT"), None);
+  }
+
+  fn request(name: http::HeaderName, value: &str) -> Request<()> {
+    Request::get("app://localhost/")
+      .header(name, value)
+      .body(())
+      .unwrap()
+  }
+
+  #[test]
+  fn conditional_requests() {
+    let cache = Cache::new();
+    let modified = UNIX_EPOCH + Duration::from_millis(784_111_777_500);
+    let validators = Validators {
+      etag: Some("\"v1\"".into()),
+      last_modified: Some(modified),
+    };
+
+    let response = cache
+      .not_modified(&request(IF_NONE_MATCH, "\"v0\", W/\"v1\""), &validators)
+      .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+    assert_eq!(response.headers()[ETAG], "\"v1\"");
+    assert_eq!(response.headers()[CACHE_CONTROL], "no-cache");
+
+    assert!(cache
+      .not_modified(&request(IF_NONE_MATCH, "\"v2\""), &validators)
+      .is_none());
+    assert!(cache
+      .not_modified(
+        &request(IF_MODIFIED_SINCE, "Sun, 06 Nov 1994 08:49:37 GMT"),
+        &validators
+      )
+      .is_some());
+    assert!(cache
+      .not_modified(
+        &request(IF_MODIFIED_SINCE, "Sun, 06 Nov 1994 08:49:36 GMT"),
+        &validators
+      )
+      .is_none());
+  }
+
+  #[test]
+  fn wrapped_handler() {
+    let handler = Cache::new()
+      .with_cache_control("max-age=60")
+      .wrap(|_, _| Response::new(Cow::Borrowed(&b"content"[..])));
+
+    let response = handler("main", Request::new(Vec::new()));
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[CACHE_CONTROL], "max-age=60");
+    let etag = response.headers()[ETAG].to_str().unwrap().to_string();
+
+    let request = Request::get("app://localhost/")
+      .header(IF_NONE_MATCH, &etag)
+      .body(Vec::new())
+      .unwrap();
+    let response = handler("main", request);
+    assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+    assert!(response.body().is_empty());
+  }
+}
@@ -0,0 +1,404 @@
+// Copyright 2020-2024 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+//! Cross-origin resource sharing (CORS) for custom protocols.
+//!
+//! A [`CorsPolicy`] registered with [`WebViewBuilder::with_custom_protocol_cors`] answers `OPTIONS`
+//! preflight requests and adds the `Access-Control-Allow-*` headers to the responses of the handler,
+//! so pages of other origins can `fetch` the custom protocol.
+//!
+//! ```no_run
+//! use wry::{custom_protocol::{cors::CorsPolicy, Directory}, http::Method, WebViewBuilder};
+//!
+//! let api = Directory::new("api");
+//! let builder = WebViewBuilder::new()
+//!   .with_custom_protocol("api".into(), move |_webview_id, request| api.handle(&request))
+//!   .with_custom_protocol_cors(
+//!     "api".into(),
+//!     CorsPolicy::new()
+//!       // pages loaded from `app://`, whatever form the origin takes on the current platform
+//!       .allow_custom_protocol_origin("app")
+//!       .allow_origin("https://tauri.app")
+//!       .allow_methods([Method::GET, Method::POST])
+//!       .allow_any_header(),
+//!   );
+//! ```
+//!
+//! [`WebViewBuilder::with_custom_protocol_cors`]: crate::WebViewBuilder::with_custom_protocol_cors
+
+use std::time::Duration;
+
+use http::{
+  header::{
+    ACCESS_CONTROL_ALLOW_CREDENTIALS, ACCESS_CONTROL_ALLOW_HEADERS, ACCESS_CONTROL_ALLOW_METHODS,
+    ACCESS_CONTROL_ALLOW_ORIGIN, ACCESS_CONTROL_EXPOSE_HEADERS, ACCESS_CONTROL_MAX_AGE,
+    ACCESS_CONTROL_REQUEST_HEADERS, ACCESS_CONTROL_REQUEST_METHOD, ORIGIN, VARY,
+  },
+  HeaderMap, HeaderName, HeaderValue, Method, Request, Response, StatusCode,
+};
+
+use super::status_response;
+use crate::{RequestAsyncResponder, ResponseBody, WebViewId};
+
+/// An origin allowed by a [`CorsPolicy`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum AllowedOrigin {
+  /// An exact origin, such as `https://tauri.app`.
+  Exact(String),
+  /// Pages loaded from a custom protocol, in any of its platform-specific forms.
+  CustomProtocol(String),
+}
+
+impl AllowedOrigin {
+  fn matches(&self, origin: &str) -> bool {
+    match self {
+      Self::Exact(allowed) => allowed.eq_ignore_ascii_case(origin),
+      Self::CustomProtocol(scheme) => {
+        // `<scheme>://<host>` on macOS, iOS and Linux, these never reach the network
+        let custom = origin
+          .strip_prefix(scheme.as_str())
+          .and_then(|host| host.strip_prefix("://"))
+          .is_some_and(|host| !host.is_empty());
+        // `http(s)://<scheme>.localhost` on Windows and Android, other hosts could be remote pages
+        let workaround = origin
+          .strip_prefix("http://")
+          .or_else(|| origin.strip_prefix("https://"))
+          .and_then(|host| host.strip_prefix(scheme.as_str()))
+          .is_some_and(|host| host.eq_ignore_ascii_case(".localhost"));
+        custom || workaround
+      }
+    }
+  }
+}
+
+/// A declarative CORS policy for a custom protocol.
+///
+/// Requests without an `Origin` header are passed to the handler untouched. Others get:
+///
+/// - a `204 No Content` answer to preflight requests from allowed origins for allowed methods,
+///   or `403 Forbidden` otherwise, without calling the handler,
+/// - `Access-Control-Allow-Origin` and the other configured headers on the handler responses when the origin is allowed.
+///
+/// By default no origin is allowed, and `GET`, `HEAD` and `POST` are the allowed methods.
+#[derive(Debug, Clone)]
+pub struct CorsPolicy {
+  any_origin: bool,
+  origins: Vec<AllowedOrigin>,
+  methods: Vec<Method>,
+  any_header: bool,
+  headers: Vec<HeaderName>,
+  expose_headers: Vec<HeaderName>,
+  credentials: bool,
+  max_age: Option<Duration>,
+}
+
+impl Default for CorsPolicy {
+  fn default() -> Self {
+    Self {
+      any_origin: false,
+      origins: Vec::new(),
+      methods: vec![Method::GET, Method::HEAD, Method::POST],
+      any_header: false,
+      headers: Vec::new(),
+      expose_headers: Vec::new(),
+      credentials: false,
+      max_age: None,
+    }
+  }
+}
+
+impl CorsPolicy {
+  /// Creates a policy allowing no origin.
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Allows requests from any origin.
+  pub fn allow_any_origin(mut self) -> Self {
+    self.any_origin = true;
+    self
+  }
+
+  /// Allows requests from `origin`, such as `https://tauri.app`.
+  pub fn allow_origin<S: Into<String>>(mut self, origin: S) -> Self {
+    self.origins.push(AllowedOrigin::Exact(
+      origin.into().trim_end_matches('/').to_string(),
+    ));
+    self
+  }
+
+  /// Allows requests from pages loaded from the `scheme` custom protocol.
+  ///
+  /// Their origin is matched in the forms described in [`WebViewBuilder::with_custom_protocol`](crate::WebViewBuilder::with_custom_protocol):
+  /// `<scheme>://<host>` on macOS, iOS and Linux, and `http(s)://<scheme>.localhost` on Windows and Android,
+  /// as returned by [`WebView::custom_protocol_origin`](crate::WebView::custom_protocol_origin).
+  /// Other hosts are not matched on Windows and Android since they could be remote pages.
+  pub fn allow_custom_protocol_origin<S: Into<String>>(mut self, scheme: S) -> Self {
+    self
+      .origins
+      .push(AllowedOrigin::CustomProtocol(scheme.into()));
+    self
+  }
+
+  /// Sets the allowed methods, replacing the defaults.
+  pub fn allow_methods<I: IntoIterator<Item = Method>>(mut self, methods: I) -> Self {
+    self.methods = methods.into_iter().collect();
+    self
+  }
+
+  /// Sets the request headers pages are allowed to send.
+  pub fn allow_headers<I: IntoIterator<Item = HeaderName>>(mut self, headers: I) -> Self {
+    self.headers = headers.into_iter().collect();
+    self
+  }
+
+  /// Allows pages to send any request header.
+  pub fn allow_any_header(mut self) -> Self {
+    self.any_header = true;
+    self
+  }
+
+  /// Sets the response headers pages are allowed to read, besides the CORS-safelisted ones.
+  pub fn expose_headers<I: IntoIterator<Item = HeaderName>>(mut self, headers: I) -> Self {
+    self.expose_headers = headers.into_iter().collect();
+    self
+  }
+
+  /// Allows requests with credentials, such as cookies.
+  ///
+  /// The origin is then always echoed back, as `Access-Control-Allow-Origin: *` does not allow credentials.
+  pub fn allow_credentials(mut self, enabled: bool) -> Self {
+    self.credentials = enabled;
+    self
+  }
+
+  /// Sets how long the webview can cache preflight answers.
+  pub fn with_max_age(mut self, max_age: Duration) -> Self {
+    self.max_age = Some(max_age);
+    self
+  }
+
+  /// Whether requests from `origin` are allowed.
+  pub fn allows_origin(&self, origin: &str) -> bool {
+    self.any_origin || self.origins.iter().any(|allowed| allowed.matches(origin))
+  }
+
+  /// Returns the answer to a preflight request, or `None` if `request` is not one.
+  pub fn preflight<T>(&self, request: &Request<T>) -> Option<Response<Vec<u8>>> {
+    let headers = request.headers();
+    if request.method() != Method::OPTIONS {
+      return None;
+    }
+    let origin = headers.get(ORIGIN)?.to_str().ok()?;
+    let method = headers.get(ACCESS_CONTROL_REQUEST_METHOD)?;
+
+    let allowed = self.allows_origin(origin)
+      && Method::from_bytes(method.as_bytes()).is_ok_and(|method| self.methods.contains(&method));
+    if !allowed {
+      return Some(status_response(StatusCode::FORBIDDEN).map(Into::into));
+    }
+
+    let mut response = Response::new(Vec::new());
+    *response.status_mut() = StatusCode::NO_CONTENT;
+    let response_headers = response.headers_mut();
+    self.apply_origin(origin, response_headers);
+    response_headers.insert(ACCESS_CONTROL_ALLOW_METHODS, join(&self.methods));
+    if self.any_header {
+      if let Some(requested) = headers.get(ACCESS_CONTROL_REQUEST_HEADERS) {
+        response_headers.insert(ACCESS_CONTROL_ALLOW_HEADERS, requested.clone());
+      }
+    } else if !self.headers.is_empty() {
+      response_headers.insert(ACCESS_CONTROL_ALLOW_HEADERS, join(&self.headers));
+    }
+    if let Some(max_age) = self.max_age {
+      response_headers.insert(ACCESS_CONTROL_MAX_AGE, max_age.as_secs().into());
+    }
+    for vary in [
+      ACCESS_CONTROL_REQUEST_METHOD,
+      ACCESS_CONTROL_REQUEST_HEADERS,
+    ] {
+      response_headers.append(VARY, HeaderValue::from_name(vary));
+    }
+    Some(response)
+  }
+
+  /// Adds the CORS headers to the response of a request with the given headers.
+  ///
+  /// Nothing is added if the request has no `Origin` header or if the origin is not allowed.
+  pub fn apply(&self, request_headers: &HeaderMap, response_headers: &mut HeaderMap) {
+    let Some(origin) = request_headers.get(ORIGIN).and_then(|o| o.to_str().ok()) else {
+      return;
+    };
+    if self.allows_origin(origin) {
+      self.apply_origin(origin, response_headers);
+      if !self.expose_headers.is_empty() {
+        response_headers.insert(ACCESS_CONTROL_EXPOSE_HEADERS, join(&self.expose_headers));
+      }
+    }
+  }
+
+  fn apply_origin(&self, origin: &str, headers: &mut HeaderMap) {
+    let allow_origin = if self.any_origin && !self.credentials {
+      HeaderValue::from_static("*")
+    } else {
+      match HeaderValue::from_str(origin) {
+        Ok(origin) => origin,
+        Err(_) => return,
+      }
+    };
+    headers.insert(ACCESS_CONTROL_ALLOW_ORIGIN, allow_origin);
+    if self.credentials {
+      headers.insert(
+        ACCESS_CONTROL_ALLOW_CREDENTIALS,
+        HeaderValue::from_static("true"),
+      );
+    }
+    headers.append(VARY, HeaderValue::from_name(ORIGIN));
+  }
+
+  /// Wraps a custom protocol handler, answering preflight requests and adding the CORS headers to its responses.
+  pub fn wrap<T, F>(
+    self,
+    handler: F,
+  ) -> impl Fn(WebViewId, Request<T>, RequestAsyncResponder) + 'static
+  where
+    F: Fn(WebViewId, Request<T>, RequestAsyncResponder) + 'static,
+  {
+    move |webview_id, request, responder| {
+      if let Some(response) = self.preflight(&request) {
+        responder.respond(response);
+        return;
+      }
+      if !request.headers().contains_key(ORIGIN) {
+        handler(webview_id, request, responder);
+        return;
+      }
+
+      let policy = self.clone();
+      let request_headers = request.headers().clone();
+      let RequestAsyncResponder {
+        responder,
+        cancellation,
+      } = responder;
+      let responder = RequestAsyncResponder {
+        responder: Box::new(move |mut response: Response<ResponseBody>| {
+          policy.apply(&request_headers, response.headers_mut());
+          responder(response)
+        }),
+        cancellation,
+      };
+      handler(webview_id, request, responder);
+    }
+  }
+}
+
+fn join<T: AsRef<str>>(items: &[T]) -> HeaderValue {
+  let joined = items
+    .iter()
+    .map(AsRef::as_ref)
+    .collect::<Vec<_>>()
+    .join(", ");
+  HeaderValue::from_str(&joined).expect("methods and header names are valid header values")
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn preflight(origin: &str, method: &str) -> Request<Vec<u8>> {
+    Request::options("api://localhost/items")
+      .header(ORIGIN, origin)
+      .header(ACCESS_CONTROL_REQUEST_METHOD, method)
+      .header(ACCESS_CONTROL_REQUEST_HEADERS, "content-type, x-token")
+      .body(Vec::new())
+      .unwrap()
+  }
+
+  #[test]
+  fn matches_custom_protocol_origins() {
+    let policy = CorsPolicy::new()
+      .allow_custom_protocol_origin("app")
+      .allow_origin("https://tauri.app/");
+
+    assert!(policy.allows_origin("app://localhost"));
+    assert!(policy.allows_origin("http://app.localhost"));
+    assert!(policy.allows_origin("https://app.localhost"));
+    assert!(policy.allows_origin("https://tauri.app"));
+    assert!(!policy.allows_origin("https://app.evil.com"));
+    assert!(!policy.allows_origin("http://apps.localhost"));
+    assert!(!policy.allows_origin("application://localhost"));
+    assert!(!policy.allows_origin("https://evil.com"));
+  }
+
+  #[test]
+  fn answers_preflights() {
+    let policy = CorsPolicy::new()
+      .allow_origin("https://tauri.app")
+      .allow_methods([Method::GET, Method::PUT])
+      .allow_any_header()
+      .allow_credentials(true)
+      .with_max_age(Duration::from_secs(600));
+
+    let response = policy
+      .preflight(&preflight("https://tauri.app", "PUT"))
+      .unwrap();
+    let headers = response.headers();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    assert_eq!(headers[ACCESS_CONTROL_ALLOW_ORIGIN], "https://tauri.app");
+    assert_eq!(headers[ACCESS_CONTROL_ALLOW_METHODS], "GET, PUT");
+    assert_eq!(
+      headers[ACCESS_CONTROL_ALLOW_HEADERS],
+      "content-type, x-token"
+    );
+    assert_eq!(headers[ACCESS_CONTROL_ALLOW_CREDENTIALS], "true");
+    assert_eq!(headers[ACCESS_CONTROL_MAX_AGE], "600");
+
+    let forbidden = [
+      preflight("https://tauri.app", "DELETE"),
+      preflight("https://evil.com", "GET"),
+    ];
+    for request in forbidden {
+      let response = policy.preflight(&request).unwrap();
+      assert_eq!(response.status(), StatusCode::FORBIDDEN);
+      assert!(!response.headers().contains_key(ACCESS_CONTROL_ALLOW_ORIGIN));
+    }
+
+    // regular OPTIONS requests go to the handler
+    let request = Request::options("api://localhost/")
+      .header(ORIGIN, "https://tauri.app")
+      .body(())
+      .unwrap();
+    assert!(policy.preflight(&request).is_none());
+  }
+
+  #[test]
+  fn decorates_responses() {
+    let mut request_headers = HeaderMap::new();
+    request_headers.insert(ORIGIN, "http://app.localhost".parse().unwrap());
+
+    let policy = CorsPolicy::new()
+      .allow_any_origin()
+      .expose_headers([HeaderName::from_static("x-total")]);
+    let mut headers = HeaderMap::new();
+    headers.insert(VARY, "Accept-Encoding".parse().unwrap());
+    policy.apply(&request_headers, &mut headers);
+    assert_eq!(headers[ACCESS_CONTROL_ALLOW_ORIGIN], "*");
+    assert_eq!(headers[ACCESS_CONTROL_EXPOSE_HEADERS], "x-total");
+    assert_eq!(
+      headers.get_all(VARY).iter().collect::<Vec<_>>(),
+      ["Accept-Encoding", "origin"]
+    );
+
+    // credentials require the origin to be echoed
+    let mut headers = HeaderMap::new();
+    policy
+      .allow_credentials(true)
+      .apply(&request_headers, &mut headers);
+    assert_eq!(headers[ACCESS_CONTROL_ALLOW_ORIGIN], "http://app.localhost");
+
+    let mut headers = HeaderMap::new();
+    CorsPolicy::new().apply(&request_headers, &mut headers);
+    assert!(headers.is_empty());
+  }
+}
@@ -0,0 +1,265 @@
+// Copyright 2020-2024 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+use std::{
+  borrow::Cow,
+  fs, io,
+  path::{Path, PathBuf},
+};
+
+use http::{
+  header::{ALLOW, CONTENT_TYPE},
+  Method, Request, Response, StatusCode,
+};
+
+use super::{
+  buffer,
+  cache::{Cache, Validators},
+  mime, range, status_response,
+};
+use crate::ResponseBody;
+
+/// Serves files from a directory on disk.
+///
+/// Request paths are percent-decoded and resolved relative to the root directory.
+/// Any path that points outside of the root after canonicalization, for example
+/// through `..` segments or symbolic links, is refused with `403 Forbidden`.
+///
+/// `Range` requests are supported, see [`range::apply`]. Conditional requests are supported
+/// when a [`Cache`] is set with [`Directory::with_cache`].
+///
+/// # Examples
+///
+/// ```no_run
+/// use wry::{custom_protocol::Directory, WebViewBuilder};
+///
+/// let assets = Directory::new("dist").with_index("app.html");
+/// let builder = WebViewBuilder::new()
+///   .with_custom_protocol("app".into(), move |_webview_id, request| assets.handle(&request))
+///   .with_url("app://localhost");
+/// ```
+///
+/// Use [`Directory::serve`] with an asynchronous handler to stream large files instead of reading them into memory:
+///
+/// ```no_run
+/// use wry::{custom_protocol::Directory, WebViewBuilder};
+///
+/// let assets = Directory::new("dist");
+/// let builder = WebViewBuilder::new()
+///   .with_asynchronous_custom_protocol("app".into(), move |_webview_id, request, responder| {
+///     responder.respond_with_body(assets.serve(&request))
+///   });
+/// ```
+#[derive(Debug, Clone)]
+pub struct Directory {
+  root: PathBuf,
+  index: Option<String>,
+  cache: Option<Cache>,
+}
+
+impl Directory {
+  /// Creates a handler serving files from `root`, using `index.html` as the index file.
+  pub fn new<P: Into<PathBuf>>(root: P) -> Self {
+    Self {
+      root: root.into(),
+      index: Some("index.html".into()),
+      cache: None,
+    }
+  }
+
+  /// Sets the file served for requests targeting a directory, such as `/`.
+  pub fn with_index<S: Into<String>>(mut self, index: S) -> Self {
+    self.index = Some(index.into());
+    self
+  }
+
+  /// Disables the index file, requests targeting a directory will answer `404 Not Found`.
+  pub fn without_index(mut self) -> Self {
+    self.index = None;
+    self
+  }
+
+  /// Enables conditional requests and caching headers, using validators computed from the file metadata.
+  pub fn with_cache(mut self, cache: Cache) -> Self {
+    self.cache = Some(cache);
+    self
+  }
+
+  /// The root directory files are served from.
+  pub fn root(&self) -> &Path {
+    &self.root
+  }
+
+  /// Resolves a request path to a canonical file path inside the root directory.
+  ///
+  /// Fails with [`io::ErrorKind::NotFound`] if the file does not exist and with
+  /// [`io::ErrorKind::PermissionDenied`] if the path escapes the root directory.
+  pub fn resolve(&self, path: &str) -> io::Result<PathBuf> {
+    let root = fs::canonicalize(&self.root)?;
+
+    let decoded = percent_encoding::percent_decode_str(path)
+      .decode_utf8()
+      .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    if decoded.contains('\0') {
+      return Err(io::Error::new(
+        io::ErrorKind::InvalidInput,
+        "path contains a nul byte",
+      ));
+    }
+
+    let relative = decoded.trim_start_matches('/');
+    let mut resolved = fs::canonicalize(root.join(relative))?;
+    ensure_inside(&root, &resolved)?;
+
+    if resolved.is_dir() {
+      let index = self
+        .index
+        .as_ref()
+        .ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))?;
+      resolved = fs::canonicalize(resolved.join(index))?;
+      ensure_inside(&root, &resolved)?;
+    }
+
+    if resolved.is_file() {
+      Ok(resolved)
+    } else {
+      Err(io::ErrorKind::NotFound.into())
+    }
+  }
+
+  /// Answers the request with the file it targets, read into memory.
+  ///
+  /// See [`Self::serve`] for more information.
+  pub fn handle<T>(&self, request: &Request<T>) -> Response<Cow<'static, [u8]>> {
+    buffer(self.serve(request)).unwrap_or_else(|e| status_response(error_status(&e)))
+  }
+
+  /// Answers the request with the file it targets, streamed from disk.
+  ///
+  /// Only `GET` and `HEAD` requests are accepted, other methods get `405 Method Not Allowed`.
+  pub fn serve<T>(&self, request: &Request<T>) -> Response<ResponseBody> {
+    let method = request.method();
+    if method != Method::GET && method != Method::HEAD {
+      let mut response = status_response(StatusCode::METHOD_NOT_ALLOWED);
+      response
+        .headers_mut()
+        .insert(ALLOW, "GET, HEAD".parse().unwrap());
+      return response.map(Into::into);
+    }
+
+    let result = self.resolve(request.uri().path()).and_then(|path| {
+      let file = fs::File::open(&path)?;
+      let mut response = Response::builder()
+        .header(CONTENT_TYPE, mime::from_path(&path))
+        .body(file)
+        .unwrap();
+
+      if let Some(cache) = &self.cache {
+        let validators = Validators::from_metadata(&response.body().metadata()?);
+        if let Some(not_modified) = cache.not_modified(request, &validators) {
+          return Ok(not_modified.map(Into::into));
+        }
+        cache.apply(&validators, response.headers_mut());
+      }
+
+      range::apply(request, response)
+    });
+
+    result.unwrap_or_else(|e| status_response(error_status(&e)).map(Into::into))
+  }
+}
+
+fn ensure_inside(root: &Path, path: &Path) -> io::Result<()> {
+  if path.starts_with(root) {
+    Ok(())
+  } else {
+    Err(io::Error::new(
+      io::ErrorKind::PermissionDenied,
+      "path escapes the root directory",
+    ))
+  }
+}
+
+/// Maps an I/O error to the status code answered for it.
+pub(crate) fn error_status(error: &io::Error) -> StatusCode {
+  match error.kind() {
+    io::ErrorKind::NotFound => StatusCode::NOT_FOUND,
+    io::ErrorKind::PermissionDenied => StatusCode::FORBIDDEN,
+    io::ErrorKind::InvalidInput => StatusCode::BAD_REQUEST,
+    _ => StatusCode::INTERNAL_SERVER_ERROR,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{super::fixture, *};
+
+  const FILES: &[(&str, &str)] = &[
+    ("root/index.html", "<h1>index</h1>"),
+    ("root/sub/my file.js", "console.log(1)"),
+    ("secret.txt", "secret"),
+  ];
+
+  fn get(directory: &Directory, uri: &str) -> Response<Cow<'static, [u8]>> {
+    directory.handle(&Request::get(uri).body(()).unwrap())
+  }
+
+  #[test]
+  fn serves_files_and_index() {
+    let dir = fixture("directory-serve", FILES);
+    let directory = Directory::new(dir.join("root"));
+
+    let response = get(&directory, "app://localhost/");
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[CONTENT_TYPE], "text/html");
+    assert_eq!(&response.body()[..], b"<h1>index</h1>");
+
+    let response = get(&directory, "app://localhost/sub/my%20file.js");
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[CONTENT_TYPE], "text/javascript");
+
+    let response = get(&directory, "app://localhost/missing.css");
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    let _ = fs::remove_dir_all(dir);
+  }
+
+  #[test]
+  fn answers_conditional_requests() {
+    let dir = fixture("directory-cache", FILES);
+    let directory = Directory::new(dir.join("root")).with_cache(Cache::new());
+
+    let response = get(&directory, "app://localhost/index.html");
+    assert_eq!(response.status(), StatusCode::OK);
+    let etag = response.headers()[http::header::ETAG].clone();
+
+    let request = Request::get("app://localhost/index.html")
+      .header(http::header::IF_NONE_MATCH, etag)
+      .body(())
+      .unwrap();
+    let response = directory.handle(&request);
+    assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+    assert!(response.body().is_empty());
+
+    let _ = fs::remove_dir_all(dir);
+  }
+
+  #[test]
+  fn refuses_paths_outside_root() {
+    let dir = fixture("directory-escape", FILES);
+    let directory = Directory::new(dir.join("root"));
+
+    for uri in [
+      "app://localhost/../secret.txt",
+      "app://localhost/sub/../../secret.txt",
+      "app://localhost/%2e%2e/secret.txt",
+      "app://localhost/..%2fsecret.txt",
+    ] {
+      let response = get(&directory, uri);
+      assert_eq!(response.status(), StatusCode::FORBIDDEN, "{uri}");
+    }
+
+    let _ = fs::remove_dir_all(dir);
+  }
+}
@@ -0,0 +1,545 @@
+// Copyright 2020-2024 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+use std::{
+  borrow::Cow,
+  io::{self, Cursor},
+};
+#[cfg(feature = "embed")]
+use std::{
+  collections::hash_map::DefaultHasher,
+  fmt::Write as _,
+  fs,
+  hash::{Hash, Hasher},
+  path::{Path, PathBuf},
+};
+
+use http::{
+  header::{ACCEPT_ENCODING, ALLOW, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_TYPE, VARY},
+  HeaderMap, Method, Request, Response, StatusCode,
+};
+
+use super::{
+  buffer,
+  cache::{Cache, Validators},
+  directory::error_status,
+  mime, range, status_response,
+};
+
+/// The encoding an [`EmbeddedAsset`] is stored with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Encoding {
+  /// Stored as is.
+  Identity,
+  /// Compressed with gzip.
+  Gzip,
+  /// Compressed with brotli.
+  Brotli,
+}
+
+impl Encoding {
+  /// The token naming this encoding in `Accept-Encoding` and `Content-Encoding` headers.
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      Self::Identity => "identity",
+      Self::Gzip => "gzip",
+      Self::Brotli => "br",
+    }
+  }
+
+  fn matches(&self, coding: &str) -> bool {
+    coding.eq_ignore_ascii_case(self.as_str())
+      || (*self == Self::Gzip && coding.eq_ignore_ascii_case("x-gzip"))
+  }
+
+  /// Whether the `Accept-Encoding` headers allow this encoding, a quality of `0` refuses it.
+  pub fn is_accepted(&self, headers: &HeaderMap) -> bool {
+    let mut wildcard = None;
+    for value in headers.get_all(ACCEPT_ENCODING) {
+      let Ok(value) = value.to_str() else {
+        continue;
+      };
+
+      for item in value.split(',') {
+        let mut params = item.split(';');
+        let coding = params.next().unwrap_or_default().trim();
+        let quality = params
+          .find_map(|param| {
+            let (name, value) = param.split_once('=')?;
+            name
+              .trim()
+              .eq_ignore_ascii_case("q")
+              .then(|| value.trim().parse::<f32>().unwrap_or(0.0))
+          })
+          .unwrap_or(1.0);
+
+        if self.matches(coding) {
+          return quality > 0.0;
+        }
+        if coding == "*" {
+          wildcard = Some(quality > 0.0);
+        }
+      }
+    }
+
+    wildcard.unwrap_or(*self == Self::Identity)
+  }
+}
+
+/// A file embedded in the binary, see [`Embedded`].
+#[derive(Debug, Clone, Copy)]
+pub struct EmbeddedAsset {
+  /// The path the asset is served at, such as `/index.html`.
+  pub path: &'static str,
+  /// The encoding `data` is stored with.
+  pub encoding: Encoding,
+  /// The stored content.
+  pub data: &'static [u8],
+  /// The entity tag of the decoded content, including its quotes.
+  pub etag: &'static str,
+}
+
+impl EmbeddedAsset {
+  /// Decodes the stored content.
+  ///
+  /// Decoding gzip and brotli requires the `embed-gzip` and `embed-brotli` features respectively,
+  /// and fails with [`io::ErrorKind::Unsupported`] otherwise.
+  pub fn decode(&self) -> io::Result<Cow<'static, [u8]>> {
+    match self.encoding {
+      Encoding::Identity => Ok(Cow::Borrowed(self.data)),
+      #[cfg(feature = "embed-gzip")]
+      Encoding::Gzip => read_all(flate2::read::GzDecoder::new(self.data)),
+      #[cfg(feature = "embed-brotli")]
+      Encoding::Brotli => read_all(brotli::Decompressor::new(self.data, 4096)),
+      #[allow(unreachable_patterns)]
+      encoding => Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!("decoding `{}` is not enabled", encoding.as_str()),
+      )),
+    }
+  }
+}
+
+#[cfg(any(feature = "embed-gzip", feature = "embed-brotli"))]
+fn read_all<R: io::Read>(mut reader: R) -> io::Result<Cow<'static, [u8]>> {
+  let mut content = Vec::new();
+  reader.read_to_end(&mut content)?;
+  Ok(Cow::Owned(content))
+}
+
+/// Serves files embedded in the binary at compile time.
+///
+/// With the `embed` feature, [`embed_dir`] generates the table of the files of a directory from the
+/// build script of your crate, and [`include_assets!`](crate::include_assets) includes it in your code.
+/// Add `wry` with the `embed` feature to your build dependencies as well:
+///
+/// ```toml
+/// [build-dependencies]
+/// wry = { version = "0.46", features = ["embed"] }
+/// ```
+///
+/// ```no_run
+/// // build.rs
+/// # #[cfg(feature = "embed")]
+/// fn main() {
+///   wry::custom_protocol::embed_dir("dist", "assets.rs").expect("failed to embed `dist`");
+/// }
+/// # #[cfg(not(feature = "embed"))]
+/// # fn main() {}
+/// ```
+///
+/// The `embed-brotli` and `embed-gzip` features compress the files when embedding them, falling back to
+/// storing them as is when that does not make them smaller. Enable them for both the build and the regular
+/// dependency, since the handler decodes the files for the requests that do not accept the encoding.
+/// Compressed files are sent as is with a `Content-Encoding` header when the request's `Accept-Encoding`
+/// allows it, since not all webviews advertise the encodings they support for custom protocols.
+///
+/// Like [`Directory`](super::Directory), index files, `Range` requests and, with [`Embedded::with_cache`],
+/// conditional requests are supported. Ranges only apply to decoded content.
+///
+/// # Examples
+///
+/// ```ignore
+/// use wry::{custom_protocol::{Embedded, EmbeddedAsset}, WebViewBuilder};
+///
+/// static ASSETS: &[EmbeddedAsset] = wry::include_assets!("assets.rs");
+///
+/// let assets = Embedded::new(ASSETS);
+/// let builder = WebViewBuilder::new()
+///   .with_custom_protocol("app".into(), move |_webview_id, request| assets.handle(&request))
+///   .with_url("app://localhost");
+/// ```
+#[derive(Debug, Clone)]
+pub struct Embedded {
+  assets: &'static [EmbeddedAsset],
+  index: Option<String>,
+  cache: Option<Cache>,
+}
+
+impl Embedded {
+  /// Creates a handler serving `assets`, using `index.html` as the index file.
+  ///
+  /// `assets` must be sorted by path.
+  pub fn new(assets: &'static [EmbeddedAsset]) -> Self {
+    debug_assert!(
+      assets.windows(2).all(|w| w[0].path < w[1].path),
+      "embedded assets must be sorted by path"
+    );
+    Self {
+      assets,
+      index: Some("index.html".into()),
+      cache: None,
+    }
+  }
+
+  /// Sets the file served for requests targeting a directory, such as `/`.
+  pub fn with_index<S: Into<String>>(mut self, index: S) -> Self {
+    self.index = Some(index.into());
+    self
+  }
+
+  /// Disables the index file, requests targeting a directory will answer `404 Not Found`.
+  pub fn without_index(mut self) -> Self {
+    self.index = None;
+    self
+  }
+
+  /// Enables conditional requests and caching headers, using the entity tags computed when embedding.
+  pub fn with_cache(mut self, cache: Cache) -> Self {
+    self.cache = Some(cache);
+    self
+  }
+
+  /// The served assets, sorted by path.
+  pub fn assets(&self) -> &'static [EmbeddedAsset] {
+    self.assets
+  }
+
+  /// Finds the asset stored at exactly `path`.
+  pub fn get(&self, path: &str) -> Option<&'static EmbeddedAsset> {
+    let assets = self.assets;
+    assets
+      .binary_search_by(|asset| asset.path.cmp(path))
+      .ok()
+      .map(|index| &assets[index])
+  }
+
+  /// Resolves a request path to an asset, falling back to the index file for directories.
+  ///
+  /// Fails with [`io::ErrorKind::NotFound`] if there is no such asset.
+  pub fn resolve(&self, path: &str) -> io::Result<&'static EmbeddedAsset> {
+    let decoded = percent_encoding::percent_decode_str(path)
+      .decode_utf8()
+      .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+
+    if !decoded.ends_with('/') {
+      if let Some(asset) = self.get(&decoded) {
+        return Ok(asset);
+      }
+    }
+
+    self
+      .index
+      .as_ref()
+      .and_then(|index| self.get(&format!("{}/{index}", decoded.trim_end_matches('/'))))
+      .ok_or_else(|| io::ErrorKind::NotFound.into())
+  }
+
+  /// Answers the request with the asset it targets.
+  ///
+  /// Only `GET` and `HEAD` requests are accepted, other methods get `405 Method Not Allowed`.
+  pub fn handle<T>(&self, request: &Request<T>) -> Response<Cow<'static, [u8]>> {
+    let method = request.method();
+    if method != Method::GET && method != Method::HEAD {
+      let mut response = status_response(StatusCode::METHOD_NOT_ALLOWED);
+      response
+        .headers_mut()
+        .insert(ALLOW, "GET, HEAD".parse().unwrap());
+      return response;
+    }
+
+    self
+      .resolve(request.uri().path())
+      .and_then(|asset| self.respond(request, asset))
+      .unwrap_or_else(|e| status_response(error_status(&e)))
+  }
+
+  fn respond<T>(
+    &self,
+    request: &Request<T>,
+    asset: &'static EmbeddedAsset,
+  ) -> io::Result<Response<Cow<'static, [u8]>>> {
+    let encoded =
+      asset.encoding != Encoding::Identity && asset.encoding.is_accepted(request.headers());
+
+    let mut response = Response::builder()
+      .header(CONTENT_TYPE, mime::from_path(asset.path))
+      .body(())
+      .unwrap();
+    if asset.encoding != Encoding::Identity {
+      response
+        .headers_mut()
+        .insert(VARY, "Accept-Encoding".parse().unwrap());
+    }
+
+    if let Some(cache) = &self.cache {
+      // each representation needs its own strong validator
+      let etag = if encoded {
+        format!(
+          "{}-{}\"",
+          asset.etag.trim_end_matches('"'),
+          asset.encoding.as_str()
+        )
+      } else {
+        asset.etag.to_string()
+      };
+      let validators = Validators {
+        etag: Some(etag),
+        last_modified: None,
+      };
+      if let Some(not_modified) = cache.not_modified(request, &validators) {
+        return Ok(not_modified);
+      }
+      cache.apply(&validators, response.headers_mut());
+    }
+
+    if encoded {
+      let headers = response.headers_mut();
+      headers.insert(CONTENT_ENCODING, asset.encoding.as_str().parse().unwrap());
+      headers.insert(CONTENT_LENGTH, asset.data.len().into());
+      let body = if request.method() == Method::HEAD {
+        Cow::Borrowed(&[][..])
+      } else {
+        Cow::Borrowed(asset.data)
+      };
+      return Ok(response.map(|()| body));
+    }
+
+    let content = asset.decode()?;
+    buffer(range::apply(
+      request,
+      response.map(|()| Cursor::new(content)),
+    )?)
+  }
+}
+
+/// Includes the table of assets generated by [`embed_dir`] in the build script, as a `&'static [EmbeddedAsset]`.
+///
+/// `$name` is the file name given to [`embed_dir`]. See [`Embedded`] for more information.
+#[cfg(feature = "embed")]
+#[cfg_attr(docsrs, doc(cfg(feature = "embed")))]
+#[macro_export]
+macro_rules! include_assets {
+  ($name:literal) => {
+    include!(concat!(env!("OUT_DIR"), "/", $name))
+  };
+}
+
+/// Embeds the files of `dir`, to be called from a build script.
+///
+/// Writes the table of assets to the `$name` file of the `OUT_DIR` of the build script, which
+/// [`include_assets!`](crate::include_assets) includes, and the compressed contents next to it.
+/// The build script is rerun when `dir` changes. See [`Embedded`] for more information.
+#[cfg(feature = "embed")]
+#[cfg_attr(docsrs, doc(cfg(feature = "embed")))]
+pub fn embed_dir<P: AsRef<Path>>(dir: P, name: &str) -> io::Result<()> {
+  fn collect(dir: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+      let path = entry?.path();
+      if path.is_dir() {
+        collect(&path, files)?;
+      } else {
+        files.push(path);
+      }
+    }
+    Ok(())
+  }
+
+  let out_dir = std::env::var_os("OUT_DIR")
+    .map(PathBuf::from)
+    .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "`OUT_DIR` is not set"))?;
+  let root = dir.as_ref().canonicalize()?;
+  println!("cargo:rerun-if-changed={}", root.display());
+
+  let mut files = Vec::new();
+  collect(&root, &mut files)?;
+
+  // sorted by path, the table is binary searched
+  let mut assets = files
+    .into_iter()
+    .map(|file| {
+      let path = file
+        .strip_prefix(&root)
+        .unwrap()
+        .to_str()
+        .ok_or_else(|| {
+          io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("embedded asset path {} is not valid UTF-8", file.display()),
+          )
+        })?
+        .replace('\\', "/");
+      Ok((format!("/{path}"), file))
+    })
+    .collect::<io::Result<Vec<_>>>()?;
+  assets.sort();
+
+  let data_dir = out_dir.join(format!("{name}.data"));
+  fs::create_dir_all(&data_dir)?;
+
+  let mut table = String::from("/* THIS FILE IS AUTO-GENERATED. DO NOT MODIFY!! */\n\n&[\n");
+  for (index, (path, file)) in assets.iter().enumerate() {
+    let content = fs::read(file)?;
+    let mut hasher = DefaultHasher::new();
+    content.hash(&mut hasher);
+    let etag = format!("\"{:x}-{:x}\"", hasher.finish(), content.len());
+
+    let (encoding, data) = encode(&content)?;
+    let data_path = data_dir.join(index.to_string());
+    // Overwrite only if changed to not trigger rebuilds
+    if fs::read(&data_path).map_or(true, |o| o != data) {
+      fs::write(&data_path, data)?;
+    }
+
+    let _ = writeln!(
+      table,
+      "  ::wry::custom_protocol::EmbeddedAsset {{ path: {path:?}, encoding: ::wry::custom_protocol::Encoding::{encoding:?}, data: include_bytes!({:?}), etag: {etag:?} }},",
+      data_path.display().to_string()
+    );
+  }
+  table.push_str("]\n");
+
+  let table_path = out_dir.join(name);
+  if fs::read_to_string(&table_path).map_or(true, |o| o != table) {
+    fs::write(table_path, table)?;
+  }
+  Ok(())
+}
+
+/// Stores the smallest encoding among the enabled ones, or the content itself
+/// if compressing it does not help, like for images that are already compressed.
+#[cfg(feature = "embed")]
+fn encode(content: &[u8]) -> io::Result<(Encoding, Vec<u8>)> {
+  #[cfg(feature = "embed-brotli")]
+  {
+    let params = brotli::enc::BrotliEncoderParams {
+      quality: 11,
+      lgwin: 22,
+      ..Default::default()
+    };
+    let mut compressed = Vec::new();
+    brotli::BrotliCompress(&mut &content[..], &mut compressed, &params)?;
+    if compressed.len() < content.len() {
+      return Ok((Encoding::Brotli, compressed));
+    }
+  }
+
+  #[cfg(feature = "embed-gzip")]
+  {
+    use std::io::Write;
+
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::best());
+    encoder.write_all(content)?;
+    let compressed = encoder.finish()?;
+    if compressed.len() < content.len() {
+      return Ok((Encoding::Gzip, compressed));
+    }
+  }
+
+  Ok((Encoding::Identity, content.to_vec()))
+}
+
+#[cfg(test)]
+mod tests {
+  use http::header::{CONTENT_RANGE, RANGE};
+
+  use super::*;
+
+  static ASSETS: &[EmbeddedAsset] = &[
+    EmbeddedAsset {
+      path: "/app.js",
+      encoding: Encoding::Brotli,
+      data: b"not really brotli",
+      etag: "\"1-2\"",
+    },
+    EmbeddedAsset {
+      path: "/index.html",
+      encoding: Encoding::Identity,
+      data: b"<h1>index</h1>",
+      etag: "\"3-4\"",
+    },
+    EmbeddedAsset {
+      path: "/sub/index.html",
+      encoding: Encoding::Identity,
+      data: b"<h1>sub</h1>",
+      etag: "\"5-6\"",
+    },
+  ];
+
+  fn get(uri: &str, headers: &[(http::HeaderName, &str)]) -> Response<Cow<'static, [u8]>> {
+    let mut request = Request::get(uri);
+    for (name, value) in headers {
+      request = request.header(name, *value);
+    }
+    Embedded::new(ASSETS)
+      .with_cache(Cache::new())
+      .handle(&request.body(()).unwrap())
+  }
+
+  #[test]
+  fn negotiates_encodings() {
+    let mut headers = HeaderMap::new();
+    assert!(Encoding::Identity.is_accepted(&headers));
+    assert!(!Encoding::Gzip.is_accepted(&headers));
+
+    headers.insert(ACCEPT_ENCODING, "gzip;q=0.5, br;q=0".parse().unwrap());
+    assert!(Encoding::Gzip.is_accepted(&headers));
+    assert!(!Encoding::Brotli.is_accepted(&headers));
+
+    headers.insert(ACCEPT_ENCODING, "*".parse().unwrap());
+    assert!(Encoding::Brotli.is_accepted(&headers));
+
+    headers.insert(ACCEPT_ENCODING, "x-gzip, *;q=0".parse().unwrap());
+    assert!(Encoding::Gzip.is_accepted(&headers));
+    assert!(!Encoding::Brotli.is_accepted(&headers));
+  }
+
+  #[test]
+  fn serves_assets() {
+    let response = get("app://localhost/", &[]);
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[CONTENT_TYPE], "text/html");
+    assert_eq!(&response.body()[..], b"<h1>index</h1>");
+
+    let response = get("app://localhost/sub", &[]);
+    assert_eq!(&response.body()[..], b"<h1>sub</h1>");
+
+    let response = get("app://localhost/index.html", &[(RANGE, "bytes=1-2")]);
+    assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(response.headers()[CONTENT_RANGE], "bytes 1-2/14");
+    assert_eq!(&response.body()[..], b"h1");
+
+    let response = get("app://localhost/missing.js", &[]);
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+  }
+
+  #[test]
+  fn serves_encoded_assets() {
+    let response = get("app://localhost/app.js", &[(ACCEPT_ENCODING, "gzip, br")]);
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[CONTENT_ENCODING], "br");
+    assert_eq!(response.headers()[VARY], "Accept-Encoding");
+    assert_eq!(response.headers()[http::header::ETAG], "\"1-2-br\"");
+    assert_eq!(&response.body()[..], b"not really brotli");
+
+    let response = get(
+      "app://localhost/app.js",
+      &[
+        (ACCEPT_ENCODING, "br"),
+        (http::header::IF_NONE_MATCH, "\"1-2-br\""),
+      ],
+    );
+    assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+  }
+}
@@ -0,0 +1,319 @@
+// Copyright 2020-2024 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+use std::{borrow::Cow, error::Error, fmt, fmt::Write, io, sync::Arc};
+
+use http::{
+  header::{ACCEPT, CONTENT_TYPE},
+  Request, Response, StatusCode,
+};
+
+use super::mime;
+use crate::{util::json_string, WebViewId};
+
+const DEFAULT_STYLESHEET: &str = "body { font-family: system-ui, sans-serif; margin: 0; padding: 2rem; color: #222; background: #fafafa; }
+h1 { font-size: 1.5rem; margin: 0 0 1rem; }
+p, li { line-height: 1.5; }
+code { font-size: 0.875rem; color: #666; word-break: break-all; }
+@media (prefers-color-scheme: dark) { body { color: #eee; background: #1e1e1e; } code { color: #aaa; } }";
+
+type StatusFn = dyn Fn(&(dyn Error + 'static)) -> StatusCode + Send + Sync;
+
+/// Renders the responses of failing custom protocol handlers.
+///
+/// Requests that prefer `application/json` in their `Accept` header get a JSON object,
+/// others a styled HTML page:
+///
+/// ```json
+/// { "status": 404, "error": "Not Found", "message": "No such file or directory (os error 2)", "causes": [] }
+/// ```
+///
+/// The status is `404 Not Found`, `403 Forbidden` or `400 Bad Request` for the matching [`io::ErrorKind`]s
+/// and `500 Internal Server Error` for other errors, see [`ErrorPage::with_status`]. The error message and its
+/// sources are only shown in debug builds, see [`ErrorPage::with_details`]. Errors are also logged with the
+/// `tracing` feature.
+///
+/// # Examples
+///
+/// ```no_run
+/// use wry::{custom_protocol::ErrorPage, http::{Response, StatusCode}, WebViewBuilder};
+///
+/// let error_page = ErrorPage::new()
+///   .with_stylesheet("body { font-family: serif; }")
+///   .with_status(|error| {
+///     if error.is::<std::num::ParseIntError>() {
+///       StatusCode::BAD_REQUEST
+///     } else {
+///       StatusCode::INTERNAL_SERVER_ERROR
+///     }
+///   });
+///
+/// let builder = WebViewBuilder::new().with_custom_protocol(
+///   "app".into(),
+///   error_page.wrap(|_webview_id, request| {
+///     let id: u32 = request.uri().path().trim_start_matches('/').parse()?;
+///     Ok::<_, std::num::ParseIntError>(Response::new(id.to_string().into_bytes()))
+///   }),
+/// );
+/// ```
+#[derive(Clone)]
+pub struct ErrorPage {
+  stylesheet: Cow<'static, str>,
+  details: bool,
+  status: Option<Arc<StatusFn>>,
+}
+
+impl fmt::Debug for ErrorPage {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("ErrorPage")
+      .field("stylesheet", &self.stylesheet)
+      .field("details", &self.details)
+      .field("status", &self.status.as_ref().map(|_| ".."))
+      .finish()
+  }
+}
+
+impl Default for ErrorPage {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl ErrorPage {
+  /// Creates an error page with the default stylesheet, showing error details in debug builds only.
+  pub fn new() -> Self {
+    Self {
+      stylesheet: Cow::Borrowed(DEFAULT_STYLESHEET),
+      details: cfg!(debug_assertions),
+      status: None,
+    }
+  }
+
+  /// Replaces the CSS of the HTML page.
+  pub fn with_stylesheet(mut self, stylesheet: impl Into<Cow<'static, str>>) -> Self {
+    self.stylesheet = stylesheet.into();
+    self
+  }
+
+  /// Sets whether the error message and its sources are shown, which may expose paths and other internals.
+  pub fn with_details(mut self, details: bool) -> Self {
+    self.details = details;
+    self
+  }
+
+  /// Maps errors to the response status, instead of using their [`io::ErrorKind`].
+  pub fn with_status<F>(mut self, status: F) -> Self
+  where
+    F: Fn(&(dyn Error + 'static)) -> StatusCode + Send + Sync + 'static,
+  {
+    self.status = Some(Arc::new(status));
+    self
+  }
+
+  /// The status of the response to `error`.
+  pub fn status(&self, error: &(dyn Error + 'static)) -> StatusCode {
+    if let Some(status) = &self.status {
+      return status(error);
+    }
+    match error.downcast_ref::<io::Error>().map(io::Error::kind) {
+      Some(io::ErrorKind::NotFound) => StatusCode::NOT_FOUND,
+      Some(io::ErrorKind::PermissionDenied) => StatusCode::FORBIDDEN,
+      Some(io::ErrorKind::InvalidInput | io::ErrorKind::InvalidData) => StatusCode::BAD_REQUEST,
+      _ => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+  }
+
+  /// Builds the response to `request` for `error`, as HTML or JSON depending on its `Accept` header.
+  pub fn render<T>(
+    &self,
+    request: &Request<T>,
+    error: &(dyn Error + 'static),
+  ) -> Response<Cow<'static, [u8]>> {
+    let status = self.status(error);
+
+    #[cfg(feature = "tracing")]
+    tracing::error!(
+      uri = %request.uri(),
+      status = status.as_u16(),
+      "custom protocol handler failed: {error}"
+    );
+
+    let reason = status.canonical_reason().unwrap_or_default();
+    let causes = std::iter::successors(error.source(), |e| (*e).source())
+      .map(ToString::to_string)
+      .collect::<Vec<_>>();
+    let message = self.details.then(|| error.to_string());
+
+    let accept = request
+      .headers()
+      .get(ACCEPT)
+      .and_then(|accept| accept.to_str().ok())
+      .unwrap_or_default();
+    let (content_type, body) = if prefers_json(accept) {
+      let mut body = format!(
+        "{{\"status\":{},\"error\":{}",
+        status.as_u16(),
+        json_string(reason)
+      );
+      if let Some(message) = &message {
+        let causes = causes.iter().map(|c| json_string(c)).collect::<Vec<_>>();
+        let _ = write!(
+          body,
+          ",\"message\":{},\"causes\":[{}]",
+          json_string(message),
+          causes.join(",")
+        );
+      }
+      body.push('}');
+      (mime::APPLICATION_JSON, body)
+    } else {
+      let title = format!("{} {reason}", status.as_u16());
+      let mut body = format!(
+        "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><title>{title}</title><style>{}</style></head><body><h1>{title}</h1>",
+        self.stylesheet
+      );
+      if let Some(message) = &message {
+        let _ = write!(body, "<p>{}</p>", html(message));
+        if !causes.is_empty() {
+          body.push_str("<ul>");
+          for cause in &causes {
+            let _ = write!(body, "<li>{}</li>", html(cause));
+          }
+          body.push_str("</ul>");
+        }
+      }
+      let _ = write!(
+        body,
+        "<p><code>{}</code></p></body></html>",
+        html(&request.uri().to_string())
+      );
+      (mime::TEXT_HTML, body)
+    };
+
+    Response::builder()
+      .status(status)
+      .header(CONTENT_TYPE, content_type)
+      .body(Cow::Owned(body.into_bytes()))
+      .unwrap()
+  }
+
+  /// Wraps a fallible custom protocol handler so its errors are answered with [`ErrorPage::render`].
+  pub fn wrap<T, B, E, F>(
+    &self,
+    handler: F,
+  ) -> impl Fn(WebViewId, Request<T>) -> Response<Cow<'static, [u8]>> + 'static
+  where
+    B: Into<Cow<'static, [u8]>>,
+    E: Into<Box<dyn Error>>,
+    F: Fn(WebViewId, Request<T>) -> Result<Response<B>, E> + 'static,
+  {
+    let error_page = self.clone();
+    move |webview_id, request| {
+      // the handler consumes the request, keep what the error page needs
+      let mut head = Request::new(());
+      *head.uri_mut() = request.uri().clone();
+      *head.headers_mut() = request.headers().clone();
+
+      match handler(webview_id, request) {
+        Ok(response) => response.map(Into::into),
+        Err(error) => error_page.render(&head, &*error.into()),
+      }
+    }
+  }
+}
+
+/// Whether `application/json` has a higher quality than `text/html` in an `Accept` header.
+fn prefers_json(accept: &str) -> bool {
+  let (mut html, mut json) = (0.0, 0.0);
+  for range in accept.split(',') {
+    let mut params = range.split(';').map(str::trim);
+    let media = params.next().unwrap_or_default().to_ascii_lowercase();
+    let quality = params
+      .find_map(|param| param.strip_prefix("q="))
+      .and_then(|q| q.parse::<f32>().ok())
+      .unwrap_or(1.0);
+    match media.as_str() {
+      "text/html" | "text/*" => html = f32::max(html, quality),
+      "*/*" => {
+        html = f32::max(html, quality);
+        json = f32::max(json, quality);
+      }
+      media if media == "application/json" || media.ends_with("+json") => {
+        json = f32::max(json, quality)
+      }
+      _ => {}
+    }
+  }
+  json > html
+}
+
+fn html(text: &str) -> String {
+  let mut escaped = String::with_capacity(text.len());
+  for c in text.chars() {
+    match c {
+      '&' => escaped.push_str("&amp;"),
+      '<' => escaped.push_str("&lt;"),
+      '>' => escaped.push_str("&gt;"),
+      '"' => escaped.push_str("&quot;"),
+      '\'' => escaped.push_str("&#39;"),
+      c => escaped.push(c),
+    }
+  }
+  escaped
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn request(accept: &str) -> Request<()> {
+    Request::builder()
+      .uri("app://localhost/missing?a='b'")
+      .header(ACCEPT, accept)
+      .body(())
+      .unwrap()
+  }
+
+  #[test]
+  fn negotiates_format() {
+    assert!(!prefers_json(""));
+    assert!(!prefers_json("*/*"));
+    assert!(!prefers_json(
+      "text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8"
+    ));
+    assert!(prefers_json("application/json"));
+    assert!(prefers_json("application/problem+json, */*;q=0.1"));
+    assert!(!prefers_json("application/json;q=0.5, text/html"));
+  }
+
+  #[test]
+  fn renders_errors() {
+    let error = io::Error::new(io::ErrorKind::NotFound, "\"missing\" <file>");
+    let page = ErrorPage::new().with_details(true);
+
+    let response = page.render(&request("application/json"), &error);
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert_eq!(response.headers()[CONTENT_TYPE], mime::APPLICATION_JSON);
+    assert_eq!(
+      std::str::from_utf8(response.body()).unwrap(),
+      r#"{"status":404,"error":"Not Found","message":"\"missing\" <file>","causes":[]}"#
+    );
+
+    let response = page.render(&request("text/html"), &error);
+    assert_eq!(response.headers()[CONTENT_TYPE], mime::TEXT_HTML);
+    let body = std::str::from_utf8(response.body()).unwrap();
+    assert!(body.contains("<h1>404 Not Found</h1><p>&quot;missing&quot; &lt;file&gt;</p>"));
+    assert!(body.contains("<code>app://localhost/missing?a=&#39;b&#39;</code>"));
+
+    // details are hidden on request
+    let page = page
+      .with_details(false)
+      .with_status(|_| StatusCode::SERVICE_UNAVAILABLE);
+    let response = page.render(&request("application/json"), &error);
+    assert_eq!(
+      std::str::from_utf8(response.body()).unwrap(),
+      r#"{"status":503,"error":"Service Unavailable"}"#
+    );
+  }
+}
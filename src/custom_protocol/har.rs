@@ -0,0 +1,371 @@
+// Copyright 2020-2024 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+//! Recording of custom protocol traffic in the HTTP Archive (HAR) format.
+//!
+//! A [`HarRecorder`] registered with [`WebViewBuilder::with_custom_protocol_recorder`] captures the requests
+//! and responses of every custom protocol of the webview. The recording can be written to a `.har` file
+//! and opened in the network panel of browser devtools.
+//!
+//! ```no_run
+//! use wry::{custom_protocol::{har::HarRecorder, Directory}, WebViewBuilder};
+//!
+//! let recorder = HarRecorder::new();
+//! let assets = Directory::new("dist");
+//! let builder = WebViewBuilder::new()
+//!   .with_custom_protocol("app".into(), move |_webview_id, request| assets.handle(&request))
+//!   .with_custom_protocol_recorder(recorder.clone());
+//!
+//! // later, for example when the window is closed
+//! recorder.write("wry.har").unwrap();
+//! ```
+//!
+//! Bodies are not captured, only their sizes. With the `tracing` feature, an event with the status,
+//! duration and body sizes is also emitted in the `wry::custom_protocol::handle` span of each request.
+//!
+//! [`WebViewBuilder::with_custom_protocol_recorder`]: crate::WebViewBuilder::with_custom_protocol_recorder
+
+use std::{
+  fmt::Write as _,
+  fs, io,
+  path::Path,
+  sync::{Arc, Mutex},
+  time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use http::{
+  header::{CONTENT_LENGTH, CONTENT_TYPE, LOCATION},
+  HeaderMap, Request, Response, Version,
+};
+use percent_encoding::percent_decode_str;
+
+use super::cache::civil_from_days;
+use crate::{util::json_string, RequestAsyncResponder, ResponseBody, WebViewId};
+
+/// A request and its response.
+#[derive(Debug, Clone)]
+struct Entry {
+  webview_id: String,
+  started: SystemTime,
+  time: Duration,
+  method: String,
+  url: String,
+  query: Vec<(String, String)>,
+  version: Version,
+  request_headers: Vec<(String, String)>,
+  request_body_size: i64,
+  status: u16,
+  response_headers: Vec<(String, String)>,
+  response_body_size: i64,
+  mime_type: String,
+  redirect_url: String,
+}
+
+/// Records the requests and responses of custom protocols, see the [module documentation](self).
+///
+/// Clones share the same recording. Requests cancelled by the webview before they were answered are not recorded.
+#[derive(Debug, Clone, Default)]
+pub struct HarRecorder {
+  entries: Arc<Mutex<Vec<Entry>>>,
+}
+
+impl HarRecorder {
+  /// Creates an empty recorder.
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// The number of recorded requests.
+  pub fn len(&self) -> usize {
+    self.entries.lock().unwrap().len()
+  }
+
+  /// Whether no request was recorded yet.
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+
+  /// Removes the recorded requests.
+  pub fn clear(&self) {
+    self.entries.lock().unwrap().clear();
+  }
+
+  /// Wraps a custom protocol handler so its requests and responses are recorded.
+  pub fn wrap<F>(
+    &self,
+    handler: F,
+  ) -> impl Fn(WebViewId, Request<Vec<u8>>, RequestAsyncResponder) + 'static
+  where
+    F: Fn(WebViewId, Request<Vec<u8>>, RequestAsyncResponder) + 'static,
+  {
+    self.wrap_with(handler, |request| request.body().len() as i64)
+  }
+
+  /// Same as [`HarRecorder::wrap`] for handlers reading the request body as a stream,
+  /// whose size is only known from its `Content-Length` header.
+  #[cfg(gtk)]
+  pub(crate) fn wrap_streaming<F>(
+    &self,
+    handler: F,
+  ) -> impl Fn(WebViewId, Request<crate::RequestBody>, RequestAsyncResponder) + 'static
+  where
+    F: Fn(WebViewId, Request<crate::RequestBody>, RequestAsyncResponder) + 'static,
+  {
+    self.wrap_with(handler, |request| content_length(request.headers()))
+  }
+
+  fn wrap_with<T: 'static, F>(
+    &self,
+    handler: F,
+    body_size: fn(&Request<T>) -> i64,
+  ) -> impl Fn(WebViewId, Request<T>, RequestAsyncResponder) + 'static
+  where
+    F: Fn(WebViewId, Request<T>, RequestAsyncResponder) + 'static,
+  {
+    let entries = self.entries.clone();
+    move |webview_id, request, responder| {
+      // the handler runs in the `wry::custom_protocol::handle` span, report to it once the response is sent
+      #[cfg(feature = "tracing")]
+      let span = tracing::Span::current();
+
+      let started = SystemTime::now();
+      let start = Instant::now();
+      let mut entry = Entry {
+        webview_id: webview_id.to_string(),
+        started,
+        time: Duration::ZERO,
+        method: request.method().to_string(),
+        url: request.uri().to_string(),
+        query: request.uri().query().map(parse_query).unwrap_or_default(),
+        version: request.version(),
+        request_headers: headers(request.headers()),
+        request_body_size: body_size(&request),
+        status: 0,
+        response_headers: Vec::new(),
+        response_body_size: -1,
+        mime_type: String::new(),
+        redirect_url: String::new(),
+      };
+
+      let entries = entries.clone();
+      let RequestAsyncResponder {
+        responder,
+        cancellation,
+      } = responder;
+      let responder = RequestAsyncResponder {
+        responder: Box::new(move |response: Response<ResponseBody>| {
+          entry.time = start.elapsed();
+          entry.status = response.status().as_u16();
+          entry.response_headers = headers(response.headers());
+          entry.response_body_size = match response.body() {
+            ResponseBody::Bytes(bytes) => bytes.len() as i64,
+            ResponseBody::Reader(_) => content_length(response.headers()),
+          };
+          entry.mime_type = header(response.headers(), CONTENT_TYPE).unwrap_or_default();
+          entry.redirect_url = header(response.headers(), LOCATION).unwrap_or_default();
+
+          #[cfg(feature = "tracing")]
+          tracing::debug!(
+            parent: &span,
+            status = entry.status,
+            time_ms = entry.time.as_secs_f64() * 1000.0,
+            request_body_size = entry.request_body_size,
+            response_body_size = entry.response_body_size,
+            "custom protocol request recorded"
+          );
+
+          entries.lock().unwrap().push(entry);
+          responder(response)
+        }),
+        cancellation,
+      };
+      handler(webview_id, request, responder)
+    }
+  }
+
+  /// Serializes the recording as a HAR 1.2 JSON document.
+  pub fn to_json(&self) -> String {
+    let entries = self.entries.lock().unwrap();
+    let mut json = format!(
+      "{{\"log\":{{\"version\":\"1.2\",\"creator\":{{\"name\":\"wry\",\"version\":\"{}\"}},\"entries\":[",
+      env!("CARGO_PKG_VERSION")
+    );
+    for (i, entry) in entries.iter().enumerate() {
+      if i > 0 {
+        json.push(',');
+      }
+      write_entry(&mut json, entry);
+    }
+    json.push_str("]}}");
+    json
+  }
+
+  /// Writes the recording to a HAR file.
+  pub fn write<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+    fs::write(path, self.to_json())
+  }
+}
+
+fn write_entry(json: &mut String, entry: &Entry) {
+  let time = entry.time.as_secs_f64() * 1000.0;
+  let version = json_string(&format!("{:?}", entry.version));
+  let _ = write!(
+    json,
+    "{{\"startedDateTime\":{},\"time\":{time},\"_webviewId\":{},\"request\":{{\"method\":{},\"url\":{},\"httpVersion\":{version},\"cookies\":[],\"headers\":",
+    json_string(&iso8601(entry.started)),
+    json_string(&entry.webview_id),
+    json_string(&entry.method),
+    json_string(&entry.url),
+  );
+  write_pairs(json, &entry.request_headers);
+  json.push_str(",\"queryString\":");
+  write_pairs(json, &entry.query);
+  let _ = write!(
+    json,
+    ",\"headersSize\":-1,\"bodySize\":{}}},\"response\":{{\"status\":{},\"statusText\":{},\"httpVersion\":{version},\"cookies\":[],\"headers\":",
+    entry.request_body_size,
+    entry.status,
+    json_string(
+      http::StatusCode::from_u16(entry.status)
+        .ok()
+        .and_then(|status| status.canonical_reason())
+        .unwrap_or_default()
+    ),
+  );
+  write_pairs(json, &entry.response_headers);
+  let _ = write!(
+    json,
+    ",\"content\":{{\"size\":{},\"mimeType\":{}}},\"redirectURL\":{},\"headersSize\":-1,\"bodySize\":{}}},\"cache\":{{}},\"timings\":{{\"send\":0,\"wait\":{time},\"receive\":0}}}}",
+    entry.response_body_size,
+    json_string(&entry.mime_type),
+    json_string(&entry.redirect_url),
+    entry.response_body_size,
+  );
+}
+
+fn write_pairs(json: &mut String, pairs: &[(String, String)]) {
+  json.push('[');
+  for (i, (name, value)) in pairs.iter().enumerate() {
+    if i > 0 {
+      json.push(',');
+    }
+    let _ = write!(
+      json,
+      "{{\"name\":{},\"value\":{}}}",
+      json_string(name),
+      json_string(value)
+    );
+  }
+  json.push(']');
+}
+
+fn headers(headers: &HeaderMap) -> Vec<(String, String)> {
+  headers
+    .iter()
+    .map(|(name, value)| {
+      let value = String::from_utf8_lossy(value.as_bytes()).into_owned();
+      (name.to_string(), value)
+    })
+    .collect()
+}
+
+fn header(headers: &HeaderMap, name: http::HeaderName) -> Option<String> {
+  headers
+    .get(name)
+    .map(|value| String::from_utf8_lossy(value.as_bytes()).into_owned())
+}
+
+fn content_length(headers: &HeaderMap) -> i64 {
+  headers
+    .get(CONTENT_LENGTH)
+    .and_then(|length| length.to_str().ok())
+    .and_then(|length| length.parse().ok())
+    .unwrap_or(-1)
+}
+
+fn parse_query(query: &str) -> Vec<(String, String)> {
+  query
+    .split('&')
+    .filter(|pair| !pair.is_empty())
+    .map(|pair| {
+      let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+      let decode = |s: &str| {
+        percent_decode_str(&s.replace('+', " "))
+          .decode_utf8_lossy()
+          .into_owned()
+      };
+      (decode(name), decode(value))
+    })
+    .collect()
+}
+
+/// Formats a time as an ISO 8601 UTC date, such as `2024-01-31T12:00:00.000Z`.
+fn iso8601(time: SystemTime) -> String {
+  let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+  let secs = since_epoch.as_secs();
+  let (days, secs_of_day) = ((secs / 86400) as i64, secs % 86400);
+  let (year, month, day) = civil_from_days(days);
+
+  format!(
+    "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}.{:03}Z",
+    secs_of_day / 3600,
+    secs_of_day / 60 % 60,
+    secs_of_day % 60,
+    since_epoch.subsec_millis()
+  )
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn formats_dates() {
+    assert_eq!(iso8601(UNIX_EPOCH), "1970-01-01T00:00:00.000Z");
+    assert_eq!(
+      iso8601(UNIX_EPOCH + Duration::from_millis(1_709_210_096_789)),
+      "2024-02-29T12:34:56.789Z"
+    );
+  }
+
+  #[test]
+  fn records_requests() {
+    let recorder = HarRecorder::new();
+    let handler = recorder.wrap(|_webview_id, _request, responder| {
+      responder.respond(
+        Response::builder()
+          .status(302)
+          .header(LOCATION, "app://localhost/home")
+          .header(CONTENT_TYPE, "text/plain")
+          .body(b"moved".to_vec())
+          .unwrap(),
+      )
+    });
+
+    let request = Request::builder()
+      .method("POST")
+      .uri("app://localhost/login?next=%2Fhome&remember")
+      .header("X-Answer", "42")
+      .body(b"user".to_vec())
+      .unwrap();
+    let (tx, rx) = std::sync::mpsc::channel();
+    let responder = RequestAsyncResponder {
+      responder: Box::new(move |response| tx.send(response.status()).unwrap()),
+      cancellation: Default::default(),
+    };
+    handler("main", request, responder);
+    assert_eq!(rx.recv().unwrap(), 302);
+    assert_eq!(recorder.len(), 1);
+
+    let json = recorder.to_json();
+    assert!(json.starts_with("{\"log\":{\"version\":\"1.2\""));
+    assert!(json.contains("\"_webviewId\":\"main\",\"request\":{\"method\":\"POST\",\"url\":\"app://localhost/login?next=%2Fhome&remember\",\"httpVersion\":\"HTTP/1.1\""));
+    assert!(json.contains("\"headers\":[{\"name\":\"x-answer\",\"value\":\"42\"}],\"queryString\":[{\"name\":\"next\",\"value\":\"/home\"},{\"name\":\"remember\",\"value\":\"\"}],\"headersSize\":-1,\"bodySize\":4}"));
+    assert!(json.contains("\"status\":302,\"statusText\":\"Found\""));
+    assert!(json.contains("\"content\":{\"size\":5,\"mimeType\":\"text/plain\"},\"redirectURL\":\"app://localhost/home\""));
+
+    recorder.clear();
+    assert!(recorder.is_empty());
+  }
+}
@@ -0,0 +1,94 @@
+// Copyright 2020-2024 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+//! MIME type detection for assets served over custom protocols.
+
+use std::path::Path;
+
+/// `application/octet-stream`, used when the type of a file can not be determined.
+pub const OCTET_STREAM: &str = "application/octet-stream";
+/// `text/plain`
+pub const TEXT_PLAIN: &str = "text/plain";
+/// `text/html`
+pub const TEXT_HTML: &str = "text/html";
+/// `application/json`
+pub const APPLICATION_JSON: &str = "application/json";
+
+/// Returns the MIME type for the given file extension (without the leading dot).
+///
+/// The lookup is case insensitive. Returns `None` for unknown extensions.
+pub fn from_extension(extension: &str) -> Option<&'static str> {
+  let mime = match extension.to_ascii_lowercase().as_str() {
+    // text
+    "html" | "htm" => TEXT_HTML,
+    "css" => "text/css",
+    "js" | "mjs" | "cjs" => "text/javascript",
+    "txt" | "text" | "log" => TEXT_PLAIN,
+    "csv" => "text/csv",
+    "md" | "markdown" => "text/markdown",
+    "xml" => "text/xml",
+    "vtt" => "text/vtt",
+    "ics" => "text/calendar",
+    // application
+    "json" | "map" => APPLICATION_JSON,
+    "jsonld" => "application/ld+json",
+    "webmanifest" => "application/manifest+json",
+    "wasm" => "application/wasm",
+    "pdf" => "application/pdf",
+    "xhtml" => "application/xhtml+xml",
+    "rtf" => "application/rtf",
+    "zip" => "application/zip",
+    "gz" => "application/gzip",
+    "tar" => "application/x-tar",
+    "7z" => "application/x-7z-compressed",
+    "bin" | "exe" | "dll" => OCTET_STREAM,
+    // images
+    "png" => "image/png",
+    "apng" => "image/apng",
+    "jpg" | "jpeg" | "jpe" | "jfif" => "image/jpeg",
+    "gif" => "image/gif",
+    "webp" => "image/webp",
+    "avif" => "image/avif",
+    "svg" | "svgz" => "image/svg+xml",
+    "ico" => "image/vnd.microsoft.icon",
+    "bmp" => "image/bmp",
+    "tif" | "tiff" => "image/tiff",
+    // fonts
+    "woff" => "font/woff",
+    "woff2" => "font/woff2",
+    "ttf" => "font/ttf",
+    "otf" => "font/otf",
+    "eot" => "application/vnd.ms-fontobject",
+    // audio
+    "mp3" => "audio/mpeg",
+    "wav" => "audio/wav",
+    "oga" | "ogg" | "opus" => "audio/ogg",
+    "weba" => "audio/webm",
+    "aac" => "audio/aac",
+    "flac" => "audio/flac",
+    "m4a" => "audio/mp4",
+    "mid" | "midi" => "audio/midi",
+    // video
+    "mp4" | "m4v" => "video/mp4",
+    "webm" => "video/webm",
+    "ogv" => "video/ogg",
+    "mov" => "video/quicktime",
+    "avi" => "video/x-msvideo",
+    "mpeg" | "mpg" => "video/mpeg",
+    "ts" => "video/mp2t",
+    _ => return None,
+  };
+  Some(mime)
+}
+
+/// Returns the MIME type for the given path based on its extension,
+/// falling back to [`OCTET_STREAM`] if the extension is missing or unknown.
+pub fn from_path<P: AsRef<Path>>(path: P) -> &'static str {
+  path
+    .as_ref()
+    .extension()
+    .and_then(|ext| ext.to_str())
+    .and_then(from_extension)
+    .unwrap_or(OCTET_STREAM)
+}
@@ -0,0 +1,91 @@
+// Copyright 2020-2024 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+//! Ready-made building blocks for custom protocol handlers.
+//!
+//! The handlers in this module plug directly into [`WebViewBuilder::with_custom_protocol`]
+//! and [`WebViewBuilder::with_asynchronous_custom_protocol`].
+//!
+//! ```no_run
+//! use wry::{custom_protocol::Directory, WebViewBuilder};
+//!
+//! let assets = Directory::new("dist");
+//! let builder = WebViewBuilder::new()
+//!   .with_custom_protocol("app".into(), move |_webview_id, request| assets.handle(&request))
+//!   .with_url("app://localhost");
+//! ```
+//!
+//! With the `embed` feature, [`Embedded`] serves a directory embedded in the binary at compile time instead,
+//! and with the `zip` feature, `Archive` serves the entries of a zip archive.
+//! [`ReverseProxy`] forwards requests to a local HTTP server, such as a frontend dev server.
+//! [`ErrorPage`] answers the errors of fallible handlers with an HTML or JSON error page,
+//! and [`har::HarRecorder`] records the traffic of custom protocols for debugging.
+//!
+//! [`WebViewBuilder::with_custom_protocol`]: crate::WebViewBuilder::with_custom_protocol
+//! [`WebViewBuilder::with_asynchronous_custom_protocol`]: crate::WebViewBuilder::with_asynchronous_custom_protocol
+
+#[cfg(feature = "zip")]
+mod archive;
+pub mod cache;
+pub mod cors;
+mod directory;
+mod embedded;
+mod error_page;
+pub mod har;
+pub mod mime;
+pub mod range;
+mod reverse_proxy;
+mod spawn;
+#[cfg(feature = "tower")]
+#[cfg_attr(docsrs, doc(cfg(feature = "tower")))]
+pub mod tower;
+
+use std::{borrow::Cow, io};
+
+use http::{header::CONTENT_TYPE, Response, StatusCode};
+
+use crate::ResponseBody;
+
+#[cfg(feature = "zip")]
+#[cfg_attr(docsrs, doc(cfg(feature = "zip")))]
+pub use archive::Archive;
+pub use directory::Directory;
+#[cfg(feature = "embed")]
+#[cfg_attr(docsrs, doc(cfg(feature = "embed")))]
+pub use embedded::embed_dir;
+pub use embedded::{Embedded, EmbeddedAsset, Encoding};
+pub use error_page::ErrorPage;
+pub use reverse_proxy::ReverseProxy;
+pub use spawn::{SpawnedFuture, Spawner, ThreadSpawner};
+
+/// Reads a streamed response body into memory, so it can be returned from a synchronous
+/// [`WebViewBuilder::with_custom_protocol`](crate::WebViewBuilder::with_custom_protocol) handler.
+pub fn buffer(response: Response<ResponseBody>) -> io::Result<Response<Cow<'static, [u8]>>> {
+  let (parts, body) = response.into_parts();
+  Ok(Response::from_parts(parts, body.into_bytes()?))
+}
+
+/// Builds a plain text response with the given status, used for error answers.
+pub(crate) fn status_response(status: StatusCode) -> Response<Cow<'static, [u8]>> {
+  let reason = status.canonical_reason().unwrap_or_default();
+  Response::builder()
+    .status(status)
+    .header(CONTENT_TYPE, mime::TEXT_PLAIN)
+    .body(Cow::Borrowed(reason.as_bytes()))
+    .unwrap()
+}
+
+/// Creates a fresh temporary directory for the `name` test, containing the `(path, content)` files.
+#[cfg(test)]
+pub(crate) fn fixture(name: &str, files: &[(&str, &str)]) -> std::path::PathBuf {
+  let dir = std::env::temp_dir().join(format!("wry-{name}-{}", std::process::id()));
+  let _ = std::fs::remove_dir_all(&dir);
+  std::fs::create_dir_all(&dir).unwrap();
+  for (path, content) in files {
+    let path = dir.join(path);
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    std::fs::write(path, content).unwrap();
+  }
+  dir
+}
@@ -0,0 +1,470 @@
+// Copyright 2020-2024 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+//! HTTP `Range` requests support for custom protocol responses.
+//!
+//! This is what makes seeking in `<video>` and `<audio>` elements work over a custom protocol.
+//!
+//! ```no_run
+//! use wry::{custom_protocol::range, http::{header::CONTENT_TYPE, Response}, WebViewBuilder};
+//!
+//! let builder = WebViewBuilder::new().with_asynchronous_custom_protocol(
+//!   "media".into(),
+//!   |_webview_id, request, responder| {
+//!     std::thread::spawn(move || {
+//!       let file = std::fs::File::open("video.mp4").unwrap();
+//!       let response = Response::builder()
+//!         .header(CONTENT_TYPE, "video/mp4")
+//!         .body(file)
+//!         .unwrap();
+//!       responder.respond_with_body(range::apply(&request, response).unwrap());
+//!     });
+//!   },
+//! );
+//! ```
+
+use std::{
+  collections::{hash_map::RandomState, VecDeque},
+  hash::{BuildHasher, Hasher},
+  io::{self, Cursor, Read, Seek, SeekFrom},
+};
+
+use http::{
+  header::{
+    HeaderValue, ACCEPT_RANGES, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, ETAG, IF_RANGE,
+    LAST_MODIFIED, RANGE,
+  },
+  HeaderMap, Method, Request, Response, StatusCode,
+};
+
+use crate::ResponseBody;
+
+/// Requests with more ranges than this are answered with the whole content.
+const MAX_RANGES: usize = 64;
+
+/// An inclusive byte range, `start..=end`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByteRange {
+  /// The first byte of the range.
+  pub start: u64,
+  /// The last byte of the range, inclusive.
+  pub end: u64,
+}
+
+impl ByteRange {
+  /// The number of bytes in the range.
+  pub fn len(&self) -> u64 {
+    self.end - self.start + 1
+  }
+
+  /// Always `false`, a byte range contains at least one byte.
+  pub fn is_empty(&self) -> bool {
+    false
+  }
+}
+
+/// The outcome of parsing a `Range` header against a content length.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Ranges {
+  /// The header is missing, malformed or uses another unit than `bytes`,
+  /// the whole content should be sent.
+  Full,
+  /// None of the ranges overlap the content, `416 Range Not Satisfiable` should be sent.
+  Unsatisfiable,
+  /// The satisfiable ranges, sorted and with overlapping ranges merged.
+  Satisfiable(Vec<ByteRange>),
+}
+
+/// Parses a `Range` header value, such as `bytes=0-499, -500`, for a content of `len` bytes.
+pub fn parse(header: &str, len: u64) -> Ranges {
+  let Some(specs) = header.trim().strip_prefix("bytes=") else {
+    return Ranges::Full;
+  };
+
+  let mut ranges = Vec::new();
+  let mut count = 0;
+  for spec in specs.split(',') {
+    let spec = spec.trim();
+    if spec.is_empty() {
+      continue;
+    }
+    count += 1;
+    if count > MAX_RANGES {
+      return Ranges::Full;
+    }
+
+    let Some((start, end)) = spec.split_once('-') else {
+      return Ranges::Full;
+    };
+    let (start, end) = (start.trim(), end.trim());
+
+    let range = if start.is_empty() {
+      // suffix range, the last `end` bytes
+      let Ok(suffix) = end.parse::<u64>() else {
+        return Ranges::Full;
+      };
+      if suffix == 0 || len == 0 {
+        continue;
+      }
+      ByteRange {
+        start: len.saturating_sub(suffix),
+        end: len - 1,
+      }
+    } else {
+      let Ok(start) = start.parse::<u64>() else {
+        return Ranges::Full;
+      };
+      let end = if end.is_empty() {
+        u64::MAX
+      } else {
+        match end.parse::<u64>() {
+          Ok(end) if end >= start => end,
+          _ => return Ranges::Full,
+        }
+      };
+      if start >= len {
+        continue;
+      }
+      ByteRange {
+        start,
+        end: end.min(len - 1),
+      }
+    };
+    ranges.push(range);
+  }
+
+  if count == 0 {
+    return Ranges::Full;
+  }
+  if ranges.is_empty() {
+    return Ranges::Unsatisfiable;
+  }
+
+  ranges.sort_by_key(|r| r.start);
+  let mut merged: Vec<ByteRange> = Vec::with_capacity(ranges.len());
+  for range in ranges {
+    match merged.last_mut() {
+      Some(last) if range.start <= last.end.saturating_add(1) => {
+        last.end = last.end.max(range.end);
+      }
+      _ => merged.push(range),
+    }
+  }
+  Ranges::Satisfiable(merged)
+}
+
+/// Answers `request` with the part of the response body it asked for.
+///
+/// The response body must be seekable, its length is found by seeking to its end.
+/// `Accept-Ranges` and `Content-Length` are always set, and when the request is a `GET` with a `Range` header:
+///
+/// - a single range is answered with `206 Partial Content` and `Content-Range`,
+/// - multiple ranges are answered with `206 Partial Content` and a `multipart/byteranges` body,
+/// - unsatisfiable ranges are answered with `416 Range Not Satisfiable`.
+///
+/// An `If-Range` header is honored when the response has an `ETag` or `Last-Modified` header,
+/// otherwise the whole content is sent. Responses whose status is not `200 OK` are left untouched,
+/// and `HEAD` requests get an empty body.
+///
+/// The returned body streams from the source, use [`RequestAsyncResponder::respond_with_body`]
+/// to send it or [`super::buffer`] to use it from a synchronous handler.
+///
+/// [`RequestAsyncResponder::respond_with_body`]: crate::RequestAsyncResponder::respond_with_body
+pub fn apply<T, R>(
+  request: &Request<T>,
+  response: Response<R>,
+) -> io::Result<Response<ResponseBody>>
+where
+  R: Read + Seek + Send + 'static,
+{
+  let (mut parts, mut source) = response.into_parts();
+  if parts.status != StatusCode::OK {
+    return Ok(Response::from_parts(
+      parts,
+      ResponseBody::from_reader(source),
+    ));
+  }
+
+  let len = source.seek(SeekFrom::End(0))?;
+  source.seek(SeekFrom::Start(0))?;
+
+  parts
+    .headers
+    .insert(ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+
+  let ranges = match request.headers().get(RANGE).and_then(|r| r.to_str().ok()) {
+    Some(range)
+      if request.method() == Method::GET && if_range_matches(request.headers(), &parts.headers) =>
+    {
+      parse(range, len)
+    }
+    _ => Ranges::Full,
+  };
+
+  let (length, body) = match ranges {
+    Ranges::Full => (len, ResponseBody::from_reader(source)),
+    Ranges::Unsatisfiable => {
+      parts.status = StatusCode::RANGE_NOT_SATISFIABLE;
+      parts
+        .headers
+        .insert(CONTENT_RANGE, content_range(None, len));
+      parts.headers.remove(CONTENT_TYPE);
+      (0, ResponseBody::Bytes(Default::default()))
+    }
+    Ranges::Satisfiable(ranges) if ranges.len() == 1 => {
+      let range = ranges[0];
+      source.seek(SeekFrom::Start(range.start))?;
+      parts.status = StatusCode::PARTIAL_CONTENT;
+      parts
+        .headers
+        .insert(CONTENT_RANGE, content_range(Some(range), len));
+      (
+        range.len(),
+        ResponseBody::from_reader(source.take(range.len())),
+      )
+    }
+    Ranges::Satisfiable(ranges) => {
+      let boundary = boundary();
+      let content_type = parts.headers.get(CONTENT_TYPE).cloned();
+
+      let mut segments = VecDeque::with_capacity(ranges.len() * 2 + 1);
+      for range in ranges {
+        let mut head = format!("\r\n--{boundary}\r\n");
+        if let Some(content_type) = content_type.as_ref().and_then(|c| c.to_str().ok()) {
+          head.push_str(&format!("{CONTENT_TYPE}: {content_type}\r\n"));
+        }
+        head.push_str(&format!(
+          "{CONTENT_RANGE}: bytes {}-{}/{len}\r\n\r\n",
+          range.start, range.end
+        ));
+        segments.push_back(Segment::Bytes(Cursor::new(head.into_bytes())));
+        segments.push_back(Segment::Range { range, read: None });
+      }
+      segments.push_back(Segment::Bytes(Cursor::new(
+        format!("\r\n--{boundary}--\r\n").into_bytes(),
+      )));
+
+      let length = segments.iter().map(Segment::len).sum();
+
+      parts.status = StatusCode::PARTIAL_CONTENT;
+      parts.headers.insert(
+        CONTENT_TYPE,
+        HeaderValue::from_str(&format!("multipart/byteranges; boundary={boundary}")).unwrap(),
+      );
+      (
+        length,
+        ResponseBody::from_reader(Multipart { source, segments }),
+      )
+    }
+  };
+
+  parts.headers.insert(CONTENT_LENGTH, length.into());
+
+  let body = if request.method() == Method::HEAD {
+    ResponseBody::Bytes(Default::default())
+  } else {
+    body
+  };
+
+  Ok(Response::from_parts(parts, body))
+}
+
+/// Whether the `If-Range` precondition, if any, holds for a response with the given headers.
+fn if_range_matches(request: &HeaderMap, response: &HeaderMap) -> bool {
+  let Some(if_range) = request.get(IF_RANGE) else {
+    return true;
+  };
+
+  let if_range = if_range.as_bytes();
+  if if_range.starts_with(b"\"") {
+    // only strong entity tags can be used
+    response.get(ETAG).map(|e| e.as_bytes()) == Some(if_range)
+  } else {
+    response.get(LAST_MODIFIED).map(|l| l.as_bytes()) == Some(if_range)
+  }
+}
+
+fn content_range(range: Option<ByteRange>, len: u64) -> HeaderValue {
+  let value = match range {
+    Some(range) => format!("bytes {}-{}/{len}", range.start, range.end),
+    None => format!("bytes */{len}"),
+  };
+  HeaderValue::from_str(&value).unwrap()
+}
+
+/// A random multipart boundary, very unlikely to appear in the content.
+fn boundary() -> String {
+  let random = || RandomState::new().build_hasher().finish();
+  format!("{:016x}{:016x}", random(), random())
+}
+
+enum Segment {
+  Bytes(Cursor<Vec<u8>>),
+  Range {
+    range: ByteRange,
+    /// How many bytes were read, `None` until the source is positioned at the range start.
+    read: Option<u64>,
+  },
+}
+
+impl Segment {
+  fn len(&self) -> u64 {
+    match self {
+      Self::Bytes(bytes) => bytes.get_ref().len() as u64,
+      Self::Range { range, .. } => range.len(),
+    }
+  }
+}
+
+/// Streams a `multipart/byteranges` body, seeking the source to each range in turn.
+struct Multipart<R> {
+  source: R,
+  segments: VecDeque<Segment>,
+}
+
+impl<R: Read + Seek> Read for Multipart<R> {
+  fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+    if buf.is_empty() {
+      return Ok(0);
+    }
+
+    while let Some(segment) = self.segments.front_mut() {
+      let count = match segment {
+        Segment::Bytes(bytes) => bytes.read(buf)?,
+        Segment::Range { range, read } => {
+          let done = match read {
+            Some(done) => *done,
+            None => {
+              self.source.seek(SeekFrom::Start(range.start))?;
+              *read = Some(0);
+              0
+            }
+          };
+          let wanted = (range.len() - done).min(buf.len() as u64) as usize;
+          if wanted == 0 {
+            0
+          } else {
+            let count = self.source.read(&mut buf[..wanted])?;
+            if count == 0 {
+              return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            *read = Some(done + count as u64);
+            count
+          }
+        }
+      };
+
+      if count > 0 {
+        return Ok(count);
+      }
+      self.segments.pop_front();
+    }
+
+    Ok(0)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn range(start: u64, end: u64) -> ByteRange {
+    ByteRange { start, end }
+  }
+
+  #[test]
+  fn parses_ranges() {
+    assert_eq!(
+      parse("bytes=0-499", 1000),
+      Ranges::Satisfiable(vec![range(0, 499)])
+    );
+    assert_eq!(
+      parse("bytes=500-", 1000),
+      Ranges::Satisfiable(vec![range(500, 999)])
+    );
+    assert_eq!(
+      parse("bytes=-200", 1000),
+      Ranges::Satisfiable(vec![range(800, 999)])
+    );
+    assert_eq!(
+      parse("bytes=-2000", 1000),
+      Ranges::Satisfiable(vec![range(0, 999)])
+    );
+    assert_eq!(
+      parse("bytes=900-5000", 1000),
+      Ranges::Satisfiable(vec![range(900, 999)])
+    );
+    assert_eq!(
+      parse("bytes=500-600, 0-10, 550-700", 1000),
+      Ranges::Satisfiable(vec![range(0, 10), range(500, 700)])
+    );
+    assert_eq!(parse("bytes=1000-", 1000), Ranges::Unsatisfiable);
+    assert_eq!(parse("bytes=-0", 1000), Ranges::Unsatisfiable);
+    assert_eq!(parse("bytes=5-1", 1000), Ranges::Full);
+    assert_eq!(parse("items=0-1", 1000), Ranges::Full);
+    assert_eq!(parse("bytes=abc", 1000), Ranges::Full);
+  }
+
+  fn get(range: &str) -> Request<()> {
+    Request::get("app://localhost/video.mp4")
+      .header(RANGE, range)
+      .body(())
+      .unwrap()
+  }
+
+  fn source() -> Response<Cursor<Vec<u8>>> {
+    Response::builder()
+      .header(CONTENT_TYPE, "text/plain")
+      .body(Cursor::new(b"0123456789".to_vec()))
+      .unwrap()
+  }
+
+  fn read(response: Response<ResponseBody>) -> (http::response::Parts, String) {
+    let (parts, body) = response.into_parts();
+    let body = body.into_bytes().unwrap();
+    (parts, String::from_utf8(body.into_owned()).unwrap())
+  }
+
+  #[test]
+  fn single_range() {
+    let (parts, body) = read(apply(&get("bytes=2-4"), source()).unwrap());
+    assert_eq!(parts.status, StatusCode::PARTIAL_CONTENT);
+    assert_eq!(parts.headers[CONTENT_RANGE], "bytes 2-4/10");
+    assert_eq!(parts.headers[CONTENT_LENGTH], "3");
+    assert_eq!(body, "234");
+  }
+
+  #[test]
+  fn multiple_ranges() {
+    let (parts, body) = read(apply(&get("bytes=0-1,-2"), source()).unwrap());
+    assert_eq!(parts.status, StatusCode::PARTIAL_CONTENT);
+    let content_type = parts.headers[CONTENT_TYPE].to_str().unwrap();
+    let boundary = content_type
+      .strip_prefix("multipart/byteranges; boundary=")
+      .unwrap();
+    assert_eq!(
+      body,
+      format!(
+        "\r\n--{boundary}\r\ncontent-type: text/plain\r\ncontent-range: bytes 0-1/10\r\n\r\n01\
+         \r\n--{boundary}\r\ncontent-type: text/plain\r\ncontent-range: bytes 8-9/10\r\n\r\n89\
+         \r\n--{boundary}--\r\n"
+      )
+    );
+    assert_eq!(parts.headers[CONTENT_LENGTH], body.len().to_string());
+  }
+
+  #[test]
+  fn unsatisfiable_and_full() {
+    let (parts, body) = read(apply(&get("bytes=20-"), source()).unwrap());
+    assert_eq!(parts.status, StatusCode::RANGE_NOT_SATISFIABLE);
+    assert_eq!(parts.headers[CONTENT_RANGE], "bytes */10");
+    assert!(body.is_empty());
+
+    let request = Request::get("app://localhost/").body(()).unwrap();
+    let (parts, body) = read(apply(&request, source()).unwrap());
+    assert_eq!(parts.status, StatusCode::OK);
+    assert_eq!(parts.headers[ACCEPT_RANGES], "bytes");
+    assert_eq!(body, "0123456789");
+  }
+}
@@ -0,0 +1,648 @@
+// Copyright 2020-2024 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+use std::{
+  borrow::Cow,
+  io::{self, BufRead, BufReader, Cursor, Read, Write},
+  net::{TcpStream, ToSocketAddrs},
+  time::Duration,
+};
+
+use http::{
+  header::{CONNECTION, CONTENT_LENGTH, HOST, LOCATION, ORIGIN, REFERER, TRANSFER_ENCODING},
+  uri::{Authority, Scheme},
+  HeaderMap, HeaderName, HeaderValue, Method, Request, Response, StatusCode, Uri, Version,
+};
+
+use super::{buffer, status_response};
+use crate::{custom_protocol_url::UrlStyle, ResponseBody};
+
+/// The largest response head accepted from the upstream server.
+const MAX_HEAD_LEN: usize = 64 * 1024;
+
+/// How many bytes of the request body are sent to the upstream server at once.
+const CHUNK_LEN: usize = 64 * 1024;
+
+/// The default timeout for connecting to, writing to and reading from the upstream server.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Headers only meaningful for a single connection, which are not forwarded.
+const HOP_BY_HOP: [&str; 8] = [
+  "connection",
+  "keep-alive",
+  "proxy-authenticate",
+  "proxy-authorization",
+  "proxy-connection",
+  "te",
+  "trailer",
+  "upgrade",
+];
+
+/// Forwards custom protocol requests to a local HTTP server, such as a frontend dev server.
+///
+/// The page keeps the custom protocol origin, so development and production builds share
+/// one origin, cookies and storage. Requests are sent over HTTP/1.1 with one connection each:
+///
+/// - `Host` is set to the upstream authority and hop-by-hop headers are dropped,
+/// - `Origin` and `Referer` pointing to the custom protocol origin are rewritten to the upstream origin,
+///   and `X-Forwarded-Host` and `X-Forwarded-Proto` carry the original ones,
+/// - request bodies are streamed, with chunked transfer encoding when their length is unknown.
+///
+/// Responses keep their status code and headers, except hop-by-hop headers, and `Location` headers
+/// pointing to the upstream origin are rewritten to the custom protocol origin. Their body is streamed
+/// from the upstream connection. Upstream failures are answered with `502 Bad Gateway`.
+///
+/// On Windows and Android, pages see the custom protocol origin as `http://<scheme>.<host>`,
+/// or `https://<scheme>.<host>` with `ReverseProxy::with_https_scheme`.
+///
+/// Only `http` upstreams are supported.
+///
+/// Forwarding blocks the calling thread until the upstream server answers, or the [timeout](Self::with_timeout)
+/// of 30 seconds by default elapses. Do not forward from the thread the webview runs on, as synchronous
+/// [`WebViewBuilder::with_custom_protocol`](crate::WebViewBuilder::with_custom_protocol) handlers do,
+/// but from [`WebViewBuilder::with_async_custom_protocol`](crate::WebViewBuilder::with_async_custom_protocol)
+/// with a [`ThreadSpawner`](super::ThreadSpawner), or another thread.
+///
+/// # Examples
+///
+/// ```no_run
+/// use wry::{
+///   custom_protocol::{ReverseProxy, ThreadSpawner},
+///   WebViewBuilder,
+/// };
+///
+/// let dev_server = ReverseProxy::new("http://localhost:5173")?;
+/// let builder = WebViewBuilder::new()
+///   .with_async_custom_protocol("app".into(), ThreadSpawner, move |_webview_id, request| {
+///     let dev_server = dev_server.clone();
+///     async move { dev_server.serve(request.map(std::io::Cursor::new)) }
+///   })
+///   .with_url("app://localhost");
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct ReverseProxy {
+  authority: Authority,
+  base_path: String,
+  timeout: Duration,
+  url_style: UrlStyle,
+}
+
+impl ReverseProxy {
+  /// Creates a proxy forwarding requests to `upstream`, such as `http://localhost:5173`.
+  ///
+  /// The path of `upstream`, if any, is prepended to the forwarded request paths.
+  /// Fails with [`io::ErrorKind::InvalidInput`] if `upstream` is not an `http` URL.
+  pub fn new(upstream: &str) -> io::Result<Self> {
+    let uri: Uri = upstream
+      .parse()
+      .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    if uri.scheme() != Some(&Scheme::HTTP) {
+      return Err(io::Error::new(
+        io::ErrorKind::InvalidInput,
+        "only http upstreams are supported",
+      ));
+    }
+    let authority = uri
+      .authority()
+      .cloned()
+      .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "upstream has no host"))?;
+
+    Ok(Self {
+      authority,
+      base_path: uri.path().trim_end_matches('/').to_string(),
+      timeout: DEFAULT_TIMEOUT,
+      #[cfg(any(windows, target_os = "android"))]
+      url_style: UrlStyle::workaround(false),
+      #[cfg(not(any(windows, target_os = "android")))]
+      url_style: UrlStyle::Custom,
+    })
+  }
+
+  /// Sets the timeout for connecting to, writing to and reading from the upstream server, 30 seconds by default.
+  pub fn with_timeout(mut self, timeout: Duration) -> Self {
+    self.timeout = timeout;
+    self
+  }
+
+  /// Whether the webview uses `https://<scheme>.<host>` instead of `http://<scheme>.<host>` for custom protocols,
+  /// which must match the `with_https_scheme` setting of the webview. Defaults to `false`.
+  #[cfg(any(windows, target_os = "android"))]
+  pub fn with_https_scheme(mut self, enabled: bool) -> Self {
+    self.url_style = UrlStyle::workaround(enabled);
+    self
+  }
+
+  /// The origin requests are forwarded to.
+  pub fn upstream_origin(&self) -> String {
+    format!("http://{}", self.authority)
+  }
+
+  /// Forwards the request and answers with the upstream response, read into memory.
+  ///
+  /// This blocks until the whole response is read, see the [type level documentation](Self)
+  /// for where to call it and [`Self::serve`] for more information.
+  pub fn handle(&self, request: Request<Vec<u8>>) -> Response<Cow<'static, [u8]>> {
+    buffer(self.serve(request.map(Cursor::new)))
+      .unwrap_or_else(|_| status_response(StatusCode::BAD_GATEWAY))
+  }
+
+  /// Forwards the request and answers with the upstream response, streamed from the upstream connection.
+  ///
+  /// Upstream failures are answered with `502 Bad Gateway`.
+  pub fn serve<B: Read>(&self, request: Request<B>) -> Response<ResponseBody> {
+    self.forward(request).unwrap_or_else(|_e| {
+      #[cfg(feature = "tracing")]
+      tracing::warn!("failed to forward custom protocol request: {_e}");
+      status_response(StatusCode::BAD_GATEWAY).map(Into::into)
+    })
+  }
+
+  /// Forwards the request and returns the upstream response, failing on I/O and protocol errors.
+  pub fn forward<B: Read>(&self, request: Request<B>) -> io::Result<Response<ResponseBody>> {
+    let (parts, mut body) = request.into_parts();
+    let request_origin = self.page_origin(&parts.uri);
+    let upstream_origin = self.upstream_origin();
+
+    let mut stream = self.connect()?;
+
+    // request head
+    let path = parts
+      .uri
+      .path_and_query()
+      .map(|p| p.as_str())
+      .unwrap_or("/");
+    let mut head = format!("{} {}{path} HTTP/1.1\r\n", parts.method, self.base_path);
+    let mut headers = parts.headers;
+    remove_hop_by_hop(&mut headers);
+    headers.remove(HOST);
+    rewrite_origin(
+      &mut headers,
+      ORIGIN,
+      request_origin.as_deref(),
+      &upstream_origin,
+    );
+    rewrite_origin(
+      &mut headers,
+      REFERER,
+      request_origin.as_deref(),
+      &upstream_origin,
+    );
+    if let Some(authority) = parts.uri.authority() {
+      headers.insert(
+        HeaderName::from_static("x-forwarded-host"),
+        HeaderValue::from_str(authority.as_str()).map_err(invalid_data)?,
+      );
+    }
+    if let Some(scheme) = parts.uri.scheme_str() {
+      headers.insert(
+        HeaderName::from_static("x-forwarded-proto"),
+        HeaderValue::from_str(scheme).map_err(invalid_data)?,
+      );
+    }
+
+    head.push_str(&format!(
+      "host: {}\r\nconnection: close\r\n",
+      self.authority
+    ));
+
+    // the length is only known upfront if announced, otherwise the body is sent in chunks
+    let mut chunk = vec![0; CHUNK_LEN];
+    let first = read_some(&mut body, &mut chunk)?;
+    let chunked = !headers.contains_key(CONTENT_LENGTH) && first > 0;
+    if chunked {
+      head.push_str("transfer-encoding: chunked\r\n");
+    } else if first == 0
+      && !headers.contains_key(CONTENT_LENGTH)
+      && parts.method != Method::GET
+      && parts.method != Method::HEAD
+    {
+      head.push_str("content-length: 0\r\n");
+    }
+
+    let mut head = head.into_bytes();
+    for (name, value) in &headers {
+      head.extend_from_slice(name.as_str().as_bytes());
+      head.extend_from_slice(b": ");
+      head.extend_from_slice(value.as_bytes());
+      head.extend_from_slice(b"\r\n");
+    }
+    head.extend_from_slice(b"\r\n");
+    stream.write_all(&head)?;
+
+    // request body
+    let mut count = first;
+    while count > 0 {
+      if chunked {
+        write!(stream, "{count:x}\r\n")?;
+        stream.write_all(&chunk[..count])?;
+        stream.write_all(b"\r\n")?;
+      } else {
+        stream.write_all(&chunk[..count])?;
+      }
+      count = read_some(&mut body, &mut chunk)?;
+    }
+    if chunked {
+      stream.write_all(b"0\r\n\r\n")?;
+    }
+    stream.flush()?;
+
+    // response head
+    let mut reader = BufReader::new(stream);
+    let mut head_len = 0;
+    let status_line = read_line(&mut reader, &mut head_len)?;
+    let mut status_parts = status_line.splitn(3, ' ');
+    let version = match status_parts.next() {
+      Some("HTTP/1.1") => Version::HTTP_11,
+      Some("HTTP/1.0") => Version::HTTP_10,
+      _ => return Err(invalid_data("invalid upstream status line")),
+    };
+    let status = status_parts
+      .next()
+      .and_then(|status| StatusCode::from_bytes(status.as_bytes()).ok())
+      .ok_or_else(|| invalid_data("invalid upstream status code"))?;
+
+    let mut response = Response::builder().status(status).version(version);
+    let response_headers = response.headers_mut().unwrap();
+    loop {
+      let line = read_line(&mut reader, &mut head_len)?;
+      if line.is_empty() {
+        break;
+      }
+      let (name, value) = line
+        .split_once(':')
+        .ok_or_else(|| invalid_data("invalid upstream header"))?;
+      response_headers.append(
+        HeaderName::from_bytes(name.trim().as_bytes()).map_err(invalid_data)?,
+        HeaderValue::from_str(value.trim()).map_err(invalid_data)?,
+      );
+    }
+
+    // response body
+    let chunked = response_headers
+      .get(TRANSFER_ENCODING)
+      .and_then(|v| v.to_str().ok())
+      .is_some_and(|v| v.to_ascii_lowercase().contains("chunked"));
+    let length = response_headers
+      .get(CONTENT_LENGTH)
+      .and_then(|v| v.to_str().ok())
+      .and_then(|v| v.parse::<u64>().ok());
+    let bodyless = parts.method == Method::HEAD
+      || status.is_informational()
+      || status == StatusCode::NO_CONTENT
+      || status == StatusCode::NOT_MODIFIED;
+
+    remove_hop_by_hop(response_headers);
+    response_headers.remove(TRANSFER_ENCODING);
+    if let Some(request_origin) = &request_origin {
+      rewrite_origin(
+        response_headers,
+        LOCATION,
+        Some(&upstream_origin),
+        request_origin,
+      );
+    }
+
+    let body = if bodyless {
+      ResponseBody::from(Vec::new())
+    } else if chunked {
+      ResponseBody::from_reader(ChunkedReader {
+        inner: reader,
+        remaining: 0,
+        done: false,
+      })
+    } else if let Some(length) = length {
+      ResponseBody::from_reader(reader.take(length))
+    } else {
+      // the connection is closed once the body is sent
+      ResponseBody::from_reader(reader)
+    };
+
+    response.body(body).map_err(invalid_data)
+  }
+
+  fn connect(&self) -> io::Result<TcpStream> {
+    let port = self.authority.port_u16().unwrap_or(80);
+    let addrs = (self.authority.host(), port).to_socket_addrs()?;
+
+    let mut last_error = io::Error::new(io::ErrorKind::NotFound, "upstream host not found");
+    for addr in addrs {
+      match TcpStream::connect_timeout(&addr, self.timeout) {
+        Ok(stream) => {
+          stream.set_read_timeout(Some(self.timeout))?;
+          stream.set_write_timeout(Some(self.timeout))?;
+          return Ok(stream);
+        }
+        Err(e) => last_error = e,
+      }
+    }
+    Err(last_error)
+  }
+
+  /// The origin of the page requesting `uri`, in the form the page sees custom protocols.
+  fn page_origin(&self, uri: &Uri) -> Option<String> {
+    let protocol = uri.scheme_str()?;
+    let authority = uri.authority()?;
+    Some(match self.url_style.scheme() {
+      None => format!("{protocol}://{authority}"),
+      Some(scheme) => format!("{scheme}://{protocol}.{authority}"),
+    })
+  }
+}
+
+/// Decodes a body sent with chunked transfer encoding.
+struct ChunkedReader<R> {
+  inner: R,
+  remaining: u64,
+  done: bool,
+}
+
+impl<R: BufRead> Read for ChunkedReader<R> {
+  fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+    if self.done || buf.is_empty() {
+      return Ok(0);
+    }
+
+    if self.remaining == 0 {
+      let mut head_len = 0;
+      let line = read_line(&mut self.inner, &mut head_len)?;
+      let size = line.split(';').next().unwrap_or_default().trim();
+      self.remaining = u64::from_str_radix(size, 16).map_err(invalid_data)?;
+      if self.remaining == 0 {
+        // skip the trailers
+        while !read_line(&mut self.inner, &mut head_len)?.is_empty() {}
+        self.done = true;
+        return Ok(0);
+      }
+    }
+
+    let max = buf
+      .len()
+      .min(self.remaining.try_into().unwrap_or(usize::MAX));
+    let count = self.inner.read(&mut buf[..max])?;
+    if count == 0 {
+      return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    self.remaining -= count as u64;
+    if self.remaining == 0 {
+      let mut crlf = [0; 2];
+      self.inner.read_exact(&mut crlf)?;
+    }
+    Ok(count)
+  }
+}
+
+/// Reads until `buf` is full or the reader is exhausted.
+fn read_some<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+  let mut count = 0;
+  while count < buf.len() {
+    match reader.read(&mut buf[count..]) {
+      Ok(0) => break,
+      Ok(n) => count += n,
+      Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+      Err(e) => return Err(e),
+    }
+  }
+  Ok(count)
+}
+
+/// Reads a line without its line ending, enforcing [`MAX_HEAD_LEN`] on the whole head.
+fn read_line<R: BufRead>(reader: &mut R, head_len: &mut usize) -> io::Result<String> {
+  let mut line = Vec::new();
+  let limit = (MAX_HEAD_LEN - *head_len) as u64;
+  let count = reader.take(limit).read_until(b'\n', &mut line)?;
+  *head_len += count;
+  if !line.ends_with(b"\n") {
+    return Err(if count as u64 == limit {
+      invalid_data("upstream response head is too large")
+    } else {
+      io::ErrorKind::UnexpectedEof.into()
+    });
+  }
+  let line = String::from_utf8(line).map_err(invalid_data)?;
+  Ok(line.trim_end_matches(['\r', '\n']).to_string())
+}
+
+fn remove_hop_by_hop(headers: &mut HeaderMap) {
+  let listed = headers
+    .get_all(CONNECTION)
+    .iter()
+    .filter_map(|v| v.to_str().ok())
+    .flat_map(|v| v.split(','))
+    .filter_map(|name| HeaderName::from_bytes(name.trim().as_bytes()).ok())
+    .collect::<Vec<_>>();
+  for name in listed {
+    headers.remove(name);
+  }
+  for name in HOP_BY_HOP {
+    headers.remove(name);
+  }
+}
+
+/// Replaces the `from` origin with `to` at the start of the `name` header.
+fn rewrite_origin(headers: &mut HeaderMap, name: HeaderName, from: Option<&str>, to: &str) {
+  let Some(from) = from else {
+    return;
+  };
+  let rewritten = headers
+    .get(&name)
+    .and_then(|v| v.to_str().ok())
+    .and_then(|v| v.strip_prefix(from))
+    .filter(|rest| rest.is_empty() || rest.starts_with(['/', '?', '#']))
+    .and_then(|rest| HeaderValue::from_str(&format!("{to}{rest}")).ok());
+  if let Some(value) = rewritten {
+    headers.insert(name, value);
+  }
+}
+
+fn invalid_data<E: Into<Box<dyn std::error::Error + Send + Sync>>>(error: E) -> io::Error {
+  io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+#[cfg(test)]
+mod tests {
+  use std::{net::TcpListener, thread};
+
+  use super::*;
+
+  /// Answers a single connection with `response` and returns the raw request it received.
+  fn upstream(response: &'static [u8]) -> (ReverseProxy, thread::JoinHandle<String>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let handle = thread::spawn(move || {
+      let (stream, _) = listener.accept().unwrap();
+      let mut reader = BufReader::new(stream);
+      let mut request = String::new();
+      let mut chunked = false;
+      let mut length = 0;
+      loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        let lower = line.to_ascii_lowercase();
+        chunked |= lower == "transfer-encoding: chunked\r\n";
+        if let Some(value) = lower.strip_prefix("content-length: ") {
+          length = value.trim().parse().unwrap();
+        }
+        request.push_str(&line);
+        if line == "\r\n" {
+          break;
+        }
+      }
+      let mut body = Vec::new();
+      if chunked {
+        ChunkedReader {
+          inner: &mut reader,
+          remaining: 0,
+          done: false,
+        }
+        .read_to_end(&mut body)
+        .unwrap();
+      } else {
+        body.resize(length, 0);
+        reader.read_exact(&mut body).unwrap();
+      }
+      request.push_str(&String::from_utf8(body).unwrap());
+      reader.into_inner().write_all(response).unwrap();
+      request
+    });
+
+    let proxy = ReverseProxy::new(&format!("http://{address}/base/"))
+      .unwrap()
+      .with_timeout(Duration::from_secs(5));
+    (proxy, handle)
+  }
+
+  #[test]
+  fn forwards_requests() {
+    let (proxy, upstream) = upstream(
+      b"HTTP/1.1 201 Created\r\ncontent-type: text/plain\r\ncontent-length: 5\r\nkeep-alive: timeout=5\r\n\r\nhello",
+    );
+
+    let request = Request::post("app://localhost/api?x=1")
+      .header(ORIGIN, "app://localhost")
+      .header(REFERER, "app://localhost/index.html")
+      .header(CONNECTION, "x-secret")
+      .header("x-secret", "1")
+      .header(CONTENT_LENGTH, "4")
+      .body(b"ping".to_vec())
+      .unwrap();
+    let response = proxy.handle(request);
+
+    let request = upstream.join().unwrap();
+    let upstream_origin = proxy.upstream_origin();
+    assert!(
+      request.starts_with("POST /base/api?x=1 HTTP/1.1\r\n"),
+      "{request}"
+    );
+    assert!(request.contains(&format!("host: {}\r\n", &upstream_origin[7..])));
+    assert!(request.contains(&format!("origin: {upstream_origin}\r\n")));
+    assert!(request.contains(&format!("referer: {upstream_origin}/index.html\r\n")));
+    assert!(request.contains("x-forwarded-host: localhost\r\n"));
+    assert!(request.contains("x-forwarded-proto: app\r\n"));
+    assert!(!request.contains("x-secret"));
+    assert!(request.ends_with("\r\n\r\nping"));
+
+    assert_eq!(response.status(), StatusCode::CREATED);
+    assert_eq!(response.headers()["content-type"], "text/plain");
+    assert!(!response.headers().contains_key("keep-alive"));
+    assert_eq!(&response.body()[..], b"hello");
+  }
+
+  #[test]
+  fn streams_chunked_bodies() {
+    let (proxy, upstream) = upstream(
+      b"HTTP/1.1 302 Found\r\nlocation: /next\r\ntransfer-encoding: chunked\r\n\r\n5\r\nhello\r\n7;ext=1\r\n, world\r\n0\r\n\r\n",
+    );
+
+    // a reader of unknown length is sent in chunks
+    let request = Request::put("app://localhost/upload")
+      .body(Cursor::new(b"streamed body".to_vec()))
+      .unwrap();
+    let response = buffer(proxy.serve(request)).unwrap();
+
+    let request = upstream.join().unwrap();
+    assert!(
+      request.contains("transfer-encoding: chunked\r\n"),
+      "{request}"
+    );
+    assert!(request.ends_with("\r\n\r\nstreamed body"));
+
+    assert_eq!(response.status(), StatusCode::FOUND);
+    assert_eq!(response.headers()[LOCATION], "/next");
+    assert!(!response.headers().contains_key(TRANSFER_ENCODING));
+    assert_eq!(&response.body()[..], b"hello, world");
+  }
+
+  #[test]
+  fn rewrites_workaround_origins() {
+    let (mut proxy, upstream) = upstream(b"HTTP/1.1 204 No Content\r\n\r\n");
+    proxy.url_style = UrlStyle::Http;
+
+    // Windows and Android hand the request to the handler as `app://`, the page uses `http://app.`
+    let uri = "app://localhost/api";
+    let request = Request::get(uri)
+      .header(ORIGIN, "http://app.localhost")
+      .header(REFERER, "http://app.localhost/index.html")
+      .body(Vec::new())
+      .unwrap();
+    let response = proxy.handle(request);
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+    let request = upstream.join().unwrap();
+    let upstream_origin = proxy.upstream_origin();
+    assert!(
+      request.contains(&format!("origin: {upstream_origin}\r\n")),
+      "{request}"
+    );
+    assert!(request.contains(&format!("referer: {upstream_origin}/index.html\r\n")));
+
+    // `Location` headers are rewritten to the same origin
+    assert_eq!(
+      proxy.page_origin(&uri.parse().unwrap()).as_deref(),
+      Some("http://app.localhost")
+    );
+    proxy.url_style = UrlStyle::Https;
+    assert_eq!(
+      proxy.page_origin(&uri.parse().unwrap()).as_deref(),
+      Some("https://app.localhost")
+    );
+  }
+
+  #[test]
+  fn rewrites_locations() {
+    let mut headers = HeaderMap::new();
+    headers.insert(LOCATION, "http://127.0.0.1:5173/next".parse().unwrap());
+    rewrite_origin(
+      &mut headers,
+      LOCATION,
+      Some("http://127.0.0.1:5173"),
+      "app://localhost",
+    );
+    assert_eq!(headers[LOCATION], "app://localhost/next");
+
+    // other hosts sharing a prefix are left untouched
+    headers.insert(LOCATION, "http://127.0.0.1:51730/next".parse().unwrap());
+    rewrite_origin(
+      &mut headers,
+      LOCATION,
+      Some("http://127.0.0.1:5173"),
+      "app://localhost",
+    );
+    assert_eq!(headers[LOCATION], "http://127.0.0.1:51730/next");
+  }
+
+  #[test]
+  fn answers_bad_gateway() {
+    // nothing listens on this port once the listener is dropped
+    let address = TcpListener::bind("127.0.0.1:0")
+      .unwrap()
+      .local_addr()
+      .unwrap();
+    let proxy = ReverseProxy::new(&format!("http://{address}")).unwrap();
+    let response = proxy.handle(Request::get("app://localhost/").body(Vec::new()).unwrap());
+    assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+
+    assert!(ReverseProxy::new("https://localhost").is_err());
+  }
+}
@@ -0,0 +1,138 @@
+// Copyright 2020-2024 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+use std::{
+  future::Future,
+  pin::{pin, Pin},
+  sync::Arc,
+  task::{Context, Poll, Wake, Waker},
+  thread::{self, Thread},
+};
+
+/// A future spawned by [`WebViewBuilder::with_async_custom_protocol`](crate::WebViewBuilder::with_async_custom_protocol).
+pub type SpawnedFuture = Pin<Box<dyn Future<Output = ()> + Send + 'static>>;
+
+/// Runs the futures of [`WebViewBuilder::with_async_custom_protocol`](crate::WebViewBuilder::with_async_custom_protocol) handlers.
+///
+/// Implemented for closures, so any executor can be plugged in:
+///
+/// ```ignore
+/// let runtime = tokio::runtime::Handle::current();
+/// let spawner = move |future: SpawnedFuture| {
+///   runtime.spawn(future);
+/// };
+/// ```
+///
+/// [`ThreadSpawner`] is a lightweight built-in alternative.
+pub trait Spawner: 'static {
+  /// Runs `future` to completion in the background.
+  fn spawn(&self, future: SpawnedFuture);
+}
+
+impl<F> Spawner for F
+where
+  F: Fn(SpawnedFuture) + 'static,
+{
+  fn spawn(&self, future: SpawnedFuture) {
+    self(future)
+  }
+}
+
+/// Runs each future to completion on its own thread, parking the thread while the future is pending.
+///
+/// Suited to futures that are mostly waiting on I/O or other threads, and do not rely on the
+/// reactor of a specific runtime such as tokio.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ThreadSpawner;
+
+impl Spawner for ThreadSpawner {
+  fn spawn(&self, future: SpawnedFuture) {
+    thread::Builder::new()
+      .name("wry-custom-protocol".into())
+      .spawn(move || block_on(future))
+      .expect("failed to spawn custom protocol thread");
+  }
+}
+
+struct ThreadWaker(Thread);
+
+impl Wake for ThreadWaker {
+  fn wake(self: Arc<Self>) {
+    self.0.unpark();
+  }
+
+  fn wake_by_ref(self: &Arc<Self>) {
+    self.0.unpark();
+  }
+}
+
+/// Polls `future` on the current thread until it completes.
+pub(crate) fn block_on<F: Future>(future: F) -> F::Output {
+  let mut future = pin!(future);
+  let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+  let mut context = Context::from_waker(&waker);
+  loop {
+    match future.as_mut().poll(&mut context) {
+      Poll::Ready(output) => return output,
+      // spurious wake ups just poll the future once more
+      Poll::Pending => thread::park(),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::{
+    sync::{mpsc::channel, Mutex},
+    time::Duration,
+  };
+
+  use super::*;
+
+  /// Completes once another thread sets its value.
+  struct Delayed(Arc<Mutex<(Option<u32>, Option<Waker>)>>);
+
+  impl Future for Delayed {
+    type Output = u32;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<u32> {
+      let mut state = self.0.lock().unwrap();
+      match state.0 {
+        Some(value) => Poll::Ready(value),
+        None => {
+          state.1 = Some(cx.waker().clone());
+          Poll::Pending
+        }
+      }
+    }
+  }
+
+  #[test]
+  fn thread_spawner_completes_futures() {
+    let shared = Arc::new(Mutex::new((None, None)));
+    let delayed = Delayed(shared.clone());
+    thread::spawn(move || {
+      thread::sleep(Duration::from_millis(10));
+      let mut state = shared.lock().unwrap();
+      state.0 = Some(42);
+      if let Some(waker) = state.1.take() {
+        waker.wake();
+      }
+    });
+
+    let (sender, receiver) = channel();
+    ThreadSpawner.spawn(Box::pin(async move {
+      sender.send(delayed.await).unwrap();
+    }));
+    assert_eq!(receiver.recv_timeout(Duration::from_secs(5)), Ok(42));
+  }
+
+  #[test]
+  fn closures_are_spawners() {
+    let spawner = |future: SpawnedFuture| block_on(future);
+    let (sender, receiver) = channel();
+    spawner.spawn(Box::pin(async move { sender.send(1).unwrap() }));
+    assert_eq!(receiver.try_recv(), Ok(1));
+  }
+}
@@ -0,0 +1,146 @@
+// Copyright 2020-2024 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+//! Custom protocols backed by [`tower`](https://docs.rs/tower) services.
+//!
+//! See [`WebViewBuilder::with_tower_custom_protocol`](crate::WebViewBuilder::with_tower_custom_protocol).
+
+use std::future::poll_fn;
+
+use bytes::Bytes;
+use http::{Request, Response, StatusCode};
+use http_body::Body;
+use http_body_util::{BodyExt, Full};
+use tower_service::Service;
+
+use super::status_response;
+use crate::ResponseBody;
+
+/// The error type services and response bodies can fail with.
+pub type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Request extension holding the id of the webview that made the request.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct WebViewIdExtension(pub String);
+
+/// Calls `service` with a custom protocol request, once it is ready, and collects the response body.
+///
+/// Service and response body errors are answered with `500 Internal Server Error`.
+pub async fn call<S, B>(mut service: S, request: Request<Full<Bytes>>) -> Response<ResponseBody>
+where
+  S: Service<Request<Full<Bytes>>, Response = Response<B>>,
+  S::Error: Into<BoxError>,
+  B: Body,
+  B::Error: Into<BoxError>,
+{
+  let result = async {
+    poll_fn(|cx| service.poll_ready(cx))
+      .await
+      .map_err(Into::into)?;
+    let (parts, body) = service
+      .call(request)
+      .await
+      .map_err(Into::into)?
+      .into_parts();
+    let body = body.collect().await.map_err(Into::into)?.to_bytes();
+    Ok::<_, BoxError>(Response::from_parts(parts, Vec::from(body).into()))
+  };
+
+  result.await.unwrap_or_else(|_e| {
+    #[cfg(feature = "tracing")]
+    tracing::error!("custom protocol service failed: {_e}");
+    status_response(StatusCode::INTERNAL_SERVER_ERROR).map(Into::into)
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use std::{
+    convert::Infallible,
+    future::{ready, Ready},
+    task::{Context, Poll},
+  };
+
+  use super::*;
+  use crate::custom_protocol::spawn::block_on;
+
+  #[derive(Clone)]
+  struct Echo;
+
+  impl Service<Request<Full<Bytes>>> for Echo {
+    type Response = Response<Full<Bytes>>;
+    type Error = Infallible;
+    type Future = std::pin::Pin<
+      Box<dyn std::future::Future<Output = Result<Self::Response, Infallible>> + Send>,
+    >;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+      Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: Request<Full<Bytes>>) -> Self::Future {
+      Box::pin(async move {
+        let webview_id = request
+          .extensions()
+          .get::<WebViewIdExtension>()
+          .map(|id| id.0.clone())
+          .unwrap_or_default();
+        let summary = format!(
+          "{} {} from {webview_id}: ",
+          request.method(),
+          request.uri().path()
+        );
+        let body = request.into_body().collect().await?.to_bytes();
+        Ok(
+          Response::builder()
+            .status(StatusCode::CREATED)
+            .body(Full::new([summary.as_bytes(), &body].concat().into()))
+            .unwrap(),
+        )
+      })
+    }
+  }
+
+  struct Failing;
+
+  impl Service<Request<Full<Bytes>>> for Failing {
+    type Response = Response<Full<Bytes>>;
+    type Error = std::io::Error;
+    type Future = Ready<Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+      Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, _request: Request<Full<Bytes>>) -> Self::Future {
+      ready(Err(std::io::ErrorKind::Other.into()))
+    }
+  }
+
+  #[test]
+  fn calls_services() {
+    let mut request = Request::post("app://localhost/echo")
+      .body(Full::new(Bytes::from_static(b"hello")))
+      .unwrap();
+    request
+      .extensions_mut()
+      .insert(WebViewIdExtension("main".into()));
+
+    let response = block_on(call(Echo, request));
+    assert_eq!(response.status(), StatusCode::CREATED);
+    assert_eq!(
+      &response.into_body().into_bytes().unwrap()[..],
+      b"POST /echo from main: hello"
+    );
+  }
+
+  #[test]
+  fn answers_errors() {
+    let request = Request::get("app://localhost/")
+      .body(Full::default())
+      .unwrap();
+    let response = block_on(call(Failing, request));
+    assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+  }
+}
@@ -0,0 +1,184 @@
+// Copyright 2020-2024 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+use std::{collections::HashMap, sync::Mutex};
+
+use http::{header::LOCATION, Response, StatusCode};
+use once_cell::sync::Lazy;
+
+/// The maximum number of redirects of a navigation, as in the Fetch standard.
+pub(crate) const MAX_REDIRECTS: usize = 20;
+
+/// The URLs visited by the redirected navigation of each webview.
+static REDIRECT_CHAINS: Lazy<Mutex<HashMap<String, Vec<String>>>> = Lazy::new(Default::default);
+
+/// What to do with a redirect response to a main document request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Redirect {
+  /// Load this URL in the webview.
+  To(String),
+  /// The redirects loop or exceed [`MAX_REDIRECTS`], answer with [`too_many_redirects`] instead.
+  TooMany,
+}
+
+/// Resolves the `Location` of a 301, 302, 303, 307 or 308 response against the request `url`.
+pub(crate) fn location<T>(url: &str, response: &Response<T>) -> Option<String> {
+  if !matches!(
+    response.status(),
+    StatusCode::MOVED_PERMANENTLY
+      | StatusCode::FOUND
+      | StatusCode::SEE_OTHER
+      | StatusCode::TEMPORARY_REDIRECT
+      | StatusCode::PERMANENT_REDIRECT
+  ) {
+    return None;
+  }
+  let location = response.headers().get(LOCATION)?.to_str().ok()?;
+  let url = url::Url::parse(url).ok()?.join(location).ok()?;
+  Some(url.into())
+}
+
+/// Follows the response to a main document request of `webview_id` for `url`.
+///
+/// Returns `None` if it is not a redirect, which also ends the redirect chain of the webview.
+/// A request for another URL than the last redirect target starts a new chain: it is either a new navigation,
+/// or the chain left the custom protocols.
+pub(crate) fn follow<T>(webview_id: &str, url: &str, response: &Response<T>) -> Option<Redirect> {
+  let mut chains = REDIRECT_CHAINS.lock().unwrap();
+  let Some(target) = location(url, response) else {
+    chains.remove(webview_id);
+    return None;
+  };
+
+  let chain = chains.entry(webview_id.to_string()).or_default();
+  if chain.last().map(|last| without_fragment(last)) != Some(without_fragment(url)) {
+    chain.clear();
+    chain.push(url.to_string());
+  }
+  if chain.len() > MAX_REDIRECTS || chain.contains(&target) {
+    chains.remove(webview_id);
+    return Some(Redirect::TooMany);
+  }
+  chain.push(target.clone());
+  Some(Redirect::To(target))
+}
+
+/// Drops the redirect chain of `webview_id`, once the webview is destroyed.
+pub(crate) fn forget(webview_id: &str) {
+  REDIRECT_CHAINS.lock().unwrap().remove(webview_id);
+}
+
+/// The fragment is not part of the requested URL.
+fn without_fragment(url: &str) -> &str {
+  url.split_once('#').map_or(url, |(url, _)| url)
+}
+
+/// The response to a main document request that was redirected by loading another URL,
+/// which leaves the current page untouched.
+pub(crate) fn no_content<B: From<&'static [u8]>>() -> Response<B> {
+  let mut response = Response::new(B::from(&[]));
+  *response.status_mut() = StatusCode::NO_CONTENT;
+  response
+}
+
+/// The response to a main document request whose redirects loop.
+pub(crate) fn too_many_redirects<B: From<&'static [u8]>>() -> Response<B> {
+  Response::builder()
+    .status(StatusCode::LOOP_DETECTED)
+    .header(http::header::CONTENT_TYPE, "text/plain")
+    .body(B::from(b"Too many redirects"))
+    .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn redirect(location: &str) -> Response<()> {
+    Response::builder()
+      .status(StatusCode::FOUND)
+      .header(LOCATION, location)
+      .body(())
+      .unwrap()
+  }
+
+  #[test]
+  fn resolves_locations() {
+    let url = "app://localhost/auth/login";
+    assert_eq!(
+      location(url, &redirect("app://home")).as_deref(),
+      Some("app://home")
+    );
+    assert_eq!(
+      location(url, &redirect("/home?a=b")).as_deref(),
+      Some("app://localhost/home?a=b")
+    );
+    assert_eq!(
+      location(url, &redirect("done")).as_deref(),
+      Some("app://localhost/auth/done")
+    );
+    assert_eq!(location(url, &Response::new(())), None);
+
+    let mut not_modified = redirect("/home");
+    *not_modified.status_mut() = StatusCode::NOT_MODIFIED;
+    assert_eq!(location(url, &not_modified), None);
+  }
+
+  #[test]
+  fn detects_loops() {
+    let id = "detects_loops";
+    assert_eq!(
+      follow(id, "app://login", &redirect("app://home")),
+      Some(Redirect::To("app://home".into()))
+    );
+    assert_eq!(
+      follow(id, "app://home", &redirect("app://login")),
+      Some(Redirect::TooMany)
+    );
+
+    // the chain starts over after a loop and after a page is served
+    follow(id, "app://login", &redirect("app://home"));
+    assert_eq!(follow(id, "app://home", &Response::new(())), None);
+    assert_eq!(
+      follow(id, "app://login", &redirect("app://home")),
+      Some(Redirect::To("app://home".into()))
+    );
+
+    // a new navigation, or a redirect leaving the custom protocols, starts a new chain
+    let id = "restarts";
+    follow(id, "app://login", &redirect("app://home#top"));
+    assert_eq!(
+      follow(id, "app://other", &redirect("app://login")),
+      Some(Redirect::To("app://login".into()))
+    );
+    follow(id, "app://login", &redirect("https://example.com"));
+    assert_eq!(
+      follow(id, "app://home", &redirect("app://login")),
+      Some(Redirect::To("app://login".into()))
+    );
+    // the fragment of the target is not requested
+    follow(id, "app://login", &redirect("app://home#top"));
+    assert_eq!(
+      follow(id, "app://home", &redirect("app://login")),
+      Some(Redirect::TooMany)
+    );
+
+    follow(id, "app://login", &redirect("app://home"));
+    forget(id);
+    assert!(!REDIRECT_CHAINS.lock().unwrap().contains_key(id));
+
+    let id = "too_many";
+    for i in 0..MAX_REDIRECTS {
+      let next = format!("app://localhost/{}", i + 1);
+      assert_eq!(
+        follow(id, &format!("app://localhost/{i}"), &redirect(&next)),
+        Some(Redirect::To(next))
+      );
+    }
+    assert_eq!(
+      follow(id, "app://localhost/20", &redirect("/21")),
+      Some(Redirect::TooMany)
+    );
+  }
+}
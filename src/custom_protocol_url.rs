@@ -0,0 +1,124 @@
+// Copyright 2020-2024 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+/// How pages see the URLs of custom protocols.
+///
+/// WebView2 only supports non-standard protocols on Windows 10+ and the Android WebView not at all,
+/// so there `<scheme>://<path>` is exposed as `http://<scheme>.<path>` instead.
+/// See <https://github.com/MicrosoftEdge/WebView2Feedback/issues/73>.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum UrlStyle {
+  /// `<scheme>://<path>`, on macOS, iOS and Linux.
+  Custom,
+  /// `http://<scheme>.<path>`.
+  #[cfg(any(windows, target_os = "android", test))]
+  Http,
+  /// `https://<scheme>.<path>`.
+  #[cfg(any(windows, target_os = "android", test))]
+  Https,
+}
+
+impl UrlStyle {
+  /// The style used by Windows and Android.
+  #[cfg(any(windows, target_os = "android", test))]
+  pub fn workaround(https: bool) -> Self {
+    if https {
+      Self::Https
+    } else {
+      Self::Http
+    }
+  }
+
+  /// The scheme seen by pages, or `None` if it is the custom protocol name.
+  pub fn scheme(self) -> Option<&'static str> {
+    match self {
+      Self::Custom => None,
+      #[cfg(any(windows, target_os = "android", test))]
+      Self::Http => Some("http"),
+      #[cfg(any(windows, target_os = "android", test))]
+      Self::Https => Some("https"),
+    }
+  }
+
+  /// The origin of pages loaded from `protocol://localhost`.
+  pub fn origin(self, protocol: &str) -> String {
+    match self.scheme() {
+      None => format!("{protocol}://localhost"),
+      Some(scheme) => format!("{scheme}://{protocol}.localhost"),
+    }
+  }
+
+  /// The URL pages use to load `path` from `protocol://localhost`.
+  pub fn url(self, protocol: &str, path: &str) -> String {
+    format!("{}/{}", self.origin(protocol), path.trim_start_matches('/'))
+  }
+
+  /// Converts a `protocol://` URL to the URL the webview has to load, or `None` for other URLs.
+  #[cfg(any(windows, target_os = "android", test))]
+  pub fn to_platform(self, url: &str, protocol: &str) -> Option<String> {
+    let rest = url.strip_prefix(protocol)?.strip_prefix("://")?;
+    Some(match self.scheme() {
+      None => url.to_string(),
+      Some(scheme) => format!("{scheme}://{protocol}.{rest}"),
+    })
+  }
+
+  /// Converts a URL requested by the webview back to a `protocol://` URL,
+  /// or `None` if the URL does not belong to `protocol`.
+  #[cfg(any(windows, target_os = "android", test))]
+  pub fn to_custom(self, uri: &str, protocol: &str) -> Option<String> {
+    let rest = match self.scheme() {
+      None => uri.strip_prefix(protocol)?.strip_prefix("://")?,
+      Some(scheme) => uri
+        .strip_prefix(scheme)?
+        .strip_prefix("://")?
+        .strip_prefix(protocol)?
+        .strip_prefix('.')?,
+    };
+    (!rest.is_empty()).then(|| format!("{protocol}://{rest}"))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn origins_and_urls() {
+    assert_eq!(UrlStyle::Custom.origin("wry"), "wry://localhost");
+    assert_eq!(UrlStyle::Http.origin("wry"), "http://wry.localhost");
+    assert_eq!(
+      UrlStyle::Https.url("wry", "/index.html"),
+      "https://wry.localhost/index.html"
+    );
+    assert_eq!(UrlStyle::Custom.url("wry", "a/b"), "wry://localhost/a/b");
+  }
+
+  #[test]
+  fn converts_urls() {
+    let style = UrlStyle::Http;
+    assert_eq!(
+      style
+        .to_platform("wry://localhost/page?next=wry://x", "wry")
+        .as_deref(),
+      Some("http://wry.localhost/page?next=wry://x")
+    );
+    assert_eq!(style.to_platform("https://tauri.app", "wry"), None);
+
+    let uri = "http://wry.localhost/path/to/page";
+    assert_eq!(
+      style.to_custom(uri, "wry").as_deref(),
+      Some("wry://localhost/path/to/page")
+    );
+    assert_eq!(style.to_custom(uri, "asset"), None);
+    assert_eq!(style.to_custom("http://wry", "wry"), None);
+    assert_eq!(UrlStyle::Https.to_custom(uri, "wry"), None);
+    assert_eq!(
+      UrlStyle::Custom
+        .to_custom("wry://localhost/", "wry")
+        .as_deref(),
+      Some("wry://localhost/")
+    );
+  }
+}
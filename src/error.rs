@@ -65,7 +65,12 @@ pub enum Error {
   DuplicateCustomProtocol(String),
   #[error("Duplicate custom protocol registered on the same web context on Linux: {0}")]
   ContextDuplicateCustomProtocol(String),
+  #[error("Custom protocol options or CORS policy set for a scheme without a handler: {0}")]
+  UnknownCustomProtocol(String),
   #[error(transparent)]
   #[cfg(any(target_os = "macos", target_os = "ios"))]
   UrlPrase(#[from] url::ParseError),
+  #[cfg(feature = "serde")]
+  #[error(transparent)]
+  Json(#[from] serde_json::Error),
 }
@@ -0,0 +1,505 @@
+// Copyright 2020-2024 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+//! Injection of scripts into HTML documents served by custom protocols.
+//!
+//! Scripts are prepended to the `<head>` of the document, and the `Content-Security-Policy` of the
+//! response, from its headers or a `<meta http-equiv>` tag, is amended with the SHA-256 hashes of the
+//! scripts so strict policies still let them run. Alternatively, the scripts can carry a [`ScriptNonce`]
+//! that the `Content-Security-Policy` allows, see [`inject_scripts_with_nonce`].
+//!
+//! Wry uses it where the webview can't run initialization scripts by itself, and it can be used
+//! directly in custom protocol handlers:
+//!
+//! ```no_run
+//! use wry::{html, http::Response, WebViewBuilder};
+//!
+//! let builder = WebViewBuilder::new().with_custom_protocol("app".into(), |_webview_id, _request| {
+//!   let mut response = Response::builder()
+//!     .header("Content-Type", "text/html")
+//!     .header("Content-Security-Policy", "script-src 'self'")
+//!     .body(b"<html><head></head><body></body></html>".to_vec().into())
+//!     .unwrap();
+//!   html::inject_scripts(&mut response, &["window.answer = 42"]);
+//!   response
+//! });
+//! ```
+//!
+//! When wry injects scripts into the documents of a custom protocol, it generates a fresh [`ScriptNonce`]
+//! for each request and adds it to the request extensions, so handlers can use it in their policy:
+//!
+//! ```no_run
+//! use wry::{html::ScriptNonce, http::Response};
+//!
+//! fn handler(request: wry::http::Request<Vec<u8>>) -> Response<Vec<u8>> {
+//!   let script_src = match request.extensions().get::<ScriptNonce>() {
+//!     Some(nonce) => format!("script-src 'self' {}", nonce.source()),
+//!     None => "script-src 'self'".to_string(),
+//!   };
+//!   Response::builder()
+//!     .header("Content-Type", "text/html")
+//!     .header("Content-Security-Policy", script_src)
+//!     .body(b"<html><head></head><body></body></html>".to_vec())
+//!     .unwrap()
+//! }
+//! ```
+
+#[cfg(gtk)]
+use std::sync::Arc;
+use std::{borrow::Cow, fmt};
+
+use base64::{engine::general_purpose, Engine};
+use html5ever::{interface::QualName, namespace_url, ns, tendril::TendrilSink, LocalName};
+use http::{
+  header::{CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_SECURITY_POLICY, CONTENT_TYPE},
+  HeaderMap, HeaderValue, Response, StatusCode,
+};
+use kuchiki::{Attribute, ExpandedName, NodeRef};
+use sha2::{Digest, Sha256};
+
+#[cfg(gtk)]
+use crate::{RequestAsyncResponder, ResponseBody, WebViewId};
+
+/// A random nonce for the `Content-Security-Policy` `nonce-` source.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ScriptNonce(String);
+
+impl ScriptNonce {
+  /// Generates a new nonce from 128 random bits.
+  ///
+  /// ## Panics
+  ///
+  /// Panics if the system random number generator is unavailable.
+  pub fn new() -> Self {
+    let mut bytes = [0u8; 16];
+    getrandom::getrandom(&mut bytes).expect("failed to generate a script nonce");
+    Self(general_purpose::STANDARD.encode(bytes))
+  }
+
+  /// The nonce, as used in the `nonce` attribute of scripts.
+  pub fn as_str(&self) -> &str {
+    &self.0
+  }
+
+  /// The CSP source allowing the scripts with this nonce, such as `'nonce-…'`.
+  pub fn source(&self) -> String {
+    format!("'nonce-{}'", self.0)
+  }
+}
+
+impl Default for ScriptNonce {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl fmt::Display for ScriptNonce {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(&self.0)
+  }
+}
+
+/// Whether the `Content-Type` of the headers is `text/html`.
+pub fn is_html(headers: &HeaderMap) -> bool {
+  headers
+    .get(CONTENT_TYPE)
+    // Content-Type must begin with the media type, but is case-insensitive.
+    // It may also be followed by any number of semicolon-delimited key value pairs.
+    // We don't care about these here.
+    // source: https://httpwg.org/specs/rfc9110.html#rfc.section.8.3.1
+    .and_then(|content_type| content_type.to_str().ok())
+    .map(|content_type| content_type.to_lowercase().starts_with("text/html"))
+    .unwrap_or_default()
+}
+
+/// Whether scripts can be injected into the body of the response: a complete `text/html` document
+/// that is not compressed.
+fn is_injectable<T>(response: &Response<T>) -> bool {
+  let encoded = response
+    .headers()
+    .get(CONTENT_ENCODING)
+    .is_some_and(|encoding| {
+      !encoding
+        .to_str()
+        .is_ok_and(|encoding| encoding.trim().eq_ignore_ascii_case("identity"))
+    });
+  response.status() == StatusCode::OK && !encoded && is_html(response.headers())
+}
+
+/// Replaces the body of the response with `document`, updating its `Content-Length`.
+fn set_document(response: &mut Response<Cow<'static, [u8]>>, document: String) {
+  response
+    .headers_mut()
+    .insert(CONTENT_LENGTH, HeaderValue::from(document.len()));
+  *response.body_mut() = document.into_bytes().into();
+}
+
+/// Returns the CSP source allowing an inline script, such as `'sha256-…'`.
+pub fn hash_script(script: &str) -> String {
+  let mut hasher = Sha256::new();
+  hasher.update(script);
+  let hash = hasher.finalize();
+  format!("'sha256-{}'", general_purpose::STANDARD.encode(hash))
+}
+
+/// Amends a `Content-Security-Policy` so it allows the inline scripts with the given sources,
+/// such as hashes from [`hash_script`].
+///
+/// The sources are added to the `script-src` and `script-src-elem` directives, or to a new `script-src`
+/// directive copied from `default-src`, replacing `'none'`. Sources already in a directive are not repeated.
+/// Directives allowing `'unsafe-inline'` are left untouched, since adding a hash or nonce to them
+/// would disallow the other inline scripts of the page.
+pub fn allow_scripts_in_csp(csp: &str, sources: &[String]) -> String {
+  let mut directives = csp
+    .split(';')
+    .map(str::trim)
+    .filter(|directive| !directive.is_empty())
+    .map(ToString::to_string)
+    .collect::<Vec<_>>();
+  let name = |directive: &str| {
+    directive
+      .split_ascii_whitespace()
+      .next()
+      .unwrap_or_default()
+      .to_ascii_lowercase()
+  };
+  let allow = |directive: &str| {
+    let values = directive
+      .split_ascii_whitespace()
+      .skip(1)
+      .collect::<Vec<_>>();
+    let unsafe_inline = values
+      .iter()
+      .any(|v| v.eq_ignore_ascii_case("'unsafe-inline'"))
+      && !values.iter().any(|v| {
+        let v = v.to_ascii_lowercase();
+        v.starts_with("'nonce-") || v.starts_with("'sha")
+      });
+    if unsafe_inline {
+      directive.to_string()
+    } else {
+      // `'none'` can't be combined with other sources
+      let kept = directive
+        .split_ascii_whitespace()
+        .filter(|v| !v.eq_ignore_ascii_case("'none'"))
+        .collect::<Vec<_>>();
+      let added = sources
+        .iter()
+        .map(String::as_str)
+        .filter(|source| !kept[1..].contains(source));
+      kept
+        .iter()
+        .copied()
+        .chain(added)
+        .collect::<Vec<_>>()
+        .join(" ")
+    }
+  };
+
+  let mut script_src = false;
+  for directive in &mut directives {
+    if matches!(name(directive).as_str(), "script-src" | "script-src-elem") {
+      script_src |= name(directive) == "script-src";
+      *directive = allow(directive);
+    }
+  }
+  if !script_src {
+    if let Some(default_src) = directives.iter().find(|d| name(d) == "default-src") {
+      let values = default_src
+        .split_ascii_whitespace()
+        .skip(1)
+        .collect::<Vec<_>>();
+      directives.push(allow(&format!("script-src {}", values.join(" "))));
+    }
+  }
+
+  directives.join("; ")
+}
+
+/// Prepends `scripts` to the `<head>` of an HTML document, creating it if needed,
+/// and amends its `<meta http-equiv="Content-Security-Policy">` tags.
+///
+/// Returns the new document and the hashes of the scripts.
+pub fn prepend_scripts<S: AsRef<str>>(html: &str, scripts: &[S]) -> (String, Vec<String>) {
+  let hashes = scripts
+    .iter()
+    .map(|script| hash_script(script.as_ref()))
+    .collect::<Vec<_>>();
+  (prepend(html, scripts, None, &hashes), hashes)
+}
+
+/// Prepends `scripts` with the `nonce` attribute to the `<head>` of an HTML document, creating it if needed,
+/// and adds the nonce to its `<meta http-equiv="Content-Security-Policy">` tags.
+pub fn prepend_scripts_with_nonce<S: AsRef<str>>(
+  html: &str,
+  scripts: &[S],
+  nonce: &ScriptNonce,
+) -> String {
+  prepend(html, scripts, Some(nonce), &[nonce.source()])
+}
+
+fn prepend<S: AsRef<str>>(
+  html: &str,
+  scripts: &[S],
+  nonce: Option<&ScriptNonce>,
+  sources: &[String],
+) -> String {
+  let document = kuchiki::parse_html().one(html);
+
+  with_html_head(&document, |head| {
+    // iterate in reverse order since we are prepending each script to the head tag
+    for script in scripts.iter().rev() {
+      let attributes = nonce.map(|nonce| {
+        (
+          ExpandedName::new(ns!(), "nonce"),
+          Attribute {
+            prefix: None,
+            value: nonce.to_string(),
+          },
+        )
+      });
+      let script_el =
+        NodeRef::new_element(QualName::new(None, ns!(html), "script".into()), attributes);
+      script_el.append(NodeRef::new_text(script.as_ref()));
+      head.prepend(script_el);
+    }
+  });
+
+  if let Ok(metas) = document.select("meta[http-equiv]") {
+    for meta in metas {
+      let mut attributes = meta.attributes.borrow_mut();
+      let is_csp = attributes
+        .get("http-equiv")
+        .is_some_and(|v| v.eq_ignore_ascii_case("content-security-policy"));
+      if let Some(csp) = attributes.get("content").filter(|_| is_csp) {
+        let csp = allow_scripts_in_csp(csp, sources);
+        attributes.insert("content", csp);
+      }
+    }
+  }
+
+  document.to_string()
+}
+
+/// Prepends `scripts` to the HTML document of a response and amends its `Content-Security-Policy` headers.
+/// The `Content-Length` header is updated to the new length of the document.
+///
+/// Does nothing if `scripts` is empty, or if the response is not a `200 OK` `text/html` document
+/// without `Content-Encoding`, such as partial or compressed responses.
+pub fn inject_scripts<S: AsRef<str>>(response: &mut Response<Cow<'static, [u8]>>, scripts: &[S]) {
+  if scripts.is_empty() || !is_injectable(response) {
+    return;
+  }
+
+  let (document, hashes) = prepend_scripts(&String::from_utf8_lossy(response.body()), scripts);
+  set_document(response, document);
+  allow_scripts_in_headers(response.headers_mut(), &hashes);
+}
+
+/// Prepends `scripts` with the `nonce` attribute to the HTML document of a response
+/// and adds the nonce to its `Content-Security-Policy` headers, if they don't allow it already.
+///
+/// Like [`inject_scripts`], it only rewrites complete and uncompressed HTML documents.
+pub fn inject_scripts_with_nonce<S: AsRef<str>>(
+  response: &mut Response<Cow<'static, [u8]>>,
+  scripts: &[S],
+  nonce: &ScriptNonce,
+) {
+  if scripts.is_empty() || !is_injectable(response) {
+    return;
+  }
+
+  let document =
+    prepend_scripts_with_nonce(&String::from_utf8_lossy(response.body()), scripts, nonce);
+  set_document(response, document);
+  allow_scripts_in_headers(response.headers_mut(), &[nonce.source()]);
+}
+
+fn allow_scripts_in_headers(headers: &mut HeaderMap, sources: &[String]) {
+  let policies = headers
+    .get_all(CONTENT_SECURITY_POLICY)
+    .iter()
+    .filter_map(|csp| csp.to_str().ok())
+    .filter_map(|csp| HeaderValue::from_str(&allow_scripts_in_csp(csp, sources)).ok())
+    .collect::<Vec<_>>();
+  if !policies.is_empty() {
+    headers.remove(CONTENT_SECURITY_POLICY);
+    for csp in policies {
+      headers.append(CONTENT_SECURITY_POLICY, csp);
+    }
+  }
+}
+
+/// Wraps a custom protocol handler so `scripts` are injected into its HTML responses,
+/// with a new [`ScriptNonce`] added to the extensions of each request.
+#[cfg(gtk)]
+pub(crate) fn injecting_handler<T, F>(
+  handler: F,
+  scripts: Arc<Vec<String>>,
+) -> impl Fn(WebViewId, http::Request<T>, RequestAsyncResponder) + 'static
+where
+  F: Fn(WebViewId, http::Request<T>, RequestAsyncResponder) + 'static,
+{
+  move |webview_id, mut request: http::Request<T>, responder| {
+    let scripts = scripts.clone();
+    let nonce = ScriptNonce::new();
+    request.extensions_mut().insert(nonce.clone());
+    let RequestAsyncResponder {
+      responder,
+      cancellation,
+    } = responder;
+    let responder = RequestAsyncResponder {
+      responder: Box::new(move |response: Response<ResponseBody>| {
+        if !is_injectable(&response) {
+          return responder(response);
+        }
+        let mut response = crate::buffer_response(response);
+        inject_scripts_with_nonce(&mut response, &scripts, &nonce);
+        responder(response.map(Into::into))
+      }),
+      cancellation,
+    };
+    handler(webview_id, request, responder)
+  }
+}
+
+fn with_html_head<F: FnOnce(&NodeRef)>(document: &NodeRef, f: F) {
+  if let Ok(ref node) = document.select_first("head") {
+    f(node.as_node())
+  } else {
+    let node = NodeRef::new_element(
+      QualName::new(None, ns!(html), LocalName::from("head")),
+      None,
+    );
+    f(&node);
+    document.prepend(node)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn amends_policies() {
+    let hashes = ["'sha256-a'".to_string()];
+    assert_eq!(
+      allow_scripts_in_csp("default-src 'self'; script-src 'self'", &hashes),
+      "default-src 'self'; script-src 'self' 'sha256-a'"
+    );
+    assert_eq!(
+      allow_scripts_in_csp("default-src 'self'; img-src *;", &hashes),
+      "default-src 'self'; img-src *; script-src 'self' 'sha256-a'"
+    );
+    assert_eq!(
+      allow_scripts_in_csp("script-src 'unsafe-inline'", &hashes),
+      "script-src 'unsafe-inline'"
+    );
+    assert_eq!(allow_scripts_in_csp("img-src *", &hashes), "img-src *");
+    assert_eq!(
+      allow_scripts_in_csp("script-src 'sha256-a'", &hashes),
+      "script-src 'sha256-a'"
+    );
+  }
+
+  #[test]
+  fn injects_scripts() {
+    let mut response = Response::builder()
+      .header(CONTENT_TYPE, "text/html; charset=utf-8")
+      .header(CONTENT_SECURITY_POLICY, "script-src 'self'")
+      .body(Cow::Borrowed(
+        &b"<html><head><meta http-equiv=\"Content-Security-Policy\" content=\"default-src 'none'\"><script>page()</script></head></html>"[..],
+      ))
+      .unwrap();
+    inject_scripts(&mut response, &["first()", "second()"]);
+
+    let html = String::from_utf8(response.body().to_vec()).unwrap();
+    let first = html.find("<script>first()</script>").unwrap();
+    let second = html.find("<script>second()</script>").unwrap();
+    assert!(first < second && second < html.find("page()").unwrap());
+
+    let hashes = format!("{} {}", hash_script("first()"), hash_script("second()"));
+    assert!(html.contains(&format!("default-src 'none'; script-src {hashes}")));
+    assert_eq!(
+      response.headers()[CONTENT_SECURITY_POLICY],
+      format!("script-src 'self' {hashes}")
+    );
+
+    // other documents are left untouched
+    let mut response = Response::new(Cow::Borrowed(&b"{}"[..]));
+    inject_scripts(&mut response, &["first()"]);
+    assert_eq!(&response.body()[..], b"{}");
+  }
+
+  #[test]
+  fn updates_content_length() {
+    let body = &b"<html><head></head></html>"[..];
+    let mut response = Response::builder()
+      .header(CONTENT_TYPE, "text/html")
+      .header(CONTENT_LENGTH, body.len())
+      .body(Cow::Borrowed(body))
+      .unwrap();
+    inject_scripts(&mut response, &["first()"]);
+
+    assert_eq!(
+      response.headers()[CONTENT_LENGTH],
+      response.body().len().to_string()
+    );
+    assert!(response.body().len() > body.len());
+  }
+
+  #[test]
+  fn skips_partial_and_encoded_responses() {
+    let body = &b"<html><head></head></html>"[..];
+
+    let mut partial = Response::builder()
+      .status(StatusCode::PARTIAL_CONTENT)
+      .header(CONTENT_TYPE, "text/html")
+      .header(CONTENT_LENGTH, body.len())
+      .body(Cow::Borrowed(body))
+      .unwrap();
+    inject_scripts(&mut partial, &["first()"]);
+    assert_eq!(&partial.body()[..], body);
+
+    let mut compressed = Response::builder()
+      .header(CONTENT_TYPE, "text/html")
+      .header(CONTENT_ENCODING, "gzip")
+      .body(Cow::Borrowed(body))
+      .unwrap();
+    inject_scripts_with_nonce(&mut compressed, &["first()"], &ScriptNonce::new());
+    assert_eq!(&compressed.body()[..], body);
+
+    // identity is no encoding
+    let mut identity = Response::builder()
+      .header(CONTENT_TYPE, "text/html")
+      .header(CONTENT_ENCODING, "identity")
+      .body(Cow::Borrowed(body))
+      .unwrap();
+    inject_scripts(&mut identity, &["first()"]);
+    assert_ne!(&identity.body()[..], body);
+  }
+
+  #[test]
+  fn injects_scripts_with_nonce() {
+    let nonce = ScriptNonce::new();
+    assert_ne!(nonce, ScriptNonce::new());
+
+    let mut response = Response::builder()
+      .header(CONTENT_TYPE, "text/html")
+      .header(
+        CONTENT_SECURITY_POLICY,
+        format!("script-src {}", nonce.source()),
+      )
+      .body(Cow::Borrowed(&b"<html><head></head></html>"[..]))
+      .unwrap();
+    inject_scripts_with_nonce(&mut response, &["first()"], &nonce);
+
+    let html = String::from_utf8(response.body().to_vec()).unwrap();
+    assert!(html.contains(&format!("<script nonce=\"{nonce}\">first()</script>")));
+    assert_eq!(
+      response.headers()[CONTENT_SECURITY_POLICY],
+      format!("script-src {}", nonce.source())
+    );
+  }
+}
@@ -0,0 +1,603 @@
+// Copyright 2020-2024 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+//! Promise-based IPC between the webview and Rust.
+//!
+//! `window.ipc.invoke(command, payload)` sends `payload` to the handler of `command` in an
+//! [`IpcRouter`] and returns a `Promise` that resolves with the value the handler returns, or
+//! rejects with an `Error` carrying the message of the error it returns.
+//!
+//! ```js
+//! const greeting = await window.ipc.invoke('greet', { name: 'wry' })
+//! ```
+//!
+//! Payloads and return values are JSON: the handler receives the `JSON.stringify`ed payload
+//! as the request body, and returns the JSON text the promise resolves with. With the `serde`
+//! feature, [`IpcRouter::with_typed_command`] does the conversions.
+//!
+//! `window.ipc.postMessage` also accepts an `ArrayBuffer`, a typed array or a `DataView`, whose
+//! bytes are passed to the [IPC body handler](crate::WebViewBuilder::with_ipc_body_handler)
+//! as an [`IpcBody::Binary`] body.
+//!
+//! The IPC requests carry the [`IpcFrame`] that sent them in their extensions, and an
+//! [`IpcPolicy`] restricts which frames may use IPC, and which commands they may invoke.
+
+mod policy;
+
+use std::{collections::HashMap, error::Error, fmt};
+
+#[cfg(not(linux))]
+use base64::{engine::general_purpose, Engine};
+use http::Request;
+use percent_encoding::percent_decode_str;
+
+use crate::util::json_string;
+
+pub use policy::IpcPolicy;
+
+/// Starts the prefixes of the messages `window.ipc` posts to the host.
+const RESERVED_PREFIX: &str = "__WRY_";
+
+/// Prefixes the `window.ipc.postMessage` messages of `window.ipc.invoke` calls.
+const INVOKE_PREFIX: &str = "__WRY_INVOKE__";
+
+/// Prefixes the base64 of the binary messages of `window.ipc.postMessage`, on the platforms
+/// whose message handlers only take strings.
+const BINARY_PREFIX: &str = "__WRY_BINARY__";
+
+/// Prefixes the text messages of `window.ipc.postMessage` starting with [`RESERVED_PREFIX`],
+/// so they are not mistaken for the other messages.
+const TEXT_PREFIX: &str = "__WRY_TEXT__";
+
+/// The global function the host calls to settle the promise of an `invoke` call.
+const REPLY_FUNCTION: &str = "__WRY_IPC_REPLY__";
+
+/// A JavaScript expression generating the id of an `invoke` call, random so the scripts of the
+/// page cannot settle the promises of other calls.
+pub(crate) const INVOKE_ID: &str = "Array.prototype.map.call(crypto.getRandomValues(new Uint8Array(16)), function (b) { return (b + 256).toString(16).slice(1); }).join('')";
+
+/// The body of an IPC message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IpcBody {
+  /// A string posted with `window.ipc.postMessage`.
+  Text(String),
+  /// The bytes of an `ArrayBuffer`, typed array or `DataView` posted with `window.ipc.postMessage`.
+  Binary(Vec<u8>),
+}
+
+impl IpcBody {
+  /// The text of a [`IpcBody::Text`] body.
+  pub fn as_text(&self) -> Option<&str> {
+    match self {
+      Self::Text(text) => Some(text),
+      Self::Binary(_) => None,
+    }
+  }
+
+  /// The bytes of the body, UTF-8 for [`IpcBody::Text`].
+  pub fn as_bytes(&self) -> &[u8] {
+    match self {
+      Self::Text(text) => text.as_bytes(),
+      Self::Binary(bytes) => bytes,
+    }
+  }
+
+  /// Whether the body is [`IpcBody::Binary`].
+  pub fn is_binary(&self) -> bool {
+    matches!(self, Self::Binary(_))
+  }
+}
+
+impl From<String> for IpcBody {
+  fn from(text: String) -> Self {
+    Self::Text(text)
+  }
+}
+
+impl From<Vec<u8>> for IpcBody {
+  fn from(bytes: Vec<u8>) -> Self {
+    Self::Binary(bytes)
+  }
+}
+
+/// The frame that sent an IPC message, in the extensions of the IPC requests.
+///
+/// ```no_run
+/// use wry::{ipc::IpcFrame, WebViewBuilder};
+///
+/// let builder = WebViewBuilder::new().with_ipc_handler(|request| {
+///   match request.extensions().get::<IpcFrame>() {
+///     Some(frame) if frame.is_main_frame && frame.origin == "https://example.com" => {
+///       println!("{}", request.body())
+///     }
+///     _ => {}
+///   }
+/// });
+/// ```
+///
+/// ## Platform-specific
+///
+/// - **Linux**: WebKitGTK does not tell which frame posted a message, so the frame is reported by
+///   a script running in an isolated world of each frame, out of reach of the scripts of the page.
+/// - **Windows**: Only the messages of the main frame are received.
+/// - **Android**: Unsupported, the requests have no `IpcFrame` and their URL is the main frame URL.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IpcFrame {
+  /// The URL of the document of the frame.
+  pub url: String,
+  /// The security origin of the frame, `scheme://host[:port]`, or `null` for opaque origins.
+  pub origin: String,
+  /// Whether the frame is the main frame of the webview.
+  pub is_main_frame: bool,
+}
+
+impl IpcFrame {
+  /// A request from this frame, whose URI is the URL of the frame unless it is not a valid URI,
+  /// like `data:` URLs.
+  #[cfg_attr(target_os = "android", allow(dead_code))]
+  pub(crate) fn request<T>(self, body: T) -> Request<T> {
+    let mut request = Request::new(body);
+    if let Ok(uri) = self.url.parse() {
+      *request.uri_mut() = uri;
+    }
+    request.extensions_mut().insert(self);
+    request
+  }
+}
+
+type CommandHandler = dyn Fn(Request<String>) -> Result<String, Box<dyn Error>>;
+
+/// Maps the commands of `window.ipc.invoke` to their handlers.
+///
+/// See the [module documentation](self) for the protocol.
+///
+/// # Examples
+///
+/// ```no_run
+/// use wry::{ipc::IpcRouter, WebViewBuilder};
+///
+/// let router = IpcRouter::new().with_command("greet", |request| {
+///   let name = request.body().trim_matches('"');
+///   if name.is_empty() {
+///     return Err("missing name");
+///   }
+///   Ok(format!("\"Hello, {name}!\""))
+/// });
+///
+/// let builder = WebViewBuilder::new().with_ipc_router(router);
+/// ```
+#[derive(Default)]
+pub struct IpcRouter {
+  commands: HashMap<String, Box<CommandHandler>>,
+}
+
+impl fmt::Debug for IpcRouter {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("IpcRouter")
+      .field("commands", &self.commands.keys().collect::<Vec<_>>())
+      .finish()
+  }
+}
+
+impl IpcRouter {
+  /// Creates a router without commands.
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Handles the `name` command, replacing its previous handler.
+  ///
+  /// The handler receives a request whose URI is the URL of the page and whose body is the
+  /// JSON payload. It returns the JSON text of the value the promise resolves with, or an error
+  /// the promise rejects with.
+  pub fn with_command<F, E>(mut self, name: impl Into<String>, handler: F) -> Self
+  where
+    F: Fn(Request<String>) -> Result<String, E> + 'static,
+    E: Into<Box<dyn Error>>,
+  {
+    self.commands.insert(
+      name.into(),
+      Box::new(move |request| handler(request).map_err(Into::into)),
+    );
+    self
+  }
+
+  /// Handles the `name` command with a handler taking the deserialized payload and returning
+  /// a value to serialize, replacing its previous handler.
+  ///
+  /// The promise rejects with the deserialization error if the payload is not a `T`.
+  ///
+  /// # Examples
+  ///
+  /// ```no_run
+  /// use wry::{http::Request, ipc::IpcRouter};
+  ///
+  /// // window.ipc.invoke('greet', ['wry', 2]) resolves with 'Hello, wry! Hello, wry!'
+  /// let router = IpcRouter::new().with_typed_command("greet", |request: Request<(String, usize)>| {
+  ///   let (name, times) = request.into_body();
+  ///   Ok::<_, String>(format!("Hello, {name}! ").repeat(times).trim_end().to_string())
+  /// });
+  /// ```
+  #[cfg(feature = "serde")]
+  #[cfg_attr(docsrs, doc(cfg(feature = "serde")))]
+  pub fn with_typed_command<T, R, F, E>(self, name: impl Into<String>, handler: F) -> Self
+  where
+    T: serde::de::DeserializeOwned,
+    R: serde::Serialize,
+    F: Fn(Request<T>) -> Result<R, E> + 'static,
+    E: Into<Box<dyn Error>>,
+  {
+    self.with_command(name, move |request: Request<String>| {
+      let (parts, payload) = request.into_parts();
+      let payload = serde_json::from_str(&payload)?;
+      let value = handler(Request::from_parts(parts, payload)).map_err(Into::into)?;
+      Ok::<_, Box<dyn Error>>(serde_json::to_string(&value)?)
+    })
+  }
+
+  /// Whether the `name` command has a handler.
+  pub fn has_command(&self, name: &str) -> bool {
+    self.commands.contains_key(name)
+  }
+
+  /// Runs the handler of an `invoke` message, `command:payload` with a percent-encoded command,
+  /// if the `policy` allows it.
+  pub(crate) fn invoke(
+    &self,
+    request: Request<String>,
+    policy: Option<&IpcPolicy>,
+  ) -> Result<String, String> {
+    let (parts, message) = request.into_parts();
+    let Some((command, payload)) = message.split_once(':') else {
+      return Err("malformed IPC invoke message".into());
+    };
+    let command = percent_decode_str(command).decode_utf8_lossy();
+
+    #[cfg(feature = "tracing")]
+    let _span = tracing::info_span!("wry::ipc::invoke", command = %command).entered();
+
+    let request = Request::from_parts(parts, payload.to_string());
+    if policy.is_some_and(|policy| !policy.allows(&request, Some(&command))) {
+      return Err(format!("IPC command `{command}` is not allowed"));
+    }
+    let Some(handler) = self.commands.get(&*command) else {
+      return Err(format!("unknown IPC command `{command}`"));
+    };
+    handler(request).map_err(|e| e.to_string())
+  }
+}
+
+/// Passes the messages of `window.ipc.postMessage` to the IPC handler and the `invoke` calls
+/// to the [`IpcRouter`].
+pub(crate) struct IpcDispatcher {
+  handler: Option<Box<dyn Fn(Request<IpcBody>)>>,
+  router: Option<IpcRouter>,
+  policy: Option<IpcPolicy>,
+}
+
+impl IpcDispatcher {
+  /// Creates a dispatcher dropping the messages the `policy` denies, if any.
+  pub(crate) fn new(
+    handler: Option<Box<dyn Fn(Request<IpcBody>)>>,
+    router: Option<IpcRouter>,
+    policy: Option<IpcPolicy>,
+  ) -> Option<Self> {
+    (handler.is_some() || router.is_some()).then_some(Self {
+      handler,
+      router,
+      policy,
+    })
+  }
+
+  /// Dispatches a message, returning the script that settles the promise if it is an `invoke` call.
+  pub(crate) fn dispatch(&self, request: Request<String>) -> Option<String> {
+    if let Some(text) = request.body().strip_prefix(TEXT_PREFIX) {
+      let text = text.to_string();
+      self.handle(request.map(|_| IpcBody::Text(text)));
+      return None;
+    }
+
+    if let Some(router) = &self.router {
+      let invoke = request
+        .body()
+        .strip_prefix(INVOKE_PREFIX)
+        .and_then(|message| message.split_once(':'))
+        .filter(|(id, _)| !id.is_empty() && id.bytes().all(|b| b.is_ascii_alphanumeric()))
+        .map(|(id, message)| (id.to_string(), message.to_string()));
+      if let Some((id, message)) = invoke {
+        let (parts, _) = request.into_parts();
+        let result = router.invoke(Request::from_parts(parts, message), self.policy.as_ref());
+        return Some(reply_script(&id, &result));
+      }
+    }
+
+    #[cfg(not(linux))]
+    if let Some(encoded) = request.body().strip_prefix(BINARY_PREFIX) {
+      match general_purpose::STANDARD.decode(encoded) {
+        Ok(bytes) => {
+          self.dispatch_binary(request.map(|_| bytes));
+          return None;
+        }
+        Err(_error) => {
+          #[cfg(feature = "tracing")]
+          tracing::warn!("malformed binary IPC message: {_error}");
+        }
+      }
+    }
+
+    self.handle(request.map(IpcBody::Text));
+    None
+  }
+
+  /// Dispatches a binary message received as bytes.
+  pub(crate) fn dispatch_binary(&self, request: Request<Vec<u8>>) {
+    self.handle(request.map(IpcBody::Binary));
+  }
+
+  fn handle(&self, request: Request<IpcBody>) {
+    if let Some(handler) = &self.handler {
+      if let Some(policy) = &self.policy {
+        if !policy.allows(&request, None) {
+          return;
+        }
+      }
+      handler(request);
+    }
+  }
+}
+
+/// The script defining `window.ipc`, with the `post_message` function posting a string, or a
+/// `Uint8Array` if `native_binary`, to the host.
+///
+/// The `invoke` promises are settled by the scripts returned by [`IpcDispatcher::dispatch`],
+/// evaluated in the main frame, so the calls of subframes are rejected.
+#[cfg_attr(all(gtk, feature = "linux-body"), allow(dead_code))]
+pub(crate) fn ipc_script(post_message: &str, native_binary: bool) -> String {
+  let send = format!(
+    "(function () {{
+  var pending = {{}};
+  Object.defineProperty(window, '{REPLY_FUNCTION}', {{ value: function (id, ok, value) {{
+    var promise = pending[id];
+    if (promise) {{ delete pending[id]; (ok ? promise.resolve : promise.reject)(value); }}
+  }} }});
+  return function (message) {{
+    return new Promise(function (resolve, reject) {{
+      // the replies are evaluated in the main frame
+      if (window !== window.top) {{
+        throw new Error('IPC invoke calls are not supported in subframes');
+      }}
+      var id = {INVOKE_ID};
+      pending[id] = {{ resolve: resolve, reject: reject }};
+      post('{INVOKE_PREFIX}' + id + ':' + message);
+    }});
+  }};
+}})()"
+  );
+  ipc_script_with_reply(post_message, native_binary, &send)
+}
+
+/// The script defining `window.ipc`, with the `send` function posting an `invoke` message to
+/// the host and returning a promise of the reply.
+pub(crate) fn ipc_script_with_reply(post_message: &str, native_binary: bool, send: &str) -> String {
+  // the message handlers of other platforms only take strings
+  let encode = if native_binary {
+    String::new()
+  } else {
+    format!(
+      "var binary = '';
+      for (var i = 0; i < message.length; i += 0x8000) {{
+        binary += String.fromCharCode.apply(null, message.subarray(i, i + 0x8000));
+      }}
+      message = '{BINARY_PREFIX}' + btoa(binary);"
+    )
+  };
+  format!(
+    "Object.defineProperty(window, 'ipc', {{ value: (function () {{
+  var post = {post_message};
+  var postMessage = function (message) {{
+    if (message instanceof ArrayBuffer) {{
+      message = new Uint8Array(message);
+    }} else if (ArrayBuffer.isView(message)) {{
+      message = new Uint8Array(message.buffer, message.byteOffset, message.byteLength);
+    }}
+    if (message instanceof Uint8Array) {{
+      {encode}
+    }} else if (typeof message === 'string' && message.lastIndexOf('{RESERVED_PREFIX}', 0) === 0) {{
+      message = '{TEXT_PREFIX}' + message;
+    }}
+    post(message);
+  }};
+  var send = {send};
+  return Object.freeze({{
+    postMessage: postMessage,
+    invoke: function (command, payload) {{
+      var message = encodeURIComponent(command) + ':' + JSON.stringify(payload === undefined ? null : payload);
+      return send(message).then(JSON.parse, function (e) {{ throw e instanceof Error ? e : new Error(e); }});
+    }}
+  }});
+}})() }});"
+  )
+}
+
+/// Serializes `value` to a JavaScript expression, to embed Rust values in the scripts passed to
+/// [`WebView::evaluate_script`](crate::WebView::evaluate_script).
+///
+/// # Examples
+///
+/// ```no_run
+/// # fn run(webview: &wry::WebView) -> wry::Result<()> {
+/// let items = vec!["a", "b"];
+/// webview.evaluate_script(&format!("render({})", wry::ipc::to_js(&items)?))?;
+/// # Ok(())
+/// # }
+/// ```
+#[cfg(feature = "serde")]
+#[cfg_attr(docsrs, doc(cfg(feature = "serde")))]
+pub fn to_js<T: serde::Serialize + ?Sized>(value: &T) -> crate::Result<String> {
+  // JSON is a subset of JavaScript since ES2019, which covers every webview wry supports
+  Ok(serde_json::to_string(value)?)
+}
+
+/// Whether a message of `window.ipc.postMessage` is an `invoke` call.
+#[cfg(any(
+  target_os = "macos",
+  target_os = "ios",
+  all(gtk, not(feature = "linux-body"))
+))]
+pub(crate) fn is_invoke(message: &str) -> bool {
+  message.starts_with(INVOKE_PREFIX)
+}
+
+/// The script settling the promise of the `invoke` call `id`.
+fn reply_script(id: &str, result: &Result<String, String>) -> String {
+  let (ok, value) = match result {
+    Ok(value) => (true, value),
+    Err(error) => (false, error),
+  };
+  format!(
+    "window.{REPLY_FUNCTION} && window.{REPLY_FUNCTION}({}, {ok}, {})",
+    json_string(id),
+    json_string(value)
+  )
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn request(body: &str) -> Request<String> {
+    Request::builder()
+      .uri("app://localhost/")
+      .body(body.to_string())
+      .unwrap()
+  }
+
+  #[test]
+  fn dispatches_invoke_calls() {
+    use std::{cell::RefCell, rc::Rc};
+
+    let messages = Rc::new(RefCell::new(Vec::new()));
+    let messages_ = messages.clone();
+    let router = IpcRouter::new()
+      .with_command("echo", |request| Ok::<_, String>(request.into_body()))
+      .with_command("fail", |_| Err("\"nope\""));
+    let dispatcher = IpcDispatcher::new(
+      Some(Box::new(move |request: Request<IpcBody>| {
+        messages_.borrow_mut().push(request.into_body())
+      })),
+      Some(router),
+      None,
+    )
+    .unwrap();
+
+    assert_eq!(
+      dispatcher.dispatch(request("__WRY_INVOKE__0a1:echo:{\"a\":1}")),
+      Some(
+        r#"window.__WRY_IPC_REPLY__ && window.__WRY_IPC_REPLY__("0a1", true, "{\"a\":1}")"#.into()
+      )
+    );
+    assert_eq!(
+      dispatcher.dispatch(request("__WRY_INVOKE__0a2:fail:null")),
+      Some(
+        r#"window.__WRY_IPC_REPLY__ && window.__WRY_IPC_REPLY__("0a2", false, "\"nope\"")"#.into()
+      )
+    );
+    assert_eq!(
+      dispatcher.dispatch(request("__WRY_INVOKE__0a3:a%3Ab:null")),
+      Some(
+        r#"window.__WRY_IPC_REPLY__ && window.__WRY_IPC_REPLY__("0a3", false, "unknown IPC command `a:b`")"#
+          .into()
+      )
+    );
+
+    // other messages go to the IPC handler
+    assert_eq!(dispatcher.dispatch(request("hello")), None);
+    assert_eq!(
+      dispatcher.dispatch(request("__WRY_INVOKE__x-y:echo:1")),
+      None
+    );
+    // text messages starting with a prefix are escaped
+    assert_eq!(
+      dispatcher.dispatch(request("__WRY_TEXT____WRY_INVOKE__0a4:echo:1")),
+      None
+    );
+    assert_eq!(
+      dispatcher.dispatch(request("__WRY_TEXT____WRY_BINARY__AP8=")),
+      None
+    );
+    assert_eq!(
+      messages.take(),
+      [
+        IpcBody::Text("hello".into()),
+        IpcBody::Text("__WRY_INVOKE__x-y:echo:1".into()),
+        IpcBody::Text("__WRY_INVOKE__0a4:echo:1".into()),
+        IpcBody::Text("__WRY_BINARY__AP8=".into()),
+      ]
+    );
+
+    #[cfg(not(linux))]
+    {
+      assert_eq!(dispatcher.dispatch(request("__WRY_BINARY__AP8=")), None);
+      assert_eq!(messages.take(), [IpcBody::Binary(vec![0, 255])]);
+    }
+  }
+
+  #[test]
+  fn dispatches_subframe_messages() {
+    use std::{cell::RefCell, rc::Rc};
+
+    let received = Rc::new(RefCell::new(Vec::new()));
+    let received_ = received.clone();
+    let router = IpcRouter::new().with_command("where", |request| {
+      Ok::<_, String>(format!("\"{}\"", request.uri()))
+    });
+    let policy = IpcPolicy::new()
+      .allow_custom_protocol("app")
+      .allow_origin_commands("https://widget.example", ["where"])
+      .resolve(crate::custom_protocol_url::UrlStyle::Custom);
+    let dispatcher = IpcDispatcher::new(
+      Some(Box::new(move |request: Request<IpcBody>| {
+        let frame = request.extensions().get::<IpcFrame>().cloned();
+        received_
+          .borrow_mut()
+          .push((request.uri().to_string(), frame))
+      })),
+      Some(router),
+      Some(policy),
+    )
+    .unwrap();
+
+    let main = IpcFrame {
+      url: "app://localhost/".into(),
+      origin: "app://localhost".into(),
+      is_main_frame: true,
+    };
+    let widget = IpcFrame {
+      url: "https://widget.example/embed".into(),
+      origin: "https://widget.example".into(),
+      is_main_frame: false,
+    };
+
+    // the request is the one of the frame that sent the message
+    assert_eq!(
+      dispatcher.dispatch(widget.clone().request("__WRY_INVOKE__1:where:null".into())),
+      Some(
+        r#"window.__WRY_IPC_REPLY__ && window.__WRY_IPC_REPLY__("1", true, "\"https://widget.example/embed\"")"#
+          .into()
+      )
+    );
+    assert_eq!(
+      dispatcher.dispatch(main.clone().request("hello".into())),
+      None
+    );
+    assert_eq!(
+      received.take(),
+      [("app://localhost/".to_string(), Some(main))]
+    );
+
+    // the policy of the subframe origin applies
+    assert_eq!(dispatcher.dispatch(widget.request("hello".into())), None);
+    assert!(received.take().is_empty());
+  }
+}
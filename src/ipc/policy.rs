@@ -0,0 +1,222 @@
+// Copyright 2020-2024 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+use std::{borrow::Cow, collections::HashSet};
+
+use http::Request;
+
+use super::IpcFrame;
+use crate::custom_protocol_url::UrlStyle;
+
+/// Restricts the IPC messages to the frames of some origins, and their `invoke` calls to some
+/// commands.
+///
+/// A message is allowed if a rule matches the origin of the [`IpcFrame`] that sent it. The
+/// messages of `window.ipc.postMessage` need a rule allowing every command. Denied messages
+/// are dropped before the IPC handler runs and logged with the `tracing` feature, denied
+/// `invoke` calls are rejected.
+///
+/// # Examples
+///
+/// ```no_run
+/// use wry::{ipc::IpcPolicy, WebViewBuilder};
+///
+/// let policy = IpcPolicy::new()
+///   // the app itself
+///   .allow_custom_protocol("app")
+///   // an embedded widget, which may only call `resize`
+///   .allow_origin_commands("https://widget.example.com", ["resize"]);
+///
+/// let builder = WebViewBuilder::new()
+///   .with_url("app://localhost")
+///   .with_ipc_policy(policy);
+/// ```
+///
+/// ## Platform-specific
+///
+/// - **Android**: The frame that sent a message is unknown, so the origin of the main frame is
+///   checked instead.
+#[derive(Debug, Clone, Default)]
+pub struct IpcPolicy {
+  rules: Vec<Rule>,
+  /// How the pages of custom protocols see their URLs, set when the webview is built.
+  url_style: Option<UrlStyle>,
+}
+
+#[derive(Debug, Clone)]
+struct Rule {
+  source: Source,
+  /// The commands that may be invoked, or `None` for every command and `window.ipc.postMessage`.
+  commands: Option<HashSet<String>>,
+}
+
+#[derive(Debug, Clone)]
+enum Source {
+  Origin(String),
+  CustomProtocol(String),
+}
+
+impl IpcPolicy {
+  /// Creates a policy denying every message.
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Allows every message of the frames of `origin`, for example `https://example.com`.
+  pub fn allow_origin(self, origin: impl Into<String>) -> Self {
+    self.rule(Source::Origin(normalize(origin.into())), None)
+  }
+
+  /// Allows every message of the pages of the custom protocol `scheme`, whatever host and URL
+  /// they have on the platform, such as `app://localhost` and `app://assets`.
+  pub fn allow_custom_protocol(self, scheme: impl Into<String>) -> Self {
+    self.rule(Source::CustomProtocol(scheme.into()), None)
+  }
+
+  /// Allows the frames of `origin` to invoke `commands`, and nothing else.
+  pub fn allow_origin_commands<I, S>(self, origin: impl Into<String>, commands: I) -> Self
+  where
+    I: IntoIterator<Item = S>,
+    S: Into<String>,
+  {
+    let commands = commands.into_iter().map(Into::into).collect();
+    self.rule(Source::Origin(normalize(origin.into())), Some(commands))
+  }
+
+  /// Allows the pages of the custom protocol `scheme` to invoke `commands`, and nothing else.
+  pub fn allow_custom_protocol_commands<I, S>(self, scheme: impl Into<String>, commands: I) -> Self
+  where
+    I: IntoIterator<Item = S>,
+    S: Into<String>,
+  {
+    let commands = commands.into_iter().map(Into::into).collect();
+    self.rule(Source::CustomProtocol(scheme.into()), Some(commands))
+  }
+
+  fn rule(mut self, source: Source, commands: Option<HashSet<String>>) -> Self {
+    self.rules.push(Rule { source, commands });
+    self
+  }
+
+  /// Matches the custom protocol rules on the origins their pages have on the platform.
+  pub(crate) fn resolve(mut self, url_style: UrlStyle) -> Self {
+    self.url_style = Some(url_style);
+    self
+  }
+
+  /// Whether the frame of `request` may post a message, or invoke `command`.
+  pub(crate) fn allows<T>(&self, request: &Request<T>, command: Option<&str>) -> bool {
+    let origin = match request.extensions().get::<IpcFrame>() {
+      Some(frame) => Cow::Borrowed(frame.origin.as_str()),
+      None => Cow::Owned(
+        url::Url::parse(&request.uri().to_string())
+          .map(|url| url.origin().ascii_serialization())
+          .unwrap_or_else(|_| "null".into()),
+      ),
+    };
+
+    let allowed = self
+      .rules
+      .iter()
+      .filter(|rule| self.matches(&rule.source, &origin))
+      .any(|rule| match (&rule.commands, command) {
+        (None, _) => true,
+        (Some(commands), Some(command)) => commands.contains(command),
+        (Some(_), None) => false,
+      });
+
+    #[cfg(feature = "tracing")]
+    if !allowed {
+      tracing::warn!(
+        origin = %origin,
+        uri = %request.uri(),
+        command,
+        "IPC message denied by the IPC policy"
+      );
+    }
+
+    allowed
+  }
+
+  /// Whether the frames of `origin` match `source`.
+  fn matches(&self, source: &Source, origin: &str) -> bool {
+    match source {
+      Source::Origin(o) => o == origin,
+      Source::CustomProtocol(protocol) => {
+        let Some((scheme, host)) = origin.split_once("://") else {
+          return false;
+        };
+        match self.url_style.unwrap_or(UrlStyle::Custom).scheme() {
+          // `<protocol>://<host>`
+          None => scheme.eq_ignore_ascii_case(protocol),
+          // `http://<protocol>.<host>`
+          Some(workaround) => {
+            scheme == workaround
+              && host
+                .get(..protocol.len())
+                .is_some_and(|prefix| prefix.eq_ignore_ascii_case(protocol))
+              && host[protocol.len()..].starts_with('.')
+          }
+        }
+      }
+    }
+  }
+}
+
+/// `https://Example.com/` to `https://example.com`, as origins are serialized.
+fn normalize(origin: String) -> String {
+  origin.trim_end_matches('/').to_ascii_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn request(origin: &str, is_main_frame: bool) -> Request<()> {
+    IpcFrame {
+      url: format!("{origin}/index.html"),
+      origin: origin.to_string(),
+      is_main_frame,
+    }
+    .request(())
+  }
+
+  #[test]
+  fn matches_origins_and_commands() {
+    let policy = IpcPolicy::new()
+      .allow_custom_protocol("app")
+      .allow_origin("https://Example.com/")
+      .allow_origin_commands("https://widget.example.com", ["resize"])
+      .resolve(UrlStyle::Http);
+
+    assert!(policy.allows(&request("http://app.localhost", true), None));
+    assert!(policy.allows(&request("http://app.localhost", true), Some("quit")));
+    assert!(policy.allows(&request("http://app.assets", false), None));
+    assert!(!policy.allows(&request("https://app.localhost", true), None));
+    assert!(!policy.allows(&request("http://application.localhost", true), None));
+    assert!(policy.allows(&request("https://example.com", false), None));
+
+    let widget = request("https://widget.example.com", false);
+    assert!(policy.allows(&widget, Some("resize")));
+    assert!(!policy.allows(&widget, Some("quit")));
+    assert!(!policy.allows(&widget, None));
+
+    assert!(!policy.allows(&request("app://localhost", true), None));
+    assert!(!policy.allows(&request("null", false), None));
+
+    // without a frame, the origin of the request URI is used
+    let without_frame = Request::builder()
+      .uri("https://example.com:443/page")
+      .body(())
+      .unwrap();
+    assert!(policy.allows(&without_frame, None));
+
+    let policy = IpcPolicy::new()
+      .allow_custom_protocol("app")
+      .resolve(UrlStyle::Custom);
+    assert!(policy.allows(&request("app://localhost", true), None));
+    assert!(policy.allows(&request("app://other", false), None));
+    assert!(!policy.allows(&request("http://app.localhost", true), None));
+  }
+}
@@ -166,7 +166,12 @@
 //! - `os-webview` (default): Enables the default WebView framework on the platform. This must be enabled
 //! for the crate to work. This feature was added in preparation of other ports like cef and servo.
 //! - `protocol` (default): Enables [`WebViewBuilder::with_custom_protocol`] to define custom URL scheme for handling tasks like
-//! loading assets.
+//! loading assets, and the ready-made handlers in [`custom_protocol`].
+//! - `embed`: Embeds directories in the binary from build scripts, see [`custom_protocol::Embedded`].
+//! - `embed-gzip`, `embed-brotli`: Compress the embedded files with gzip or brotli.
+//! - `zip`: Enables `custom_protocol::Archive` to serve the entries of a zip archive.
+//! - `tower`: Enables `WebViewBuilder::with_tower_custom_protocol` to serve custom protocols with `tower` services.
+//! - `html`: Enables the `html` module to inject scripts into HTML documents, and `WebViewBuilderExtUnix::with_custom_protocol_script_injection` on Linux.
 //! - `drag-drop` (default): Enables [`WebViewBuilder::with_drag_drop_handler`] to control the behaviour when there are files
 //! interacting with the window.
 //! - `devtools`: Enables devtools on release builds. Devtools are always enabled in debug builds.
@@ -179,6 +184,7 @@
 //! libraries and prevent from building documentation on doc.rs fails.
 //! - `linux-body`: Enables body support of custom protocol request on Linux. Requires
 //! webkit2gtk v2.40 or above.
+//! - `serde`: Enables `WebViewBuilder::with_typed_ipc_handler`, `IpcRouter::with_typed_command` and `WebView::evaluate_function` to exchange `serde` values with the webview as JSON.
 //! - `tracing`: enables [`tracing`] for `evaluate_script`, `ipc_handler` and `custom_protocols.
 //!
 //! [`tao`]: https://docs.rs/tao
@@ -194,9 +200,19 @@
 // #[macro_use]
 // extern crate objc;
 
+mod cancellation;
+#[cfg(feature = "protocol")]
+#[cfg_attr(docsrs, doc(cfg(feature = "protocol")))]
+pub mod custom_protocol;
+#[cfg(any(gtk, target_os = "macos", target_os = "ios", target_os = "android"))]
+mod custom_protocol_redirect;
+mod custom_protocol_url;
 mod error;
+#[cfg(any(feature = "html", target_os = "android"))]
+#[cfg_attr(docsrs, doc(cfg(feature = "html")))]
+pub mod html;
+pub mod ipc;
 mod proxy;
-#[cfg(any(target_os = "macos", target_os = "android", target_os = "ios"))]
 mod util;
 mod web_context;
 
@@ -220,6 +236,8 @@ pub(crate) mod webkitgtk;
 pub use raw_window_handle;
 use raw_window_handle::HasWindowHandle;
 #[cfg(gtk)]
+pub use webkitgtk::RequestBody;
+#[cfg(gtk)]
 use webkitgtk::*;
 
 #[cfg(any(target_os = "macos", target_os = "ios"))]
@@ -244,10 +262,11 @@ use self::webview2::*;
 #[cfg(target_os = "windows")]
 use webview2_com::Microsoft::Web::WebView2::Win32::ICoreWebView2Controller;
 
-use std::{borrow::Cow, collections::HashMap, path::PathBuf, rc::Rc};
+use std::{borrow::Cow, collections::HashMap, fmt, io::Read, path::PathBuf, rc::Rc};
 
 use http::{Request, Response};
 
+pub use cancellation::{CancellationToken, Cancelled};
 pub use cookie;
 pub use dpi;
 pub use error::*;
@@ -273,11 +292,101 @@ impl Default for Rect {
   }
 }
 
+/// The body of a custom protocol response.
+pub enum ResponseBody {
+  /// A body that is already fully in memory.
+  Bytes(Cow<'static, [u8]>),
+  /// A body that is read incrementally while the webview consumes it.
+  Reader(Box<dyn Read + Send>),
+}
+
+impl ResponseBody {
+  /// Creates a body that is read incrementally from `reader`.
+  pub fn from_reader<R: Read + Send + 'static>(reader: R) -> Self {
+    Self::Reader(Box::new(reader))
+  }
+
+  /// Reads the whole body into memory.
+  pub fn into_bytes(self) -> std::io::Result<Cow<'static, [u8]>> {
+    match self {
+      Self::Bytes(bytes) => Ok(bytes),
+      Self::Reader(mut reader) => {
+        let mut buffer = Vec::new();
+        reader.read_to_end(&mut buffer)?;
+        Ok(Cow::Owned(buffer))
+      }
+    }
+  }
+}
+
+impl fmt::Debug for ResponseBody {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Self::Bytes(bytes) => f.debug_tuple("Bytes").field(&bytes.len()).finish(),
+      Self::Reader(_) => f.debug_tuple("Reader").finish(),
+    }
+  }
+}
+
+impl From<Cow<'static, [u8]>> for ResponseBody {
+  fn from(bytes: Cow<'static, [u8]>) -> Self {
+    Self::Bytes(bytes)
+  }
+}
+
+impl From<Vec<u8>> for ResponseBody {
+  fn from(bytes: Vec<u8>) -> Self {
+    Self::Bytes(Cow::Owned(bytes))
+  }
+}
+
+impl From<&'static [u8]> for ResponseBody {
+  fn from(bytes: &'static [u8]) -> Self {
+    Self::Bytes(Cow::Borrowed(bytes))
+  }
+}
+
+impl From<String> for ResponseBody {
+  fn from(string: String) -> Self {
+    Self::Bytes(Cow::Owned(string.into_bytes()))
+  }
+}
+
+impl From<&'static str> for ResponseBody {
+  fn from(string: &'static str) -> Self {
+    Self::Bytes(Cow::Borrowed(string.as_bytes()))
+  }
+}
+
+/// Reads a streamed response body into memory, for webviews that need the whole body upfront.
+///
+/// Failing to read the body results in a `500 Internal Server Error` response.
+#[cfg(any(
+  target_os = "windows",
+  target_os = "android",
+  all(gtk, feature = "html")
+))]
+pub(crate) fn buffer_response(response: Response<ResponseBody>) -> Response<Cow<'static, [u8]>> {
+  let (parts, body) = response.into_parts();
+  match body.into_bytes() {
+    Ok(body) => Response::from_parts(parts, body),
+    Err(_e) => {
+      #[cfg(feature = "tracing")]
+      tracing::error!("failed to read custom protocol response body: {_e}");
+      Response::builder()
+        .status(http::StatusCode::INTERNAL_SERVER_ERROR)
+        .body(Cow::Borrowed(&[][..]))
+        .unwrap()
+    }
+  }
+}
+
 /// Resolves a custom protocol [`Request`] asynchronously.
 ///
 /// See [`WebViewBuilder::with_asynchronous_custom_protocol`] for more information.
 pub struct RequestAsyncResponder {
-  pub(crate) responder: Box<dyn FnOnce(Response<Cow<'static, [u8]>>)>,
+  pub(crate) responder: Box<dyn FnOnce(Response<ResponseBody>)>,
+  pub(crate) cancellation: CancellationToken,
 }
 
 // SAFETY: even though the webview bindings do not indicate the responder is Send,
@@ -286,10 +395,110 @@ pub struct RequestAsyncResponder {
 unsafe impl Send for RequestAsyncResponder {}
 
 impl RequestAsyncResponder {
+  /// The token signaling that the webview cancelled this request, so the response doesn't need to be computed anymore.
+  ///
+  /// See [`CancellationToken`] for the platform-specific behavior.
+  ///
+  /// # Examples
+  ///
+  /// ```no_run
+  /// use wry::{http::Response, WebViewBuilder};
+  ///
+  /// WebViewBuilder::new()
+  ///   .with_asynchronous_custom_protocol("wry".into(), |_webview_id, _request, responder| {
+  ///     std::thread::spawn(move || {
+  ///       let cancellation = responder.cancellation().clone();
+  ///       let mut report = String::new();
+  ///       for step in 0..100 {
+  ///         if cancellation.is_cancelled() {
+  ///           return;
+  ///         }
+  ///         report.push_str(&format!("step {step}\n"));
+  ///       }
+  ///       responder.respond(Response::new(report.into_bytes()));
+  ///     });
+  ///   });
+  /// ```
+  pub fn cancellation(&self) -> &CancellationToken {
+    &self.cancellation
+  }
+
   /// Resolves the request with the given response.
+  ///
+  /// This is a no-op if the request was [cancelled](Self::cancellation).
   pub fn respond<T: Into<Cow<'static, [u8]>>>(self, response: Response<T>) {
     let (parts, body) = response.into_parts();
-    (self.responder)(Response::from_parts(parts, body.into()))
+    self.respond_with_body(Response::from_parts(
+      parts,
+      ResponseBody::Bytes(body.into()),
+    ))
+  }
+
+  /// Resolves the request with a response whose body is read incrementally from the given reader,
+  /// so large or slowly produced bodies don't have to be held in memory.
+  ///
+  /// Set the `Content-Length` header if the body length is known upfront.
+  ///
+  /// ## Platform-specific
+  ///
+  /// - **Linux**: The reader backs the [`gio::InputStream`](https://docs.rs/gio/latest/gio/struct.InputStream.html)
+  ///   WebKit reads the response from, on a worker thread.
+  /// - **macOS / iOS**: The body is read in chunks on the calling thread and each chunk is sent to the webview as soon as it is read.
+  /// - **Windows / Android**: Unsupported, the whole body is read into memory before the webview receives the response.
+  pub fn respond_with_reader<R: Read + Send + 'static>(self, response: Response<R>) {
+    self.respond_with_body(response.map(ResponseBody::from_reader))
+  }
+
+  /// Resolves the request with the given response body, which can either be buffered or streamed.
+  ///
+  /// See [`Self::respond_with_reader`] for the platform-specific behavior of streamed bodies.
+  /// Like [`Self::respond`], this is a no-op if the request was cancelled.
+  pub fn respond_with_body(self, response: Response<ResponseBody>) {
+    if self.cancellation.finish() {
+      (self.responder)(response)
+    }
+  }
+}
+
+/// Privileges of a custom protocol scheme, set with [`WebViewBuilder::with_custom_protocol_options`].
+///
+/// ## Platform-specific
+///
+/// - **Linux**: Maps to the WebKit [`SecurityManager`](https://webkitgtk.org/reference/webkit2gtk/stable/class.SecurityManager.html).
+///   WebKit can't revoke privileges, so they apply to every webview of the [`WebContext`] once granted.
+/// - **macOS / iOS**: Unsupported, WebKit treats custom schemes as secure and applies the usual CORS checks to them.
+/// - **Windows / Android**: Unsupported, custom protocols are served from regular `http(s)://<scheme>.localhost` origins,
+///   use [`WebViewBuilderExtWindows::with_https_scheme`] and [`WebViewBuilderExtAndroid::with_https_scheme`] to make them secure.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CustomProtocolOptions {
+  /// Whether pages of other origins can load the scheme with CORS requests,
+  /// for example with `fetch()` from `https://` pages. Defaults to `false`.
+  pub cors_enabled: bool,
+  /// Whether the scheme is treated as local, like `file://`,
+  /// so only local pages can load or link to it. Defaults to `false`.
+  pub local: bool,
+  /// Whether pages loaded from the scheme get a unique opaque origin,
+  /// so they can't access any other page. Defaults to `false`.
+  pub no_access: bool,
+  /// Whether only pages loaded from the same scheme can display its content. Defaults to `false`.
+  pub display_isolated: bool,
+  /// Whether loading the scheme synchronously returns an empty document, like `about:blank`. Defaults to `false`.
+  pub empty_document: bool,
+  /// Whether pages loaded from the scheme are secure contexts,
+  /// so they can use secure-only web APIs and aren't flagged for mixed content. Defaults to `true`.
+  pub secure: bool,
+}
+
+impl Default for CustomProtocolOptions {
+  fn default() -> Self {
+    Self {
+      cors_enabled: false,
+      local: false,
+      no_access: false,
+      display_isolated: false,
+      empty_document: false,
+      secure: true,
+    }
   }
 }
 
@@ -374,7 +583,7 @@ pub struct WebViewAttributes<'a> {
   ///
   /// # Note
   ///
-  /// If using a shared [WebContext], make sure custom protocols were not already registered on that web context on Linux.
+  /// Webviews sharing a [WebContext] can register the same scheme, each request is handled by the handler of the requesting webview.
   ///
   /// # Warning
   ///
@@ -386,6 +595,20 @@ pub struct WebViewAttributes<'a> {
   /// - macOS, iOS and Linux: `<scheme_name>://<path>` (so it will be `wry://path/to/page/`).
   /// - Windows and Android: `http://<scheme_name>.<path>` by default (so it will be `http://wry.path/to/page). To use `https` instead of `http`, use [`WebViewBuilderExtWindows::with_https_scheme`] and [`WebViewBuilderExtAndroid::with_https_scheme`].
   ///
+  /// [`WebView::custom_protocol_url`] and [`WebView::custom_protocol_origin`] return the right form for the current platform.
+  ///
+  /// # Redirects
+  ///
+  /// A `301`, `302`, `303`, `307` or `308` response to a page navigation loads its `Location` instead,
+  /// which may be relative or use the `<scheme_name>://` form on every platform. The navigation handler is
+  /// called for the target and the page load handler reports the final URL. A navigation that is redirected
+  /// more than 20 times or back to a URL of its redirects gets a `508 Loop Detected` response instead.
+  ///
+  /// ## Platform-specific
+  ///
+  /// - **Linux, macOS, iOS and Android:** Only the redirects of the main document are followed.
+  /// - **Windows:** WebView2 follows all redirects by itself and shows its own error page when they loop.
+  ///
   /// # Reading assets on mobile
   ///
   /// - Android: Android has `assets` and `resource` path finder to
@@ -394,9 +617,40 @@ pub struct WebViewAttributes<'a> {
   pub custom_protocols:
     HashMap<String, Box<dyn Fn(WebViewId, Request<Vec<u8>>, RequestAsyncResponder)>>,
 
+  /// The privileges of custom protocol schemes, by scheme name.
+  ///
+  /// Schemes without an entry use [`CustomProtocolOptions::default`].
+  /// See [`CustomProtocolOptions`] for the platform-specific behavior.
+  #[cfg(feature = "protocol")]
+  pub custom_protocol_options: HashMap<String, CustomProtocolOptions>,
+
+  /// The CORS policies of custom protocols, by scheme name.
+  ///
+  /// See [`WebViewBuilder::with_custom_protocol_cors`] for more information.
+  #[cfg(feature = "protocol")]
+  pub custom_protocol_cors: HashMap<String, custom_protocol::cors::CorsPolicy>,
+
+  /// Records the requests and responses of all custom protocols.
+  ///
+  /// See [`WebViewBuilder::with_custom_protocol_recorder`] for more information.
+  #[cfg(feature = "protocol")]
+  pub custom_protocol_recorder: Option<custom_protocol::har::HarRecorder>,
+
   /// The IPC handler to receive the message from Javascript on webview
   /// using `window.ipc.postMessage("insert_message_here")` to host Rust code.
-  pub ipc_handler: Option<Box<dyn Fn(Request<String>)>>,
+  ///
+  /// See [`WebViewBuilder::with_ipc_body_handler`] for more information.
+  pub ipc_handler: Option<Box<dyn Fn(Request<ipc::IpcBody>)>>,
+
+  /// The router of the `window.ipc.invoke` calls.
+  ///
+  /// See [`WebViewBuilder::with_ipc_router`] for more information.
+  pub ipc_router: Option<ipc::IpcRouter>,
+
+  /// The policy restricting which frames may use IPC.
+  ///
+  /// See [`WebViewBuilder::with_ipc_policy`] for more information.
+  pub ipc_policy: Option<ipc::IpcPolicy>,
 
   /// A handler closure to process incoming [`DragDropEvent`] of the webview.
   ///
@@ -534,7 +788,15 @@ impl<'a> Default for WebViewAttributes<'a> {
       html: None,
       initialization_scripts: Default::default(),
       custom_protocols: Default::default(),
+      #[cfg(feature = "protocol")]
+      custom_protocol_options: Default::default(),
+      #[cfg(feature = "protocol")]
+      custom_protocol_cors: Default::default(),
+      #[cfg(feature = "protocol")]
+      custom_protocol_recorder: None,
       ipc_handler: None,
+      ipc_router: None,
+      ipc_policy: None,
       drag_drop_handler: None,
       navigation_handler: None,
       download_started_handler: None,
@@ -567,6 +829,80 @@ struct WebviewBuilderParts<'a> {
   platform_specific: PlatformSpecificWebViewAttributes,
 }
 
+impl WebviewBuilderParts<'_> {
+  /// Wraps the custom protocol handlers with their CORS policy, then with the recorder.
+  ///
+  /// Fails with [`Error::UnknownCustomProtocol`] if options or a CORS policy target a scheme without a handler.
+  #[allow(unused_mut)]
+  fn apply_custom_protocol_wrappers(mut self) -> Result<Self> {
+    #[cfg(feature = "protocol")]
+    if let Some(name) = self
+      .attrs
+      .custom_protocol_options
+      .keys()
+      .chain(self.attrs.custom_protocol_cors.keys())
+      .find(|name| !self.has_custom_protocol(name))
+    {
+      return Err(Error::UnknownCustomProtocol(name.clone()));
+    }
+
+    #[cfg(feature = "protocol")]
+    for (name, policy) in std::mem::take(&mut self.attrs.custom_protocol_cors) {
+      if let Some(handler) = self.attrs.custom_protocols.remove(&name) {
+        let handler = Box::new(policy.wrap(handler));
+        self.attrs.custom_protocols.insert(name, handler);
+        continue;
+      }
+
+      #[cfg(gtk)]
+      if let Some(handler) = self
+        .platform_specific
+        .streaming_custom_protocols
+        .remove(&name)
+      {
+        let handler = Box::new(policy.wrap(handler));
+        self
+          .platform_specific
+          .streaming_custom_protocols
+          .insert(name, handler);
+      }
+    }
+
+    #[cfg(feature = "protocol")]
+    if let Some(recorder) = self.attrs.custom_protocol_recorder.take() {
+      for handler in self.attrs.custom_protocols.values_mut() {
+        let inner = std::mem::replace(handler, Box::new(|_, _, _| {}));
+        *handler = Box::new(recorder.wrap(inner));
+      }
+
+      #[cfg(gtk)]
+      for handler in self
+        .platform_specific
+        .streaming_custom_protocols
+        .values_mut()
+      {
+        let inner = std::mem::replace(handler, Box::new(|_, _, _| {}));
+        *handler = Box::new(recorder.wrap_streaming(inner));
+      }
+    }
+    Ok(self)
+  }
+
+  /// Whether a handler is registered for the `name` custom protocol.
+  #[cfg(feature = "protocol")]
+  fn has_custom_protocol(&self, name: &str) -> bool {
+    #[cfg(gtk)]
+    if self
+      .platform_specific
+      .streaming_custom_protocols
+      .contains_key(name)
+    {
+      return true;
+    }
+    self.attrs.custom_protocols.contains_key(name)
+  }
+}
+
 /// Builder type of [`WebView`].
 ///
 /// [`WebViewBuilder`] / [`WebView`] are the basic building blocks to construct WebView contents and
@@ -725,6 +1061,20 @@ impl<'a> WebViewBuilder<'a> {
   /// - macOS, iOS and Linux: `<scheme_name>://<path>` (so it will be `wry://path/to/page).
   /// - Windows and Android: `http://<scheme_name>.<path>` by default (so it will be `http://wry.path/to/page`). To use `https` instead of `http`, use [`WebViewBuilderExtWindows::with_https_scheme`] and [`WebViewBuilderExtAndroid::with_https_scheme`].
   ///
+  /// [`WebView::custom_protocol_url`] and [`WebView::custom_protocol_origin`] return the right form for the current platform.
+  ///
+  /// # Redirects
+  ///
+  /// A `301`, `302`, `303`, `307` or `308` response to a page navigation loads its `Location` instead,
+  /// which may be relative or use the `<scheme_name>://` form on every platform. The navigation handler is
+  /// called for the target and the page load handler reports the final URL. A navigation that is redirected
+  /// more than 20 times or back to a URL of its redirects gets a `508 Loop Detected` response instead.
+  ///
+  /// ## Platform-specific
+  ///
+  /// - **Linux, macOS, iOS and Android:** Only the redirects of the main document are followed.
+  /// - **Windows:** WebView2 follows all redirects by itself and shows its own error page when they loop.
+  ///
   /// # Reading assets on mobile
   ///
   /// - Android: For loading content from the `assets` folder (which is copied to the Andorid apk) please
@@ -739,17 +1089,6 @@ impl<'a> WebViewBuilder<'a> {
     F: Fn(WebViewId, Request<Vec<u8>>) -> Response<Cow<'static, [u8]>> + 'static,
   {
     self.and_then(|mut b| {
-      #[cfg(any(
-        target_os = "linux",
-        target_os = "dragonfly",
-        target_os = "freebsd",
-        target_os = "netbsd",
-        target_os = "openbsd",
-      ))]
-      if let Some(context) = &mut b.attrs.context {
-        context.register_custom_protocol(name.clone())?;
-      }
-
       if b.attrs.custom_protocols.iter().any(|(n, _)| n == &name) {
         return Err(Error::DuplicateCustomProtocol(name));
       }
@@ -766,6 +1105,35 @@ impl<'a> WebViewBuilder<'a> {
     })
   }
 
+  /// Same as [`Self::with_custom_protocol`] but with a handler that can fail.
+  ///
+  /// Errors are answered with the default [`ErrorPage`](crate::custom_protocol::ErrorPage), an HTML page
+  /// or JSON object depending on the `Accept` header of the request. Use [`ErrorPage::wrap`](crate::custom_protocol::ErrorPage::wrap)
+  /// with [`Self::with_custom_protocol`] to customize it.
+  ///
+  /// # Examples
+  ///
+  /// ```no_run
+  /// use wry::{http::Response, WebViewBuilder};
+  ///
+  /// WebViewBuilder::new()
+  ///   .with_fallible_custom_protocol("wry".into(), |_webview_id, request| {
+  ///     // a missing file is answered with `404 Not Found`
+  ///     let content = std::fs::read(&request.uri().path()[1..])?;
+  ///     Ok::<_, std::io::Error>(Response::new(content))
+  ///   });
+  /// ```
+  #[cfg(feature = "protocol")]
+  pub fn with_fallible_custom_protocol<F, B, E>(self, name: String, handler: F) -> Self
+  where
+    F: Fn(WebViewId, Request<Vec<u8>>) -> std::result::Result<Response<B>, E> + 'static,
+    B: Into<Cow<'static, [u8]>>,
+    E: Into<Box<dyn std::error::Error>>,
+  {
+    let handler = custom_protocol::ErrorPage::new().wrap(handler);
+    self.with_custom_protocol(name, handler)
+  }
+
   /// Same as [`Self::with_custom_protocol`] but with an asynchronous responder.
   ///
   /// When registering a custom protocol with the same name, only the last regisered one will be used.
@@ -791,17 +1159,6 @@ impl<'a> WebViewBuilder<'a> {
     F: Fn(WebViewId, Request<Vec<u8>>, RequestAsyncResponder) + 'static,
   {
     self.and_then(|mut b| {
-      #[cfg(any(
-        target_os = "linux",
-        target_os = "dragonfly",
-        target_os = "freebsd",
-        target_os = "netbsd",
-        target_os = "openbsd",
-      ))]
-      if let Some(context) = &mut b.attrs.context {
-        context.register_custom_protocol(name.clone())?;
-      }
-
       if b.attrs.custom_protocols.iter().any(|(n, _)| n == &name) {
         return Err(Error::DuplicateCustomProtocol(name));
       }
@@ -812,15 +1169,220 @@ impl<'a> WebViewBuilder<'a> {
     })
   }
 
+  /// Sets the privileges of the `name` custom protocol scheme, such as whether it allows CORS requests.
+  ///
+  /// See [`CustomProtocolOptions`] for the platform-specific behavior.
+  ///
+  /// Building the webview fails with [`Error::UnknownCustomProtocol`] if no handler is registered for `name`.
+  ///
+  /// # Examples
+  ///
+  /// ```no_run
+  /// use wry::{CustomProtocolOptions, WebViewBuilder};
+  ///
+  /// WebViewBuilder::new()
+  ///   .with_custom_protocol_options(
+  ///     "wry".into(),
+  ///     CustomProtocolOptions {
+  ///       cors_enabled: true,
+  ///       ..Default::default()
+  ///     },
+  ///   )
+  ///   .with_custom_protocol("wry".into(), |_webview_id, _request| {
+  ///     wry::http::Response::new(Vec::new().into())
+  ///   });
+  /// ```
+  #[cfg(feature = "protocol")]
+  pub fn with_custom_protocol_options(self, name: String, options: CustomProtocolOptions) -> Self {
+    self.and_then(|mut b| {
+      b.attrs.custom_protocol_options.insert(name, options);
+      Ok(b)
+    })
+  }
+
+  /// Sets the CORS policy of the `name` custom protocol.
+  ///
+  /// Preflight requests are answered by wry and the `Access-Control-Allow-*` headers are added to the responses
+  /// of the handler, whichever method registered it. See [`custom_protocol::cors`] for more information.
+  ///
+  /// Building the webview fails with [`Error::UnknownCustomProtocol`] if no handler is registered for `name`.
+  #[cfg(feature = "protocol")]
+  pub fn with_custom_protocol_cors(
+    self,
+    name: String,
+    policy: custom_protocol::cors::CorsPolicy,
+  ) -> Self {
+    self.and_then(|mut b| {
+      b.attrs.custom_protocol_cors.insert(name, policy);
+      Ok(b)
+    })
+  }
+
+  /// Records the requests and responses of all custom protocols of the webview with `recorder`,
+  /// whichever method registered them. See [`custom_protocol::har`] for more information.
+  #[cfg(feature = "protocol")]
+  pub fn with_custom_protocol_recorder(self, recorder: custom_protocol::har::HarRecorder) -> Self {
+    self.and_then(|mut b| {
+      b.attrs.custom_protocol_recorder = Some(recorder);
+      Ok(b)
+    })
+  }
+
+  /// Same as [`Self::with_asynchronous_custom_protocol`] but the handler returns a future resolving to the response.
+  ///
+  /// The handler is called on the thread the webview runs on and the returned future is run by `spawner`,
+  /// either a [`ThreadSpawner`](custom_protocol::ThreadSpawner) or any executor through the [`Spawner`](custom_protocol::Spawner) trait.
+  /// Its response is sent back to the webview on the right thread once the future completes,
+  /// as with [`RequestAsyncResponder`]. The future is dropped if the webview [cancels](CancellationToken) the request.
+  ///
+  /// # Examples
+  ///
+  /// ```no_run
+  /// use wry::{custom_protocol::ThreadSpawner, http::Response, WebViewBuilder};
+  ///
+  /// WebViewBuilder::new()
+  ///   .with_async_custom_protocol("wry".into(), ThreadSpawner, |_webview_id, request| async move {
+  ///     let name = request.uri().path().trim_start_matches('/').to_string();
+  ///     Response::new(format!("hello {name}"))
+  ///   });
+  /// ```
+  #[cfg(feature = "protocol")]
+  pub fn with_async_custom_protocol<S, F, Fut, B>(
+    self,
+    name: String,
+    spawner: S,
+    handler: F,
+  ) -> Self
+  where
+    S: custom_protocol::Spawner,
+    F: Fn(WebViewId, Request<Vec<u8>>) -> Fut + 'static,
+    Fut: std::future::Future<Output = Response<B>> + Send + 'static,
+    B: Into<ResponseBody>,
+  {
+    use std::{future::Future, pin::Pin, task::Poll};
+
+    self.with_asynchronous_custom_protocol(name, move |id, request, responder| {
+      let mut response = Box::pin(handler(id, request));
+      let mut cancelled = responder.cancellation().cancelled();
+      spawner.spawn(Box::pin(async move {
+        let response = std::future::poll_fn(|cx| {
+          if Pin::new(&mut cancelled).poll(cx).is_ready() {
+            return Poll::Ready(None);
+          }
+          response.as_mut().poll(cx).map(Some)
+        })
+        .await;
+        if let Some(response) = response {
+          responder.respond_with_body(response.map(Into::into));
+        }
+      }));
+    })
+  }
+
+  /// Registers a [`tower`](https://docs.rs/tower) service, such as an `axum` router, as a custom protocol handler.
+  ///
+  /// Requests are passed to the service with a [`Full<Bytes>`](http_body_util::Full) body and a
+  /// [`WebViewIdExtension`](custom_protocol::tower::WebViewIdExtension) extension, and the service runs on `spawner`,
+  /// see [`Self::with_async_custom_protocol`]. The service is cloned for each request, and the response body is collected
+  /// before it is sent to the webview. Service and body errors are answered with `500 Internal Server Error`.
+  ///
+  /// # Examples
+  ///
+  /// ```ignore
+  /// use axum::{routing::get, Router};
+  /// use wry::WebViewBuilder;
+  ///
+  /// let runtime = tokio::runtime::Handle::current();
+  /// let app = Router::new().route("/", get(|| async { "Hello from axum" }));
+  ///
+  /// WebViewBuilder::new()
+  ///   .with_tower_custom_protocol(
+  ///     "app".into(),
+  ///     move |future: wry::custom_protocol::SpawnedFuture| {
+  ///       runtime.spawn(future);
+  ///     },
+  ///     app,
+  ///   )
+  ///   .with_url("app://localhost");
+  /// ```
+  #[cfg(feature = "tower")]
+  #[cfg_attr(docsrs, doc(cfg(feature = "tower")))]
+  pub fn with_tower_custom_protocol<Sp, S, B>(self, name: String, spawner: Sp, service: S) -> Self
+  where
+    Sp: custom_protocol::Spawner,
+    S: tower_service::Service<Request<http_body_util::Full<bytes::Bytes>>, Response = Response<B>>
+      + Clone
+      + Send
+      + 'static,
+    S::Future: Send,
+    S::Error: Into<custom_protocol::tower::BoxError>,
+    B: http_body::Body + Send + 'static,
+    B::Data: Send,
+    B::Error: Into<custom_protocol::tower::BoxError>,
+  {
+    self.with_async_custom_protocol(name, spawner, move |webview_id, request| {
+      let mut request = request.map(|body| http_body_util::Full::new(body.into()));
+      request
+        .extensions_mut()
+        .insert(custom_protocol::tower::WebViewIdExtension(
+          webview_id.to_string(),
+        ));
+      custom_protocol::tower::call(service.clone(), request)
+    })
+  }
+
   /// Set the IPC handler to receive the message from Javascript on webview
   /// using `window.ipc.postMessage("insert_message_here")` to host Rust code.
   ///
+  /// The request URL is the URL of the frame that sent the message, which is described by the
+  /// [`ipc::IpcFrame`] in the request extensions.
+  ///
+  /// Binary messages are ignored, use [`Self::with_ipc_body_handler`] to receive them.
+  ///
   /// ## Platform-specific
   ///
-  /// - **Linux / Android**: The request URL is not supported on iframes and the main frame URL is used instead.
+  /// - **Android**: The request URL is not supported on iframes and the main frame URL is used instead.
   pub fn with_ipc_handler<F>(self, handler: F) -> Self
   where
     F: Fn(Request<String>) + 'static,
+  {
+    self.with_ipc_body_handler(move |request| {
+      let (parts, body) = request.into_parts();
+      match body {
+        ipc::IpcBody::Text(text) => handler(Request::from_parts(parts, text)),
+        ipc::IpcBody::Binary(_) => {
+          #[cfg(feature = "tracing")]
+          tracing::warn!("binary IPC message ignored by a text IPC handler");
+        }
+      }
+    })
+  }
+
+  /// Set the IPC handler to receive the messages of `window.ipc.postMessage`, which takes a
+  /// string, an `ArrayBuffer`, a typed array or a `DataView`.
+  ///
+  /// Replaces the handler set by [`Self::with_ipc_handler`].
+  ///
+  /// # Examples
+  ///
+  /// ```no_run
+  /// use wry::{ipc::IpcBody, WebViewBuilder};
+  ///
+  /// // window.ipc.postMessage(new Uint8Array([1, 2, 3]))
+  /// let builder = WebViewBuilder::new().with_ipc_body_handler(|request| match request.body() {
+  ///   IpcBody::Text(text) => println!("text: {text}"),
+  ///   IpcBody::Binary(bytes) => println!("{} bytes", bytes.len()),
+  /// });
+  /// ```
+  ///
+  /// ## Platform-specific
+  ///
+  /// - **Android**: The request URL is not supported on iframes and the main frame URL is used instead.
+  /// - **macOS / iOS / Windows / Android**: The message handlers only take strings, so the
+  ///   bytes are sent base64-encoded and decoded before calling the handler.
+  pub fn with_ipc_body_handler<F>(self, handler: F) -> Self
+  where
+    F: Fn(Request<ipc::IpcBody>) + 'static,
   {
     self.and_then(|mut b| {
       b.attrs.ipc_handler = Some(Box::new(handler));
@@ -828,6 +1390,82 @@ impl<'a> WebViewBuilder<'a> {
     })
   }
 
+  /// Set the IPC handler to receive the messages of `window.ipc.postMessage(JSON.stringify(value))`
+  /// deserialized into `T`.
+  ///
+  /// Messages that are not valid JSON for `T` are passed to `error_handler` instead, with the
+  /// deserialization error.
+  ///
+  /// # Examples
+  ///
+  /// ```no_run
+  /// use wry::WebViewBuilder;
+  ///
+  /// let builder = WebViewBuilder::new().with_typed_ipc_handler(
+  ///   |request: wry::http::Request<(String, u32)>| {
+  ///     let (event, count) = request.into_body();
+  ///     println!("{event} fired {count} times");
+  ///   },
+  ///   |request, error| eprintln!("malformed IPC message {:?}: {error}", request.body()),
+  /// );
+  /// ```
+  ///
+  /// ## Platform-specific
+  ///
+  /// - **Android**: The request URL is not supported on iframes and the main frame URL is used instead.
+  #[cfg(feature = "serde")]
+  #[cfg_attr(docsrs, doc(cfg(feature = "serde")))]
+  pub fn with_typed_ipc_handler<T, F, E>(self, handler: F, error_handler: E) -> Self
+  where
+    T: serde::de::DeserializeOwned,
+    F: Fn(Request<T>) + 'static,
+    E: Fn(Request<String>, serde_json::Error) + 'static,
+  {
+    self.with_ipc_handler(move |request| match serde_json::from_str(request.body()) {
+      Ok(value) => handler(request.map(|_| value)),
+      Err(error) => {
+        #[cfg(feature = "tracing")]
+        tracing::warn!(uri = %request.uri(), "malformed IPC message: {error}");
+        error_handler(request, error)
+      }
+    })
+  }
+
+  /// Set the router answering `window.ipc.invoke(command, payload)` calls, which return a
+  /// `Promise` of the value returned by the handler of `command`.
+  ///
+  /// The messages of `window.ipc.postMessage` keep going to the [IPC handler](Self::with_ipc_handler).
+  /// See the [`ipc`] module for the protocol.
+  ///
+  /// ## Platform-specific
+  ///
+  /// - **Linux**: With the `linux-body` feature, the calls are answered with the script message
+  ///   replies of WebKitGTK 2.40, in the frame that made them. Otherwise they are answered by evaluating
+  ///   a script in the main frame, and the promises of iframes reject.
+  /// - **macOS / iOS**: The calls are answered in the frame that made them. Before macOS 11 and iOS 14,
+  ///   the calls of iframes are dropped.
+  /// - **Windows / Android**: `invoke` is only available in the main frame, the promises of iframes reject.
+  /// - **Android**: The request URL is not supported on iframes and the main frame URL is used instead.
+  pub fn with_ipc_router(self, router: ipc::IpcRouter) -> Self {
+    self.and_then(|mut b| {
+      b.attrs.ipc_router = Some(router);
+      Ok(b)
+    })
+  }
+
+  /// Set the policy restricting the IPC messages to the frames of some origins, and their
+  /// `window.ipc.invoke` calls to some commands.
+  ///
+  /// The messages it denies are dropped before the [IPC handler](Self::with_ipc_handler) and the
+  /// [router](Self::with_ipc_router) run. Without a policy, every frame may use IPC.
+  /// See [`ipc::IpcPolicy`] for more information.
+  pub fn with_ipc_policy(self, policy: ipc::IpcPolicy) -> Self {
+    self.and_then(|mut b| {
+      b.attrs.ipc_policy = Some(policy);
+      Ok(b)
+    })
+  }
+
   /// Set a handler closure to process incoming [`DragDropEvent`] of the webview.
   ///
   /// # Blocking OS Default Behavior
@@ -1116,7 +1754,7 @@ impl<'a> WebViewBuilder<'a> {
   /// - Panics if the provided handle was not supported or invalid.
   /// - Panics on Linux, if [`gtk::init`] was not called in this thread.
   pub fn build<W: HasWindowHandle>(self, window: &'a W) -> Result<WebView> {
-    let parts = self.inner?;
+    let parts = self.inner?.apply_custom_protocol_wrappers()?;
 
     InnerWebView::new(window, parts.attrs, parts.platform_specific)
       .map(|webview| WebView { webview })
@@ -1145,7 +1783,7 @@ impl<'a> WebViewBuilder<'a> {
   /// - Panics if the provided handle was not support or invalid.
   /// - Panics on Linux, if [`gtk::init`] was not called in this thread.
   pub fn build_as_child<W: HasWindowHandle>(self, window: &'a W) -> Result<WebView> {
-    let parts = self.inner?;
+    let parts = self.inner?.apply_custom_protocol_wrappers()?;
 
     InnerWebView::new_as_child(window, parts.attrs, parts.platform_specific)
       .map(|webview| WebView { webview })
@@ -1405,6 +2043,11 @@ impl WebViewBuilderExtAndroid for WebViewBuilder<'_> {
 #[derive(Default)]
 pub(crate) struct PlatformSpecificWebViewAttributes {
   extension_path: Option<PathBuf>,
+  #[cfg(gtk)]
+  streaming_custom_protocols:
+    HashMap<String, Box<dyn Fn(WebViewId, Request<RequestBody>, RequestAsyncResponder)>>,
+  #[cfg(feature = "html")]
+  custom_protocol_script_injection: bool,
 }
 
 #[cfg(any(
@@ -1431,6 +2074,48 @@ pub trait WebViewBuilderExtUnix<'a> {
 
   /// Set the path from which to load extensions from.
   fn with_extension_path(self, path: impl Into<PathBuf>) -> Self;
+
+  /// Same as [`WebViewBuilder::with_asynchronous_custom_protocol`] but the request body is a [`RequestBody`]
+  /// stream instead of a buffered `Vec<u8>`.
+  ///
+  /// The body is read from WebKit chunk by chunk as the handler reads it, so large uploads
+  /// can be consumed from another thread without blocking the UI.
+  ///
+  /// Requires the `linux-body` feature, the request body is always empty otherwise.
+  ///
+  /// # Examples
+  ///
+  /// ```no_run
+  /// use std::io::Read;
+  /// use wry::{http::Response, WebViewBuilder, WebViewBuilderExtUnix};
+  ///
+  /// WebViewBuilder::new()
+  ///   .with_streaming_custom_protocol("app".into(), |_webview_id, request, responder| {
+  ///     std::thread::spawn(move || {
+  ///       let mut received = 0;
+  ///       let mut buffer = [0; 8192];
+  ///       let mut body = request.into_body();
+  ///       while let Ok(count @ 1..) = body.read(&mut buffer) {
+  ///         received += count;
+  ///       }
+  ///       responder.respond(Response::new(format!("received {received} bytes").into_bytes()));
+  ///     });
+  ///   });
+  /// ```
+  #[cfg(gtk)]
+  fn with_streaming_custom_protocol<F>(self, name: String, handler: F) -> Self
+  where
+    F: Fn(WebViewId, Request<RequestBody>, RequestAsyncResponder) + 'static;
+
+  /// Injects the initialization scripts and the IPC bootstrap script into the HTML documents served by
+  /// custom protocols, instead of running them as WebKit user scripts on these pages.
+  ///
+  /// Each request gets a new [`html::ScriptNonce`] in its extensions, which the injected scripts carry
+  /// and which is added to the `Content-Security-Policy` of the documents, so pages with a strict policy
+  /// can still use them. See the [`html`] module for more information.
+  /// Other pages keep running the scripts as user scripts. Disabled by default.
+  #[cfg(feature = "html")]
+  fn with_custom_protocol_script_injection(self, enabled: bool) -> Self;
 }
 
 #[cfg(any(
@@ -1445,7 +2130,7 @@ impl<'a> WebViewBuilderExtUnix<'a> for WebViewBuilder<'a> {
   where
     W: gtk::prelude::IsA<gtk::Container>,
   {
-    let parts = self.inner?;
+    let parts = self.inner?.apply_custom_protocol_wrappers()?;
 
     InnerWebView::new_gtk(widget, parts.attrs, parts.platform_specific)
       .map(|webview| WebView { webview })
@@ -1457,6 +2142,37 @@ impl<'a> WebViewBuilderExtUnix<'a> for WebViewBuilder<'a> {
       Ok(b)
     })
   }
+
+  #[cfg(gtk)]
+  fn with_streaming_custom_protocol<F>(self, name: String, handler: F) -> Self
+  where
+    F: Fn(WebViewId, Request<RequestBody>, RequestAsyncResponder) + 'static,
+  {
+    self.and_then(|mut b| {
+      if b.attrs.custom_protocols.contains_key(&name)
+        || b
+          .platform_specific
+          .streaming_custom_protocols
+          .contains_key(&name)
+      {
+        return Err(Error::DuplicateCustomProtocol(name));
+      }
+
+      b.platform_specific
+        .streaming_custom_protocols
+        .insert(name, Box::new(handler));
+
+      Ok(b)
+    })
+  }
+
+  #[cfg(feature = "html")]
+  fn with_custom_protocol_script_injection(self, enabled: bool) -> Self {
+    self.and_then(|mut b| {
+      b.platform_specific.custom_protocol_script_injection = enabled;
+      Ok(b)
+    })
+  }
 }
 
 /// The fundamental type to present a [`WebView`].
@@ -1528,6 +2244,25 @@ impl WebView {
     self.webview.url()
   }
 
+  /// Returns the URL pages use to load `path` from the `scheme` custom protocol.
+  ///
+  /// For example, `custom_protocol_url("wry", "index.html")` returns:
+  ///
+  /// - macOS, iOS and Linux: `wry://localhost/index.html`.
+  /// - Windows and Android: `http://wry.localhost/index.html`, or `https://wry.localhost/index.html`
+  ///   with [`WebViewBuilderExtWindows::with_https_scheme`] and [`WebViewBuilderExtAndroid::with_https_scheme`].
+  pub fn custom_protocol_url(&self, scheme: &str, path: &str) -> String {
+    self.webview.custom_protocol_url_style().url(scheme, path)
+  }
+
+  /// Returns the origin of pages loaded from the `scheme` custom protocol,
+  /// for example to fill `Access-Control-Allow-Origin` headers.
+  ///
+  /// See [`Self::custom_protocol_url`] for the forms it takes on each platform.
+  pub fn custom_protocol_origin(&self, scheme: &str) -> String {
+    self.webview.custom_protocol_url_style().origin(scheme)
+  }
+
   /// Evaluate and run javascript code.
   pub fn evaluate_script(&self, js: &str) -> Result<()> {
     self
@@ -1535,6 +2270,27 @@ impl WebView {
       .eval(js, None::<Box<dyn Fn(String) + Send + 'static>>)
   }
 
+  /// Call the javascript function `function` with `argument` serialized as its only argument.
+  ///
+  /// ```no_run
+  /// # fn run(webview: &wry::WebView) -> wry::Result<()> {
+  /// // runs `window.render(["a","b"])`
+  /// webview.evaluate_function("window.render", &["a", "b"])?;
+  /// # Ok(())
+  /// # }
+  /// ```
+  ///
+  /// See [`ipc::to_js`] to embed values in other scripts.
+  #[cfg(feature = "serde")]
+  #[cfg_attr(docsrs, doc(cfg(feature = "serde")))]
+  pub fn evaluate_function<T: serde::Serialize + ?Sized>(
+    &self,
+    function: &str,
+    argument: &T,
+  ) -> Result<()> {
+    self.evaluate_script(&format!("{function}({})", ipc::to_js(argument)?))
+  }
+
   /// Evaluate and run javascript code with callback function. The evaluation result will be
   /// serialized into a JSON string and passed to the callback function.
   ///
@@ -1,7 +1,11 @@
+use std::fmt::Write;
+#[cfg(any(target_os = "macos", target_os = "android", target_os = "ios"))]
 use std::sync::atomic::{AtomicU32, Ordering};
 
+#[cfg(any(target_os = "macos", target_os = "android", target_os = "ios"))]
 pub struct Counter(AtomicU32);
 
+#[cfg(any(target_os = "macos", target_os = "android", target_os = "ios"))]
 impl Counter {
   pub const fn new() -> Self {
     Self(AtomicU32::new(1))
@@ -11,3 +15,24 @@ impl Counter {
     self.0.fetch_add(1, Ordering::Relaxed)
   }
 }
+
+/// Quotes and escapes a JSON string.
+pub(crate) fn json_string(text: &str) -> String {
+  let mut escaped = String::with_capacity(text.len() + 2);
+  escaped.push('"');
+  for c in text.chars() {
+    match c {
+      '"' => escaped.push_str("\\\""),
+      '\\' => escaped.push_str("\\\\"),
+      '\n' => escaped.push_str("\\n"),
+      '\r' => escaped.push_str("\\r"),
+      '\t' => escaped.push_str("\\t"),
+      c if c.is_control() => {
+        let _ = write!(escaped, "\\u{:04x}", c as u32);
+      }
+      c => escaped.push(c),
+    }
+  }
+  escaped.push('"');
+  escaped
+}
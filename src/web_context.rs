@@ -58,16 +58,15 @@ impl WebContext {
     self.data_directory.as_deref()
   }
 
-  #[allow(dead_code)]
-  pub(crate) fn register_custom_protocol(&mut self, name: String) -> Result<(), crate::Error> {
-    if self.custom_protocols.contains(&name) {
-      return Err(crate::Error::ContextDuplicateCustomProtocol(name));
-    }
-
-    Ok(())
-  }
-
   /// Check if a custom protocol has been registered on this context.
+  ///
+  /// ## Platform-specific
+  ///
+  /// - **Linux:** Webviews of the same context share a single registration of each scheme,
+  ///   which dispatches the requests to the handler of the requesting webview. The scheme stays
+  ///   registered after its webviews are dropped.
+  /// - **Windows / macOS / iOS / Android:** Custom protocols are registered on each webview,
+  ///   so this always returns `false`.
   pub fn is_custom_protocol_registered(&self, name: String) -> bool {
     self.custom_protocols.contains(&name)
   }
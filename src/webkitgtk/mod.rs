@@ -14,8 +14,8 @@ use gtk::{
   glib::{self, translate::FromGlibPtrFull},
   prelude::*,
 };
-use http::Request;
-use javascriptcore::ValueExt;
+use javascriptcore::{ValueExt, ValueExtManual};
+use once_cell::sync::Lazy;
 use raw_window_handle::{HasWindowHandle, RawWindowHandle};
 #[cfg(any(debug_assertions, feature = "devtools"))]
 use std::sync::atomic::{AtomicBool, Ordering};
@@ -39,18 +39,100 @@ use webkit2gtk_sys::{
 };
 use x11_dl::xlib::*;
 
+pub use request_body::RequestBody;
 pub use web_context::WebContextImpl;
 
+#[cfg(feature = "linux-body")]
+use crate::ipc::{IpcPolicy, IpcRouter};
 use crate::{
-  proxy::ProxyConfig, web_context::WebContext, Error, PageLoadEvent, Rect, Result,
-  WebViewAttributes, RGBA,
+  custom_protocol_url::UrlStyle,
+  ipc::{IpcDispatcher, IpcFrame},
+  proxy::ProxyConfig,
+  web_context::WebContext,
+  Error, PageLoadEvent, Rect, Result, WebViewAttributes, RGBA,
 };
 
 use self::web_context::WebContextExt;
 
 const WEBVIEW_ID: &str = "webview_id";
 
+/// The script message handler answering the `window.ipc.invoke` calls with a reply.
+#[cfg(feature = "linux-body")]
+const IPC_INVOKE_HANDLER: &str = "ipcInvoke";
+
+/// The world of the script posting the IPC messages with the frame that sent them, where the
+/// message handlers are registered so the pages cannot forge the frame.
+const IPC_WORLD: &str = "wry";
+
+/// Passes the messages of the pages to [`IPC_FRAME_SCRIPT`].
+static IPC_SCRIPT: Lazy<String> = Lazy::new(|| {
+  let post_message =
+    "function (x) { document.dispatchEvent(new CustomEvent('wry-ipc-message', { detail: x })) }";
+  #[cfg(feature = "linux-body")]
+  return crate::ipc::ipc_script_with_reply(
+    post_message,
+    true,
+    &format!(
+      "(function () {{
+  var pending = {{}};
+  document.addEventListener('wry-ipc-reply', function (event) {{
+    var reply = event.detail, promise = pending[reply.id];
+    if (promise) {{ delete pending[reply.id]; (reply.ok ? promise.resolve : promise.reject)(reply.value); }}
+  }});
+  return function (message) {{
+    return new Promise(function (resolve, reject) {{
+      var id = {};
+      pending[id] = {{ resolve: resolve, reject: reject }};
+      document.dispatchEvent(new CustomEvent('wry-ipc-invoke', {{ detail: {{ id: id, message: message }} }}));
+    }});
+  }};
+}})()",
+      crate::ipc::INVOKE_ID
+    ),
+  );
+  #[cfg(not(feature = "linux-body"))]
+  crate::ipc::ipc_script(post_message, true)
+});
+
+/// Runs in the [`IPC_WORLD`] of every frame, posting the messages of [`IPC_SCRIPT`] with the
+/// frame, and answering its `invoke` calls with the script message replies.
+///
+/// WebKitGTK script messages don't tell which frame posted them, so the frame is read here,
+/// where the scripts of the page can't tamper with `location` or `window.top`.
+static IPC_FRAME_SCRIPT: Lazy<String> = Lazy::new(|| {
+  #[cfg(feature = "linux-body")]
+  let invoke = format!(
+    "document.addEventListener('wry-ipc-invoke', function (event) {{
+    var id = event.detail.id;
+    function reply(ok, value) {{
+      document.dispatchEvent(new CustomEvent('wry-ipc-reply', {{ detail: {{ id: id, ok: ok, value: value }} }}));
+    }}
+    post('{IPC_INVOKE_HANDLER}', event.detail.message).then(
+      function (value) {{ reply(true, value); }},
+      function (error) {{ reply(false, String(error && error.message !== undefined ? error.message : error)); }}
+    );
+  }});"
+  );
+  #[cfg(not(feature = "linux-body"))]
+  let invoke = "";
+  format!(
+    "(function () {{
+  function post(handler, message) {{
+    return window.webkit.messageHandlers[handler].postMessage({{
+      message: message,
+      url: location.href,
+      origin: self.origin,
+      isMainFrame: window === window.top
+    }});
+  }}
+  document.addEventListener('wry-ipc-message', function (event) {{ post('ipc', event.detail); }});
+  {invoke}
+}})();"
+  )
+});
+
 mod drag_drop;
+mod request_body;
 mod synthetic_mouse_events;
 mod web_context;
 
@@ -78,10 +160,14 @@ pub(crate) struct InnerWebView {
   is_in_fixed_parent: bool,
 
   x11: Option<X11Data>,
+
+  // Deregisters the custom protocol handlers of the webview when dropped
+  custom_protocols: web_context::WebViewCustomProtocols,
 }
 
 impl Drop for InnerWebView {
   fn drop(&mut self) {
+    crate::custom_protocol_redirect::forget(&self.id);
     unsafe { self.webview.destroy() }
   }
 }
@@ -291,7 +377,8 @@ impl InnerWebView {
       .unwrap_or_else(|| (webview.as_ptr() as isize).to_string());
     unsafe { webview.set_data(WEBVIEW_ID, id.clone()) };
 
-    let w = Self {
+    let mut w = Self {
+      custom_protocols: web_context.os.webview_custom_protocols(&id),
       id,
       webview,
       pending_scripts: Arc::new(Mutex::new(Some(Vec::new()))),
@@ -303,12 +390,38 @@ impl InnerWebView {
       is_inspector_open,
     };
 
-    // Initialize message handler
-    w.init("Object.defineProperty(window, 'ipc', { value: Object.freeze({ postMessage: function(x) { window.webkit.messageHandlers['ipc'].postMessage(x) } }) })")?;
+    // Custom protocol documents get the scripts injected in their HTML instead of running them as user scripts
+    #[cfg(feature = "html")]
+    let injected_scripts = pl_attrs.custom_protocol_script_injection.then(|| {
+      let scripts = std::iter::once(IPC_SCRIPT.to_string())
+        .chain(attributes.initialization_scripts.iter().cloned());
+      Arc::new(scripts.collect::<Vec<_>>())
+    });
+    #[cfg(feature = "html")]
+    let block_list = match injected_scripts {
+      Some(_) => attributes
+        .custom_protocols
+        .keys()
+        .chain(pl_attrs.streaming_custom_protocols.keys())
+        .map(|name| format!("{name}://*/*"))
+        .collect(),
+      None => Vec::new(),
+    };
+    #[cfg(not(feature = "html"))]
+    let block_list = Vec::<String>::new();
+    let block_list = block_list.iter().map(String::as_str).collect::<Vec<_>>();
+
+    // Initialize message handler, in every frame so their messages are told apart
+    w.init(
+      &IPC_SCRIPT,
+      UserContentInjectedFrames::AllFrames,
+      &block_list,
+    )?;
 
     // Initialize scripts
     for js in attributes.initialization_scripts {
-      w.init(&js)?;
+      // TODO: feature to allow injecting into subframes
+      w.init(&js, UserContentInjectedFrames::TopFrame, &block_list)?;
     }
 
     // Run pending webview.eval() scripts once webview loads.
@@ -326,8 +439,32 @@ impl InnerWebView {
     });
 
     // Custom protocols handler
+    #[cfg(feature = "protocol")]
+    for (name, options) in attributes.custom_protocol_options {
+      web_context.set_uri_scheme_options(&name, options);
+    }
     for (name, handler) in attributes.custom_protocols {
-      web_context.register_uri_scheme(&name, handler)?;
+      if pl_attrs.streaming_custom_protocols.contains_key(&name) {
+        return Err(Error::DuplicateCustomProtocol(name));
+      }
+      let custom_protocols = &mut w.custom_protocols;
+      #[cfg(feature = "html")]
+      if let Some(scripts) = &injected_scripts {
+        let handler = crate::html::injecting_handler(handler, scripts.clone());
+        web_context.register_uri_scheme(&name, custom_protocols, handler)?;
+        continue;
+      }
+      web_context.register_uri_scheme(&name, custom_protocols, handler)?;
+    }
+    for (name, handler) in pl_attrs.streaming_custom_protocols {
+      let custom_protocols = &mut w.custom_protocols;
+      #[cfg(feature = "html")]
+      if let Some(scripts) = &injected_scripts {
+        let handler = crate::html::injecting_handler(handler, scripts.clone());
+        web_context.register_streaming_uri_scheme(&name, custom_protocols, handler)?;
+        continue;
+      }
+      web_context.register_streaming_uri_scheme(&name, custom_protocols, handler)?;
     }
 
     // Navigation
@@ -526,29 +663,130 @@ impl InnerWebView {
   fn attach_ipc_handler(webview: WebView, attributes: &mut WebViewAttributes) {
     // Message handler
     let ipc_handler = attributes.ipc_handler.take();
+    let ipc_policy = attributes
+      .ipc_policy
+      .take()
+      .map(|policy| policy.resolve(UrlStyle::Custom));
     let manager = webview
       .user_content_manager()
       .expect("WebView does not have UserContentManager");
 
+    // The invoke calls are answered with script message replies instead
+    #[cfg(feature = "linux-body")]
+    let dispatcher = {
+      if let Some(router) = attributes.ipc_router.take() {
+        Self::attach_ipc_router(&manager, router, ipc_policy.clone());
+      }
+      IpcDispatcher::new(ipc_handler, None, ipc_policy)
+    };
+    #[cfg(not(feature = "linux-body"))]
+    let dispatcher = IpcDispatcher::new(ipc_handler, attributes.ipc_router.take(), ipc_policy);
+
     // Connect before registering as recommended by the docs
     manager.connect_script_message_received(None, move |_m, msg| {
       #[cfg(feature = "tracing")]
       let _span = tracing::info_span!(parent: None, "wry::ipc::handle").entered();
 
       if let Some(js) = msg.js_value() {
-        if let Some(ipc_handler) = &ipc_handler {
-          ipc_handler(
-            Request::builder()
-              .uri(webview.uri().unwrap().to_string())
-              .body(js.to_string())
-              .unwrap(),
-          );
+        if let Some(dispatcher) = &dispatcher {
+          let Some((frame, message)) = frame_message(&js) else {
+            #[cfg(feature = "tracing")]
+            tracing::warn!("WebView received invalid IPC call.");
+            return;
+          };
+          if let Some(bytes) = js_bytes(&message) {
+            dispatcher.dispatch_binary(frame.request(bytes));
+            return;
+          }
+          let message = message.to_string();
+          // the replies are evaluated in the main frame, `window.ipc` rejects the calls of subframes
+          #[cfg(not(feature = "linux-body"))]
+          if !frame.is_main_frame && crate::ipc::is_invoke(&message) {
+            #[cfg(feature = "tracing")]
+            tracing::warn!("IPC invoke call of a subframe dropped.");
+            return;
+          }
+          let request = frame.request(message);
+          #[cfg(feature = "linux-body")]
+          dispatcher.dispatch(request);
+          #[cfg(not(feature = "linux-body"))]
+          if let Some(reply) = dispatcher.dispatch(request) {
+            let cancellable: Option<&Cancellable> = None;
+            webview.run_javascript(&reply, cancellable, |_| ());
+          }
         }
       }
     });
 
-    // Register the handler we just connected
-    manager.register_script_message_handler("ipc");
+    // Register the handler we just connected, in the world of the script posting the messages
+    manager.register_script_message_handler_in_world("ipc", IPC_WORLD);
+    manager.add_script(&UserScript::for_world(
+      &IPC_FRAME_SCRIPT,
+      UserContentInjectedFrames::AllFrames,
+      UserScriptInjectionTime::Start,
+      IPC_WORLD,
+      &[],
+      &[],
+    ));
+  }
+
+  /// Answers the `window.ipc.invoke` calls with the script message replies of WebKitGTK 2.40.
+  #[cfg(feature = "linux-body")]
+  fn attach_ipc_router(manager: &UserContentManager, router: IpcRouter, policy: Option<IpcPolicy>) {
+    use glib::translate::ToGlibPtr;
+    use std::ffi::CString;
+    use webkit2gtk_sys::{
+      webkit_script_message_reply_return_error_message, webkit_script_message_reply_return_value,
+      webkit_user_content_manager_register_script_message_handler_with_reply,
+      WebKitScriptMessageReply,
+    };
+
+    let signal = format!("script-message-with-reply-received::{IPC_INVOKE_HANDLER}");
+    manager.connect_local(&signal, false, move |values| {
+      #[cfg(feature = "tracing")]
+      let _span = tracing::info_span!(parent: None, "wry::ipc::handle").entered();
+
+      let (Some(Ok(message)), Some(reply)) = (
+        values.get(1).map(|v| v.get::<javascriptcore::Value>()),
+        values.get(2),
+      ) else {
+        return Some(false.to_value());
+      };
+      let reply = unsafe {
+        glib::gobject_ffi::g_value_get_boxed(reply.to_glib_none().0)
+          as *mut WebKitScriptMessageReply
+      };
+
+      let Some((frame, payload)) = frame_message(&message) else {
+        return Some(false.to_value());
+      };
+      match (
+        router.invoke(frame.request(payload.to_string()), policy.as_ref()),
+        message.context(),
+      ) {
+        (Ok(value), Some(context)) => {
+          let value = javascriptcore::Value::new_string(&context, Some(&value));
+          unsafe { webkit_script_message_reply_return_value(reply, value.to_glib_none().0) };
+        }
+        (Ok(_), None) => return Some(false.to_value()),
+        (Err(error), _) => {
+          let error = CString::new(error.replace('\0', "")).unwrap_or_default();
+          unsafe { webkit_script_message_reply_return_error_message(reply, error.as_ptr()) };
+        }
+      }
+      Some(true.to_value())
+    });
+
+    // Register the handler we just connected, in the world of the script posting the calls
+    let name = CString::new(IPC_INVOKE_HANDLER).unwrap();
+    let world = CString::new(IPC_WORLD).unwrap();
+    unsafe {
+      webkit_user_content_manager_register_script_message_handler_with_reply(
+        manager.to_glib_none().0,
+        name.as_ptr(),
+        world.as_ptr(),
+      )
+    };
   }
 
   #[cfg(any(debug_assertions, feature = "devtools"))]
@@ -582,6 +820,10 @@ impl InnerWebView {
     Ok(self.webview.uri().unwrap_or_default().to_string())
   }
 
+  pub fn custom_protocol_url_style(&self) -> UrlStyle {
+    UrlStyle::Custom
+  }
+
   pub fn eval(
     &self,
     js: &str,
@@ -614,16 +856,9 @@ impl InnerWebView {
     Ok(())
   }
 
-  fn init(&self, js: &str) -> Result<()> {
+  fn init(&self, js: &str, frames: UserContentInjectedFrames, block_list: &[&str]) -> Result<()> {
     if let Some(manager) = self.webview.user_content_manager() {
-      let script = UserScript::new(
-        js,
-        // TODO: feature to allow injecting into subframes
-        UserContentInjectedFrames::TopFrame,
-        UserScriptInjectionTime::Start,
-        &[],
-        &[],
-      );
+      let script = UserScript::new(js, frames, UserScriptInjectionTime::Start, &[], block_list);
       manager.add_script(&script);
     } else {
       return Err(Error::InitScriptError);
@@ -956,6 +1191,34 @@ struct SendEnteredSpan(tracing::span::EnteredSpan);
 #[cfg(feature = "tracing")]
 unsafe impl Send for SendEnteredSpan {}
 
+/// The frame and the message of a message posted by [`IPC_FRAME_SCRIPT`].
+fn frame_message(value: &javascriptcore::Value) -> Option<(IpcFrame, javascriptcore::Value)> {
+  let frame = IpcFrame {
+    url: value.object_get_property("url")?.to_str().to_string(),
+    origin: value.object_get_property("origin")?.to_str().to_string(),
+    is_main_frame: value.object_get_property("isMainFrame")?.to_boolean(),
+  };
+  Some((frame, value.object_get_property("message")?))
+}
+
+/// The bytes of an `ArrayBuffer` or `Uint8Array` posted with `window.ipc.postMessage`,
+/// which turns the other typed arrays and data views into `Uint8Array`s.
+fn js_bytes(value: &javascriptcore::Value) -> Option<Vec<u8>> {
+  if value.is_array_buffer() {
+    return Some(value.array_buffer_get_data().to_vec());
+  }
+  if value.is_typed_array() {
+    let buffer = value.typed_array_get_buffer()?;
+    let start = value.typed_array_get_offset();
+    let end = start + value.typed_array_get_size();
+    return buffer
+      .array_buffer_get_data()
+      .get(start..end)
+      .map(<[u8]>::to_vec);
+  }
+  None
+}
+
 const BASE_DPI: f64 = 96.0;
 fn scale_factor_from_x11(xlib: &Xlib, display: *mut _XDisplay, parent: c_ulong) -> f64 {
   let mut attrs = unsafe { std::mem::zeroed() };
@@ -0,0 +1,165 @@
+// Copyright 2020-2024 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+use std::{
+  fmt, io,
+  sync::mpsc::{channel, Receiver, Sender, TryRecvError},
+};
+
+use gtk::{
+  gio::{self, prelude::InputStreamExt, Cancellable},
+  glib::{self, MainContext},
+};
+
+/// How many bytes are requested from WebKit at once.
+const CHUNK_LEN: usize = 64 * 1024;
+
+type ChunkResult = Result<glib::Bytes, glib::Error>;
+
+/// The body of a custom protocol request, read incrementally from the webview.
+///
+/// See [`WebViewBuilderExtUnix::with_streaming_custom_protocol`](crate::WebViewBuilderExtUnix::with_streaming_custom_protocol).
+///
+/// Chunks are requested from WebKit on the GTK main thread only when the body is read,
+/// so reading it from another thread never blocks the UI. Reading it on the main thread
+/// works too, the main context is iterated while waiting for the next chunk.
+pub struct RequestBody {
+  stream: Option<MainThreadStream>,
+  chunk: glib::Bytes,
+  offset: usize,
+  sender: Sender<ChunkResult>,
+  receiver: Receiver<ChunkResult>,
+}
+
+impl RequestBody {
+  pub(crate) fn new(stream: Option<gio::InputStream>) -> Self {
+    let (sender, receiver) = channel();
+    Self {
+      stream: stream.map(MainThreadStream),
+      chunk: glib::Bytes::from_static(&[]),
+      offset: 0,
+      sender,
+      receiver,
+    }
+  }
+
+  /// Reads the whole body without blocking the main thread and calls `callback` with it.
+  ///
+  /// Must be called on the main thread, where `callback` will be called too.
+  pub(crate) fn collect<F>(mut self, callback: F)
+  where
+    F: FnOnce(io::Result<Vec<u8>>) + 'static,
+  {
+    fn read_all<F>(stream: gio::InputStream, mut buffer: Vec<u8>, callback: F)
+    where
+      F: FnOnce(io::Result<Vec<u8>>) + 'static,
+    {
+      let cancellable: Option<&Cancellable> = None;
+      stream.clone().read_bytes_async(
+        CHUNK_LEN,
+        glib::Priority::DEFAULT,
+        cancellable,
+        move |result| match result {
+          Ok(bytes) if bytes.is_empty() => callback(Ok(buffer)),
+          Ok(bytes) => {
+            buffer.extend_from_slice(&bytes);
+            read_all(stream, buffer, callback);
+          }
+          Err(e) => callback(Err(io::Error::other(e))),
+        },
+      );
+    }
+
+    let buffer = self.chunk[self.offset..].to_vec();
+    match self.stream.take() {
+      Some(stream) => read_all(stream.0, buffer, callback),
+      None => callback(Ok(buffer)),
+    }
+  }
+
+  /// Releases the stream on the main thread, where it was created.
+  fn release(&mut self) {
+    if let Some(stream) = self.stream.take() {
+      MainContext::default().invoke(move || drop(stream));
+    }
+  }
+
+  fn next_chunk(&mut self, stream: MainThreadStream) -> ChunkResult {
+    let sender = self.sender.clone();
+    let context = MainContext::default();
+    context.invoke(move || {
+      // move the whole wrapper into the closure, not just the non-`Send` stream field
+      let stream = stream;
+      let cancellable: Option<&Cancellable> = None;
+      stream.0.read_bytes_async(
+        CHUNK_LEN,
+        glib::Priority::DEFAULT,
+        cancellable,
+        move |result| {
+          let _ = sender.send(result);
+        },
+      );
+    });
+
+    if context.is_owner() {
+      loop {
+        match self.receiver.try_recv() {
+          Ok(result) => return result,
+          Err(TryRecvError::Empty) => {
+            context.iteration(true);
+          }
+          Err(TryRecvError::Disconnected) => unreachable!("we hold a sender"),
+        }
+      }
+    } else {
+      self.receiver.recv().expect("we hold a sender")
+    }
+  }
+}
+
+impl io::Read for RequestBody {
+  fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+    if buf.is_empty() {
+      return Ok(0);
+    }
+
+    while self.offset >= self.chunk.len() {
+      let Some(stream) = self.stream.clone() else {
+        return Ok(0);
+      };
+
+      let chunk = self.next_chunk(stream).map_err(io::Error::other)?;
+      if chunk.is_empty() {
+        self.release();
+      }
+      self.chunk = chunk;
+      self.offset = 0;
+    }
+
+    let count = buf.len().min(self.chunk.len() - self.offset);
+    buf[..count].copy_from_slice(&self.chunk[self.offset..self.offset + count]);
+    self.offset += count;
+    Ok(count)
+  }
+}
+
+impl Drop for RequestBody {
+  fn drop(&mut self) {
+    self.release();
+  }
+}
+
+impl fmt::Debug for RequestBody {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("RequestBody")
+      .field("finished", &self.stream.is_none())
+      .finish()
+  }
+}
+
+#[derive(Clone)]
+struct MainThreadStream(gio::InputStream);
+
+// SAFETY: the stream is only used on the main thread, we only move it around to get it there.
+unsafe impl Send for MainThreadStream {}
@@ -4,16 +4,26 @@
 
 //! Unix platform extensions for [`WebContext`](super::WebContext).
 
-use crate::{Error, RequestAsyncResponder};
-use gtk::glib::{self, MainContext, ObjectExt};
-use http::{header::CONTENT_TYPE, HeaderName, HeaderValue, Request, Response as HttpResponse};
+use super::RequestBody;
+use crate::{
+  custom_protocol_redirect::{self, Redirect},
+  CancellationToken, CustomProtocolOptions, Error, RequestAsyncResponder, ResponseBody, WebViewId,
+};
+use gtk::{
+  gio,
+  glib::{self, Cast, MainContext, ObjectExt},
+};
+use http::{
+  header::{CONTENT_LENGTH, CONTENT_TYPE},
+  HeaderName, HeaderValue, Request, Response as HttpResponse, StatusCode,
+};
 use soup::{MessageHeaders, MessageHeadersType};
 use std::{
-  borrow::Cow,
   cell::RefCell,
-  collections::VecDeque,
+  collections::{HashMap, VecDeque},
+  fmt,
   path::{Path, PathBuf},
-  rc::Rc,
+  rc::{Rc, Weak},
   sync::{
     atomic::{AtomicBool, Ordering::SeqCst},
     Mutex,
@@ -21,17 +31,31 @@ use std::{
 };
 use webkit2gtk::{
   ApplicationInfo, AutomationSessionExt, CookiePersistentStorage, DownloadExt, LoadEvent,
-  SecurityManagerExt, URIRequest, URIRequestExt, URISchemeRequest, URISchemeRequestExt,
-  URISchemeResponse, URISchemeResponseExt, WebContext, WebContextExt as Webkit2gtkContextExt,
-  WebView, WebViewExt,
+  SecurityManager, SecurityManagerExt, URIRequest, URIRequestExt, URISchemeRequest,
+  URISchemeRequestExt, URISchemeResponse, URISchemeResponseExt, WebContext,
+  WebContextExt as Webkit2gtkContextExt, WebView, WebViewExt,
 };
 
+type StreamingHandler = dyn Fn(WebViewId, Request<RequestBody>, RequestAsyncResponder);
+
+/// The custom protocol handlers of the webviews in a context, by scheme and webview id.
+#[derive(Default)]
+struct CustomProtocols(RefCell<HashMap<(String, String), Rc<StreamingHandler>>>);
+
+impl fmt::Debug for CustomProtocols {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_set().entries(self.0.borrow().keys()).finish()
+  }
+}
+
 #[derive(Debug)]
 pub struct WebContextImpl {
   context: WebContext,
   webview_uri_loader: Rc<WebViewUriLoader>,
   automation: bool,
   app_info: Option<ApplicationInfo>,
+  scheme_options: HashMap<String, CustomProtocolOptions>,
+  custom_protocols: Rc<CustomProtocols>,
 }
 
 impl WebContextImpl {
@@ -85,6 +109,8 @@ impl WebContextImpl {
       automation,
       webview_uri_loader: Rc::default(),
       app_info: Some(app_info),
+      scheme_options: HashMap::new(),
+      custom_protocols: Rc::default(),
     }
   }
 
@@ -98,6 +124,41 @@ impl WebContextImpl {
       .context
       .set_web_extensions_directory(&path.to_string_lossy());
   }
+
+  /// The custom protocols of the webview `webview_id`, deregistered when the returned value is dropped.
+  pub(crate) fn webview_custom_protocols(&self, webview_id: &str) -> WebViewCustomProtocols {
+    WebViewCustomProtocols {
+      custom_protocols: Rc::downgrade(&self.custom_protocols),
+      webview_id: webview_id.to_string(),
+      handlers: Vec::new(),
+    }
+  }
+}
+
+/// The custom protocol handlers a webview registered on its context.
+///
+/// WebKit can't unregister schemes, so dropping it only removes the handlers of the webview,
+/// whose scheme keeps being handled by the context.
+pub(crate) struct WebViewCustomProtocols {
+  custom_protocols: Weak<CustomProtocols>,
+  webview_id: String,
+  handlers: Vec<(String, Rc<StreamingHandler>)>,
+}
+
+impl Drop for WebViewCustomProtocols {
+  fn drop(&mut self) {
+    let Some(custom_protocols) = self.custom_protocols.upgrade() else {
+      return;
+    };
+    let mut handlers = custom_protocols.0.borrow_mut();
+    for (name, handler) in self.handlers.drain(..) {
+      let key = (name, self.webview_id.clone());
+      // another webview with the same id may have replaced the handler
+      if handlers.get(&key).is_some_and(|h| Rc::ptr_eq(h, &handler)) {
+        handlers.remove(&key);
+      }
+    }
+  }
 }
 
 /// [`WebContext`](super::WebContext) items that only matter on unix.
@@ -105,11 +166,40 @@ pub trait WebContextExt {
   /// The GTK [`WebContext`] of all webviews in the context.
   fn context(&self) -> &WebContext;
 
-  /// Register a custom protocol to the web context.
-  fn register_uri_scheme<F>(&mut self, name: &str, handler: F) -> crate::Result<()>
+  /// Register a custom protocol handler of a webview to the web context.
+  ///
+  /// The scheme is registered once per context, and its requests are dispatched to the handler
+  /// of the requesting webview until `custom_protocols` is dropped.
+  ///
+  /// The request body is read without blocking the main thread before calling the handler.
+  fn register_uri_scheme<F>(
+    &mut self,
+    name: &str,
+    custom_protocols: &mut WebViewCustomProtocols,
+    handler: F,
+  ) -> crate::Result<()>
   where
     F: Fn(crate::WebViewId, Request<Vec<u8>>, RequestAsyncResponder) + 'static;
 
+  /// Register a custom protocol handler of a webview to the web context, which reads the request
+  /// body as a stream.
+  ///
+  /// See [`WebContextExt::register_uri_scheme`] for the dispatching of requests.
+  fn register_streaming_uri_scheme<F>(
+    &mut self,
+    name: &str,
+    custom_protocols: &mut WebViewCustomProtocols,
+    handler: F,
+  ) -> crate::Result<()>
+  where
+    F: Fn(crate::WebViewId, Request<RequestBody>, RequestAsyncResponder) + 'static;
+
+  /// Set the privileges of a custom protocol, applied when it is registered.
+  ///
+  /// WebKit can't revoke privileges, so this has no effect on already registered schemes.
+  #[cfg(feature = "protocol")]
+  fn set_uri_scheme_options(&mut self, name: &str, options: CustomProtocolOptions);
+
   /// Add a [`WebView`] to the queue waiting to be opened.
   ///
   /// See the [`WebViewUriLoader`] for more information.
@@ -139,18 +229,87 @@ impl WebContextExt for super::WebContext {
     &self.os.context
   }
 
-  fn register_uri_scheme<F>(&mut self, name: &str, handler: F) -> crate::Result<()>
+  fn register_uri_scheme<F>(
+    &mut self,
+    name: &str,
+    custom_protocols: &mut WebViewCustomProtocols,
+    handler: F,
+  ) -> crate::Result<()>
   where
     F: Fn(crate::WebViewId, Request<Vec<u8>>, RequestAsyncResponder) + 'static,
   {
-    // Enable secure context
+    let handler = Rc::new(handler);
+    self.register_streaming_uri_scheme(
+      name,
+      custom_protocols,
+      move |webview_id, request, responder| {
+        let (parts, body) = request.into_parts();
+        let webview_id = webview_id.to_string();
+        let handler = handler.clone();
+        // the body is read after the request span exits, the handler still runs in it
+        #[cfg(feature = "tracing")]
+        let span = tracing::Span::current();
+        body.collect(move |body| {
+          #[cfg(feature = "tracing")]
+          let _span = span.enter();
+          match body {
+            Ok(body) => handler(&webview_id, Request::from_parts(parts, body), responder),
+            Err(_e) => {
+              #[cfg(feature = "tracing")]
+              tracing::warn!("failed to read custom protocol request body: {_e}");
+              responder.respond(
+                HttpResponse::builder()
+                  .status(StatusCode::BAD_REQUEST)
+                  .body(Vec::new())
+                  .unwrap(),
+              );
+            }
+          }
+        });
+      },
+    )
+  }
+
+  fn register_streaming_uri_scheme<F>(
+    &mut self,
+    name: &str,
+    custom_protocols: &mut WebViewCustomProtocols,
+    handler: F,
+  ) -> crate::Result<()>
+  where
+    F: Fn(crate::WebViewId, Request<RequestBody>, RequestAsyncResponder) + 'static,
+  {
+    let handler: Rc<StreamingHandler> = Rc::new(handler);
+    let key = (name.to_string(), custom_protocols.webview_id.clone());
     self
+      .os
+      .custom_protocols
+      .0
+      .borrow_mut()
+      .insert(key, handler.clone());
+    custom_protocols.handlers.push((name.to_string(), handler));
+
+    // the scheme is registered once per context, and dispatches its requests
+    // to the handler of the requesting webview
+    if self.custom_protocols.contains(name) {
+      return Ok(());
+    }
+
+    let options = self
+      .os
+      .scheme_options
+      .get(name)
+      .copied()
+      .unwrap_or_default();
+    let security_manager = self
       .os
       .context
       .security_manager()
-      .ok_or(Error::MissingManager)?
-      .register_uri_scheme_as_secure(name);
+      .ok_or(Error::MissingManager)?;
+    register_scheme_privileges(&security_manager, name, options);
 
+    let scheme = name.to_string();
+    let handlers = self.os.custom_protocols.clone();
     self.os.context.register_uri_scheme(name, move |request| {
       #[cfg(feature = "tracing")]
       let span = tracing::info_span!(parent: None, "wry::custom_protocol::handle", uri = tracing::field::Empty).entered();
@@ -182,37 +341,11 @@ impl WebContextExt for super::WebContext {
           http_request = http_request.method(method.as_str());
         }
 
-        let body;
+        // Set request http body, it is read lazily by the handler
         #[cfg(feature = "linux-body")]
-        {
-          use gtk::{gdk::prelude::InputStreamExtManual, gio::Cancellable};
-
-          // Set request http body
-          let cancellable: Option<&Cancellable> = None;
-          body = request
-            .http_body()
-            .map(|s| {
-              const BUFFER_LEN: usize = 1024;
-              let mut result = Vec::new();
-              let mut buffer = vec![0; BUFFER_LEN];
-              while let Ok(count) = s.read(&mut buffer[..], cancellable) {
-                if count == BUFFER_LEN {
-                  result.append(&mut buffer);
-                  buffer.resize(BUFFER_LEN, 0);
-                } else {
-                  buffer.truncate(count);
-                  result.append(&mut buffer);
-                  break;
-                }
-              }
-              result
-            })
-            .unwrap_or_default();
-        }
+        let body = RequestBody::new(request.http_body());
         #[cfg(not(feature = "linux-body"))]
-        {
-          body = Vec::new();
-        }
+        let body = RequestBody::new(None);
 
         let http_request = match http_request.body(body) {
           Ok(req) => req,
@@ -225,29 +358,77 @@ impl WebContextExt for super::WebContext {
           }
         };
 
-        let request_ = MainThreadRequest(request.clone());
-        let responder: Box<dyn FnOnce(HttpResponse<Cow<'static, [u8]>>)> =
+        // WebKit holds the only strong reference to the request until it is answered,
+        // so it is released early when WebKit cancels the request.
+        let cancellation = CancellationToken::new();
+        let cancellation_ = cancellation.clone();
+        request.add_weak_ref_notify_local(move || cancellation_.cancel());
+
+        let webview_id = request
+          .web_view()
+          .and_then(|w| unsafe { w.data::<String>(super::WEBVIEW_ID) })
+          .map(|id| unsafe { id.as_ref().clone() })
+          .unwrap_or_default();
+
+        // the borrow ends before calling the handler, which may register other webviews
+        let handler = handlers
+          .0
+          .borrow()
+          .get(&(scheme.clone(), webview_id.clone()))
+          .cloned();
+        let Some(handler) = handler else {
+          request.finish_error(&mut glib::Error::new(
+            glib::FileError::Noent,
+            "No custom protocol handler for this webview.",
+          ));
+          return;
+        };
+
+        let request_ = MainThreadRequest(request.downgrade());
+        let webview_id_ = webview_id.clone();
+        let responder: Box<dyn FnOnce(HttpResponse<ResponseBody>)> =
           Box::new(move |http_response| {
             MainContext::default().invoke(move || {
-              let buffer = http_response.body();
-              let input = gtk::gio::MemoryInputStream::from_bytes(&gtk::glib::Bytes::from(buffer));
+              let (http_response, redirect) = request_.follow_redirect(&webview_id_, http_response);
+              let (http_response, body) = http_response.into_parts();
+              let (input, length) = match body {
+                ResponseBody::Bytes(buffer) => (
+                  gio::MemoryInputStream::from_bytes(&glib::Bytes::from(&buffer))
+                    .upcast::<gio::InputStream>(),
+                  buffer.len() as i64,
+                ),
+                // WebKit reads the stream asynchronously, which runs the blocking reads on a worker thread.
+                ResponseBody::Reader(reader) => (
+                  gio::ReadInputStream::new(reader).upcast::<gio::InputStream>(),
+                  http_response
+                    .headers
+                    .get(CONTENT_LENGTH)
+                    .and_then(|h| h.to_str().ok())
+                    .and_then(|h| h.parse::<i64>().ok())
+                    .unwrap_or(-1),
+                ),
+              };
               let content_type = http_response
-                .headers()
+                .headers
                 .get(CONTENT_TYPE)
                 .and_then(|h| h.to_str().ok());
 
-              let response = URISchemeResponse::new(&input, buffer.len() as i64);
-              response.set_status(http_response.status().as_u16() as u32, None);
+              let response = URISchemeResponse::new(&input, length);
+              response.set_status(http_response.status.as_u16() as u32, None);
               if let Some(content_type) = content_type {
                 response.set_content_type(content_type);
               }
 
               let headers = MessageHeaders::new(MessageHeadersType::Response);
-              for (name, value) in http_response.headers().into_iter() {
+              for (name, value) in http_response.headers.iter() {
                 headers.append(name.as_str(), value.to_str().unwrap_or(""));
               }
               response.set_http_headers(headers);
               request_.finish_with_response(&response);
+
+              if let Some((webview, url)) = redirect {
+                webview.load_uri(&url);
+              }
             });
 
           });
@@ -255,13 +436,14 @@ impl WebContextExt for super::WebContext {
         #[cfg(feature = "tracing")]
         let _span = tracing::info_span!("wry::custom_protocol::call_handler").entered();
 
-        let webview_id = request
-          .web_view()
-          .and_then(|w| unsafe { w.data::<String>(super::WEBVIEW_ID) })
-          .map(|id| unsafe { id.as_ref().clone() })
-          .unwrap_or_default();
-
-        handler(&webview_id, http_request, RequestAsyncResponder { responder });
+        handler(
+          &webview_id,
+          http_request,
+          RequestAsyncResponder {
+            responder,
+            cancellation,
+          },
+        );
       } else {
         request.finish_error(&mut glib::Error::new(
           glib::FileError::Exist,
@@ -270,9 +452,15 @@ impl WebContextExt for super::WebContext {
       }
     });
 
+    self.custom_protocols.insert(name.to_string());
     Ok(())
   }
 
+  #[cfg(feature = "protocol")]
+  fn set_uri_scheme_options(&mut self, name: &str, options: CustomProtocolOptions) {
+    self.os.scheme_options.insert(name.to_string(), options);
+  }
+
   fn queue_load_uri(&self, webview: WebView, url: String, headers: Option<http::HeaderMap>) {
     self.os.webview_uri_loader.push(webview, url, headers)
   }
@@ -361,11 +549,69 @@ impl WebContextExt for super::WebContext {
   }
 }
 
-struct MainThreadRequest(URISchemeRequest);
+/// Grants `options` to the `name` scheme.
+fn register_scheme_privileges(
+  security_manager: &SecurityManager,
+  name: &str,
+  options: CustomProtocolOptions,
+) {
+  if options.secure {
+    security_manager.register_uri_scheme_as_secure(name);
+  }
+  if options.cors_enabled {
+    security_manager.register_uri_scheme_as_cors_enabled(name);
+  }
+  if options.local {
+    security_manager.register_uri_scheme_as_local(name);
+  }
+  if options.no_access {
+    security_manager.register_uri_scheme_as_no_access(name);
+  }
+  if options.display_isolated {
+    security_manager.register_uri_scheme_as_display_isolated(name);
+  }
+  if options.empty_document {
+    security_manager.register_uri_scheme_as_empty_document(name);
+  }
+}
+
+struct MainThreadRequest(glib::WeakRef<URISchemeRequest>);
 
 impl MainThreadRequest {
   fn finish_with_response(&self, response: &URISchemeResponse) {
-    self.0.finish_with_response(response);
+    // the request is gone if WebKit cancelled it
+    if let Some(request) = self.0.upgrade() {
+      request.finish_with_response(response);
+    }
+  }
+
+  /// WebKit doesn't follow redirects of custom protocols, so a redirect of the main document
+  /// is answered with no content and returns the target to load in the webview instead.
+  fn follow_redirect(
+    &self,
+    webview_id: &str,
+    response: HttpResponse<ResponseBody>,
+  ) -> (HttpResponse<ResponseBody>, Option<(WebView, String)>) {
+    let Some(request) = self.0.upgrade() else {
+      return (response, None);
+    };
+    let (Some(webview), Some(uri)) = (request.web_view(), request.uri()) else {
+      return (response, None);
+    };
+    // only the main document request has the uri of the webview
+    if webview.uri().as_deref() != Some(uri.as_str()) {
+      return (response, None);
+    }
+
+    match custom_protocol_redirect::follow(webview_id, &uri, &response) {
+      None => (response, None),
+      Some(Redirect::To(url)) => (custom_protocol_redirect::no_content(), Some((webview, url))),
+      Some(Redirect::TooMany) => {
+        #[cfg(feature = "tracing")]
+        tracing::warn!("too many redirects loading {uri}");
+        (custom_protocol_redirect::too_many_redirects(), None)
+      }
+    }
   }
 }
 
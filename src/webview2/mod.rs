@@ -6,12 +6,18 @@ mod drag_drop;
 mod util;
 
 use std::{
-  borrow::Cow, cell::RefCell, collections::HashSet, fmt::Write, fs, path::PathBuf, rc::Rc,
-  sync::mpsc,
+  borrow::Cow,
+  cell::RefCell,
+  collections::HashSet,
+  fmt::Write,
+  fs,
+  path::PathBuf,
+  rc::Rc,
+  sync::{mpsc, Arc},
 };
 
 use dpi::{PhysicalPosition, PhysicalSize};
-use http::{Request, Response as HttpResponse, StatusCode};
+use http::{header::LOCATION, HeaderValue, Request, Response as HttpResponse, StatusCode};
 use once_cell::sync::Lazy;
 use raw_window_handle::{HasWindowHandle, RawWindowHandle};
 use webview2_com::{Microsoft::Web::WebView2::Win32::*, *};
@@ -29,8 +35,12 @@ use windows::{
 use self::drag_drop::DragDropController;
 use super::Theme;
 use crate::{
-  proxy::ProxyConfig, Error, MemoryUsageLevel, PageLoadEvent, Rect, RequestAsyncResponder, Result,
-  WebViewAttributes, RGBA,
+  buffer_response,
+  custom_protocol_url::UrlStyle,
+  ipc::{IpcDispatcher, IpcFrame},
+  proxy::ProxyConfig,
+  Error, MemoryUsageLevel, PageLoadEvent, Rect, RequestAsyncResponder, Result, WebViewAttributes,
+  RGBA,
 };
 
 const PARENT_SUBCLASS_ID: u32 = WM_USER + 0x64;
@@ -58,6 +68,7 @@ pub(crate) struct InnerWebView {
   pub controller: ICoreWebView2Controller,
   webview: ICoreWebView2,
   env: ICoreWebView2Environment,
+  url_style: UrlStyle,
   // Store FileDropController in here to make sure it gets dropped when
   // the webview gets dropped, otherwise we'll have a memory leak
   #[allow(dead_code)]
@@ -121,6 +132,7 @@ impl InnerWebView {
       .map(|id| id.to_string())
       .unwrap_or_else(|| (hwnd.0 as isize).to_string());
 
+    let url_style = UrlStyle::workaround(pl_attrs.use_https);
     let env = Self::create_environment(&attributes, pl_attrs.clone())?;
     let controller = Self::create_controller(hwnd, &env, attributes.incognito)?;
     let webview = Self::init_webview(
@@ -131,6 +143,7 @@ impl InnerWebView {
       &env,
       &controller,
       pl_attrs,
+      url_style,
       is_child,
     )?;
 
@@ -144,6 +157,7 @@ impl InnerWebView {
       is_child,
       webview,
       env,
+      url_style,
       drag_drop_controller,
     };
 
@@ -375,6 +389,7 @@ impl InnerWebView {
     env: &ICoreWebView2Environment,
     controller: &ICoreWebView2Controller,
     pl_attrs: super::PlatformSpecificWebViewAttributes,
+    url_style: UrlStyle,
     is_child: bool,
   ) -> Result<ICoreWebView2> {
     let webview = unsafe { controller.CoreWebView2()? };
@@ -417,10 +432,9 @@ impl InnerWebView {
     unsafe { Self::attach_handlers(hwnd, &webview, &mut attributes, &mut token)? };
 
     // IPC handler
-    unsafe { Self::attach_ipc_handler(&webview, &mut attributes, &mut token)? };
+    unsafe { Self::attach_ipc_handler(&webview, &mut attributes, url_style, &mut token)? };
 
     // Custom protocols handler
-    let scheme = if pl_attrs.use_https { "https" } else { "http" };
     let custom_protocols: HashSet<String> = attributes
       .custom_protocols
       .iter()
@@ -433,7 +447,7 @@ impl InnerWebView {
           env,
           hwnd,
           webview_id,
-          scheme,
+          url_style,
           &mut attributes,
           &mut token,
         )?
@@ -467,13 +481,12 @@ impl InnerWebView {
 
     // Navigation
     if let Some(mut url) = attributes.url {
-      if let Some(pos) = url.find("://") {
-        let name = &url[..pos];
-        if custom_protocols.contains(name) {
-          // WebView2 supports non-standard protocols only on Windows 10+, so we have to use this workaround
-          // See https://github.com/MicrosoftEdge/WebView2Feedback/issues/73
-          url = url.replace(&format!("{name}://"), &format!("{scheme}://{name}."))
-        }
+      // WebView2 supports non-standard protocols only on Windows 10+, so we have to use this workaround
+      if let Some(platform_url) = custom_protocols
+        .iter()
+        .find_map(|name| url_style.to_platform(&url, name))
+      {
+        url = platform_url;
       }
 
       if let Some(headers) = attributes.headers {
@@ -750,19 +763,25 @@ impl InnerWebView {
   unsafe fn attach_ipc_handler(
     webview: &ICoreWebView2,
     attributes: &mut WebViewAttributes,
+    url_style: UrlStyle,
     token: &mut EventRegistrationToken,
   ) -> Result<()> {
     Self::add_script_to_execute_on_document_created(
       webview,
-      String::from(
-        r#"Object.defineProperty(window, 'ipc', { value: Object.freeze({ postMessage: s=> window.chrome.webview.postMessage(s) }) });"#,
-      ),
+      crate::ipc::ipc_script("s=> window.chrome.webview.postMessage(s)", false),
     )?;
 
-    let ipc_handler = attributes.ipc_handler.take();
+    let dispatcher = IpcDispatcher::new(
+      attributes.ipc_handler.take(),
+      attributes.ipc_router.take(),
+      attributes
+        .ipc_policy
+        .take()
+        .map(|policy| policy.resolve(url_style)),
+    );
     webview.add_WebMessageReceived(
-      &WebMessageReceivedEventHandler::create(Box::new(move |_, args| {
-        let (Some(args), Some(ipc_handler)) = (args, &ipc_handler) else {
+      &WebMessageReceivedEventHandler::create(Box::new(move |webview, args| {
+        let (Some(args), Some(dispatcher)) = (args, &dispatcher) else {
           return Ok(());
         };
 
@@ -780,7 +799,18 @@ impl InnerWebView {
 
         #[cfg(feature = "tracing")]
         let _span = tracing::info_span!(parent: None, "wry::ipc::handle").entered();
-        ipc_handler(Request::builder().uri(url).body(js).unwrap());
+        // messages of the iframes are not received by the webview
+        let frame = IpcFrame {
+          origin: url::Url::parse(&url)
+            .map(|url| url.origin().ascii_serialization())
+            .unwrap_or_else(|_| "null".into()),
+          url,
+          is_main_frame: true,
+        };
+        let reply = dispatcher.dispatch(frame.request(js));
+        if let (Some(reply), Some(webview)) = (reply, webview) {
+          Self::execute_script(&webview, reply, |_| ())?;
+        }
 
         Ok(())
       })),
@@ -796,19 +826,26 @@ impl InnerWebView {
     env: &ICoreWebView2Environment,
     hwnd: HWND,
     webview_id: String,
-    scheme: &'static str,
+    url_style: UrlStyle,
     attributes: &mut WebViewAttributes,
     token: &mut EventRegistrationToken,
   ) -> Result<()> {
     for (name, _) in &attributes.custom_protocols {
       // WebView2 supports non-standard protocols only on Windows 10+, so we have to use this workaround
       // See https://github.com/MicrosoftEdge/WebView2Feedback/issues/73
-      let filter = HSTRING::from(format!("{scheme}://{name}.*"));
+      let filter = url_style
+        .to_platform(&format!("{name}://*"), name)
+        .unwrap_or_default();
+      let filter = HSTRING::from(filter);
       webview.AddWebResourceRequestedFilter(&filter, COREWEBVIEW2_WEB_RESOURCE_CONTEXT_ALL)?;
     }
 
     let env = env.clone();
     let custom_protocols = std::mem::take(&mut attributes.custom_protocols);
+    let protocol_names = custom_protocols
+      .iter()
+      .map(|(name, _)| name.clone())
+      .collect::<Arc<[String]>>();
     let main_thread_id = std::thread::current().id();
 
     webview.add_WebResourceRequested(
@@ -833,12 +870,15 @@ impl InnerWebView {
         #[cfg(feature = "tracing")]
         span.record("uri", &uri);
 
-        if let Some((custom_protocol, custom_protocol_handler)) = custom_protocols
-          .iter()
-          .find(|(protocol, _)| is_custom_protocol_uri(&uri, scheme, protocol))
+        // Undo the protocol workaround when giving the uri to the handler
+        if let Some((custom_uri, custom_protocol_handler)) =
+          custom_protocols.iter().find_map(|(protocol, handler)| {
+            url_style
+              .to_custom(&uri, protocol)
+              .map(|custom_uri| (custom_uri, handler))
+          })
         {
-          let request = match Self::prepare_request(scheme, custom_protocol, &webview_request, &uri)
-          {
+          let request = match Self::prepare_request(&webview_request, &custom_uri) {
             Ok(req) => req,
             Err(e) => {
               let err_response = Self::prepare_web_request_err(&env, e)?;
@@ -849,8 +889,27 @@ impl InnerWebView {
 
           let env = env.clone();
           let deferral = args.GetDeferral();
+          let protocol_names = protocol_names.clone();
 
           let async_responder = Box::new(move |sent_response| {
+            // WebView2 needs the whole body upfront, read streamed bodies before going back to the main thread
+            let mut sent_response = buffer_response(sent_response);
+
+            // WebView2 follows redirects by itself, custom protocol targets need the workaround too
+            let location = sent_response
+              .headers()
+              .get(LOCATION)
+              .and_then(|location| location.to_str().ok())
+              .and_then(|location| {
+                protocol_names
+                  .iter()
+                  .find_map(|name| url_style.to_platform(location, name))
+              })
+              .and_then(|location| HeaderValue::from_str(&location).ok());
+            if let Some(location) = location {
+              sent_response.headers_mut().insert(LOCATION, location);
+            }
+
             let handler = move || {
               match Self::prepare_web_request_response(&env, &sent_response) {
                 Ok(response) => {
@@ -882,6 +941,8 @@ impl InnerWebView {
             request,
             RequestAsyncResponder {
               responder: async_responder,
+              // WebView2 doesn't notify about cancelled requests
+              cancellation: Default::default(),
             },
           );
         }
@@ -898,10 +959,8 @@ impl InnerWebView {
 
   #[inline]
   unsafe fn prepare_request(
-    scheme: &'static str,
-    custom_protocol: &str,
     webview_request: &ICoreWebView2WebResourceRequest,
-    webview_request_uri: &str,
+    uri: &str,
   ) -> Result<http::Request<Vec<u8>>> {
     let mut request = Request::builder();
 
@@ -949,13 +1008,7 @@ impl InnerWebView {
       }
     }
 
-    // Undo the protocol workaround when giving path to resolver
-    let path = webview_request_uri.replace(
-      &format!("{scheme}://{}.", custom_protocol),
-      &format!("{}://", custom_protocol),
-    );
-
-    let request = request.uri(&path).body(body_sent)?;
+    let request = request.uri(uri).body(body_sent)?;
 
     Ok(request)
   }
@@ -1227,6 +1280,10 @@ impl InnerWebView {
     Self::url_from_webview(&self.webview).map_err(Into::into)
   }
 
+  pub fn custom_protocol_url_style(&self) -> UrlStyle {
+    self.url_style
+  }
+
   pub fn zoom(&self, scale_factor: f64) -> Result<()> {
     unsafe { self.controller.SetZoomFactor(scale_factor) }.map_err(Into::into)
   }
@@ -1610,22 +1667,6 @@ unsafe fn set_theme(webview: &ICoreWebView2, theme: Theme) -> Result<()> {
     .map_err(Into::into)
 }
 
-#[inline]
-fn is_custom_protocol_uri(uri: &str, scheme: &'static str, protocol: &str) -> bool {
-  let uri_len = uri.len();
-  let scheme_len = scheme.len();
-  let protocol_len = protocol.len();
-
-  // starts with `http` or `https``
-  &uri[..scheme_len] == scheme
-  // followed by `://`
-  && &uri[scheme_len..scheme_len + 3] == "://"
-  // followed by custom protocol name
-  && scheme_len + 3 + protocol_len < uri_len && &uri[scheme_len + 3.. scheme_len + 3 + protocol_len] == protocol
-  // and a dot
-  && scheme_len + 3 + protocol_len < uri_len && uri.as_bytes()[scheme_len + 3 + protocol_len] == b'.'
-}
-
 pub fn platform_webview_version() -> Result<String> {
   let mut versioninfo = PWSTR::null();
   unsafe { GetAvailableCoreWebView2BrowserVersionString(PCWSTR::null(), &mut versioninfo) }?;
@@ -1638,16 +1679,3 @@ fn is_windows_7() -> bool {
   // windows 7 is 6.1
   v.major == 6 && v.minor == 1
 }
-
-#[cfg(test)]
-mod tests {
-  use super::is_custom_protocol_uri;
-
-  #[test]
-  fn checks_if_custom_protocol_uri() {
-    let scheme = "http";
-    let uri = "http://wry.localhost/path/to/page";
-    assert!(is_custom_protocol_uri(uri, scheme, "wry"));
-    assert!(!is_custom_protocol_uri(uri, scheme, "asset"));
-  }
-}
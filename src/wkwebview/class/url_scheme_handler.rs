@@ -3,8 +3,8 @@
 // SPDX-License-Identifier: MIT
 
 use std::{
-  borrow::Cow,
   ffi::{c_char, c_void, CStr},
+  io::Read,
   panic::AssertUnwindSafe,
   ptr::NonNull,
   slice,
@@ -20,12 +20,17 @@ use objc2::{
   ClassType,
 };
 use objc2_foundation::{
-  NSData, NSHTTPURLResponse, NSMutableDictionary, NSObject, NSObjectProtocol, NSString, NSURL,
-  NSUUID,
+  NSData, NSError, NSHTTPURLResponse, NSMutableDictionary, NSObject,
+  NSObjectNSThreadPerformAdditions, NSObjectProtocol, NSString, NSURLErrorDomain, NSURLRequest,
+  NSURL, NSUUID,
 };
 use objc2_web_kit::{WKURLSchemeHandler, WKURLSchemeTask};
 
-use crate::{wkwebview::WEBVIEW_IDS, RequestAsyncResponder, WryWebView};
+use crate::{
+  custom_protocol_redirect::{self, Redirect},
+  wkwebview::WEBVIEW_IDS,
+  CancellationToken, RequestAsyncResponder, ResponseBody, WryWebView,
+};
 
 pub fn create(name: &str) -> &AnyClass {
   unsafe {
@@ -63,7 +68,8 @@ extern "C" fn start_task(
             .entered();
 
     let task_key = task.hash(); // hash by task object address
-    let task_uuid = webview.add_custom_task_key(task_key);
+    let cancellation = CancellationToken::new();
+    let task_uuid = webview.add_custom_task_key(task_key, cancellation.clone());
 
     let ivar = this.class().instance_variable("webview_id").unwrap();
     let webview_id_ptr: *mut c_char = *ivar.load(this);
@@ -87,6 +93,12 @@ extern "C" fn start_task(
       #[cfg(feature = "tracing")]
       span.record("uri", uri.clone());
 
+      let is_main_document = request
+        .mainDocumentURL()
+        .and_then(|url| url.absoluteString())
+        .is_some_and(|url| url.to_string() == uri);
+      let redirect_uri = uri.clone();
+
       // Get request method (GET, POST, PUT etc...)
       let method = request.HTTPMethod().unwrap().to_string();
 
@@ -148,7 +160,7 @@ extern "C" fn start_task(
       // send response
       match http_request.body(sent_form_body) {
         Ok(final_request) => {
-          let responder: Box<dyn FnOnce(HttpResponse<Cow<'static, [u8]>>)> =
+          let responder: Box<dyn FnOnce(HttpResponse<ResponseBody>)> =
             Box::new(move |sent_response| {
               fn check_webview_id_valid(webview_id: &str) -> crate::Result<()> {
                 if !WEBVIEW_IDS.lock().unwrap().contains(webview_id) {
@@ -187,17 +199,17 @@ extern "C" fn start_task(
                 task_uuid: Retained<NSUUID>,
                 webview_id: &str,
                 url: Retained<NSURL>,
-                sent_response: HttpResponse<Cow<'_, [u8]>>,
+                sent_response: HttpResponse<ResponseBody>,
               ) -> crate::Result<()> {
                 check_task_is_valid(&*webview, task_key, task_uuid.clone())?;
 
-                let content = sent_response.body();
+                let (sent_response, content) = sent_response.into_parts();
                 // default: application/octet-stream, but should be provided by the client
-                let wanted_mime = sent_response.headers().get(CONTENT_TYPE);
+                let wanted_mime = sent_response.headers.get(CONTENT_TYPE);
                 // default to 200
-                let wanted_status_code = sent_response.status().as_u16() as i32;
+                let wanted_status_code = sent_response.status.as_u16() as i32;
                 // default to HTTP/1.1
-                let wanted_version = format!("{:#?}", sent_response.version());
+                let wanted_version = format!("{:#?}", sent_response.version);
 
                 let mut headers = NSMutableDictionary::new();
 
@@ -207,13 +219,16 @@ extern "C" fn start_task(
                     NSString::from_str(mime.to_str().unwrap()),
                   );
                 }
-                headers.insert_id(
-                  NSString::from_str(CONTENT_LENGTH.as_str()).as_ref(),
-                  NSString::from_str(&content.len().to_string()),
-                );
+                // streamed bodies only have a length if the handler provided one
+                if let ResponseBody::Bytes(content) = &content {
+                  headers.insert_id(
+                    NSString::from_str(CONTENT_LENGTH.as_str()).as_ref(),
+                    NSString::from_str(&content.len().to_string()),
+                  );
+                }
 
                 // add headers
-                for (name, value) in sent_response.headers().iter() {
+                for (name, value) in sent_response.headers.iter() {
                   if let Ok(value) = value.to_str() {
                     headers.insert_id(
                       NSString::from_str(name.as_str()).as_ref(),
@@ -241,17 +256,52 @@ extern "C" fn start_task(
                 .unwrap();
 
                 // Send data
-                let bytes = content.as_ptr() as *mut c_void;
-                let data = NSData::alloc();
-                // MIGRATE NOTE: we copied the content to the NSData because content will be freed
-                // when out of scope but NSData will also free the content when it's done and cause doube free.
-                let data = NSData::initWithBytes_length(data, bytes, content.len());
-                check_webview_id_valid(webview_id)?;
-                check_task_is_valid(&*webview, task_key, task_uuid.clone())?;
-                objc2::exception::catch(AssertUnwindSafe(|| {
-                  task.didReceiveData(&data);
-                }))
-                .unwrap();
+                let send_data = |content: &[u8]| -> crate::Result<()> {
+                  let bytes = content.as_ptr() as *mut c_void;
+                  let data = NSData::alloc();
+                  // MIGRATE NOTE: we copied the content to the NSData because content will be freed
+                  // when out of scope but NSData will also free the content when it's done and cause doube free.
+                  let data = NSData::initWithBytes_length(data, bytes, content.len());
+                  check_webview_id_valid(webview_id)?;
+                  check_task_is_valid(&*webview, task_key, task_uuid.clone())?;
+                  objc2::exception::catch(AssertUnwindSafe(|| {
+                    task.didReceiveData(&data);
+                  }))
+                  .unwrap();
+                  Ok(())
+                };
+
+                match content {
+                  ResponseBody::Bytes(content) => send_data(&content)?,
+                  ResponseBody::Reader(mut reader) => {
+                    const CHUNK_LEN: usize = 64 * 1024;
+                    let mut buffer = vec![0; CHUNK_LEN];
+                    loop {
+                      match reader.read(&mut buffer) {
+                        Ok(0) => break,
+                        Ok(count) => send_data(&buffer[..count])?,
+                        Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                        Err(_e) => {
+                          #[cfg(feature = "tracing")]
+                          tracing::error!("failed to read custom protocol response body: {_e}");
+
+                          // fail the load instead of finishing it with a truncated body
+                          check_webview_id_valid(webview_id)?;
+                          check_task_is_valid(&*webview, task_key, task_uuid.clone())?;
+                          // NSURLErrorUnknown
+                          let error = NSError::new(-1, NSURLErrorDomain);
+                          objc2::exception::catch(AssertUnwindSafe(|| {
+                            task.didFailWithError(&error);
+                          }))
+                          .unwrap();
+
+                          webview.remove_custom_task_key(task_key);
+                          return Ok(());
+                        }
+                      }
+                    }
+                  }
+                }
 
                 // Finish
                 check_webview_id_valid(webview_id)?;
@@ -265,6 +315,35 @@ extern "C" fn start_task(
                 Ok(())
               }
 
+              // WKWebView doesn't follow redirects of custom protocols,
+              // so the target of a main document redirect is loaded instead
+              let redirect = is_main_document
+                .then(|| {
+                  custom_protocol_redirect::follow(webview_id, &redirect_uri, &sent_response)
+                })
+                .flatten();
+              let sent_response = match redirect {
+                None => sent_response,
+                Some(Redirect::To(target)) => {
+                  if let Some(target) = NSURL::URLWithString(&NSString::from_str(&target)) {
+                    // the responder may run on any thread, and the load has to wait for the response
+                    let request = NSURLRequest::requestWithURL(&target);
+                    let request: &AnyObject = &request;
+                    webview.performSelectorOnMainThread_withObject_waitUntilDone(
+                      objc2::sel!(loadRequest:),
+                      Some(request),
+                      false,
+                    );
+                  }
+                  custom_protocol_redirect::no_content()
+                }
+                Some(Redirect::TooMany) => {
+                  #[cfg(feature = "tracing")]
+                  tracing::warn!("too many redirects loading {redirect_uri}");
+                  custom_protocol_redirect::too_many_redirects()
+                }
+              };
+
               let _ = response(
                 task,
                 webview,
@@ -281,7 +360,10 @@ extern "C" fn start_task(
           function(
             webview_id,
             final_request,
-            RequestAsyncResponder { responder },
+            RequestAsyncResponder {
+              responder,
+              cancellation,
+            },
           );
         }
         Err(_) => respond_with_404(),
@@ -300,5 +382,7 @@ extern "C" fn stop_task(
   webview: &mut WryWebView,
   task: &ProtocolObject<dyn WKURLSchemeTask>,
 ) {
-  webview.remove_custom_task_key(task.hash());
+  if let Some(cancellation) = webview.remove_custom_task_key(task.hash()) {
+    cancellation.cancel();
+  }
 }
@@ -15,6 +15,7 @@ use objc2_foundation::{NSObjectProtocol, NSUUID};
 
 #[cfg(target_os = "ios")]
 use crate::wkwebview::ios::WKWebView::WKWebView;
+use crate::CancellationToken;
 #[cfg(target_os = "macos")]
 use crate::{
   wkwebview::{drag_drop, synthetic_mouse_events},
@@ -31,7 +32,7 @@ pub struct WryWebViewIvars {
   pub(crate) drag_drop_handler: Box<dyn Fn(DragDropEvent) -> bool>,
   #[cfg(target_os = "macos")]
   pub(crate) accept_first_mouse: objc2::runtime::Bool,
-  pub(crate) custom_protocol_task_ids: HashMap<usize, Retained<NSUUID>>,
+  pub(crate) custom_protocol_task_ids: HashMap<usize, (Retained<NSUUID>, CancellationToken)>,
 }
 
 declare_class!(
@@ -137,18 +138,30 @@ declare_class!(
 
 // Custom Protocol Task Checker
 impl WryWebView {
-  pub(crate) fn add_custom_task_key(&mut self, task_id: usize) -> Retained<NSUUID> {
+  pub(crate) fn add_custom_task_key(
+    &mut self,
+    task_id: usize,
+    cancellation: CancellationToken,
+  ) -> Retained<NSUUID> {
     let task_uuid = NSUUID::new();
     self
       .ivars_mut()
       .custom_protocol_task_ids
-      .insert(task_id, task_uuid.clone());
+      .insert(task_id, (task_uuid.clone(), cancellation));
     task_uuid
   }
-  pub(crate) fn remove_custom_task_key(&mut self, task_id: usize) {
-    self.ivars_mut().custom_protocol_task_ids.remove(&task_id);
+  pub(crate) fn remove_custom_task_key(&mut self, task_id: usize) -> Option<CancellationToken> {
+    self
+      .ivars_mut()
+      .custom_protocol_task_ids
+      .remove(&task_id)
+      .map(|(_, cancellation)| cancellation)
   }
   pub(crate) fn get_custom_task_uuid(&self, task_id: usize) -> Option<Retained<NSUUID>> {
-    self.ivars().custom_protocol_task_ids.get(&task_id).cloned()
+    self
+      .ivars()
+      .custom_protocol_task_ids
+      .get(&task_id)
+      .map(|(task_uuid, _)| task_uuid.clone())
   }
 }
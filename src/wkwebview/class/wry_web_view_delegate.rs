@@ -4,22 +4,29 @@
 
 use std::ffi::CStr;
 
-use http::Request;
 use objc2::{
-  declare_class, msg_send_id,
+  declare_class, msg_send, msg_send_id,
   mutability::MainThreadOnly,
-  rc::Retained,
-  runtime::{NSObject, ProtocolObject},
+  rc::{Retained, Weak},
+  runtime::{AnyClass, AnyObject, NSObject, ProtocolObject},
   ClassType, DeclaredClass,
 };
-use objc2_foundation::{MainThreadMarker, NSObjectProtocol, NSString};
-use objc2_web_kit::{WKScriptMessage, WKScriptMessageHandler, WKUserContentController};
+use objc2_foundation::{MainThreadMarker, NSError, NSObjectProtocol, NSString};
+use objc2_web_kit::{
+  WKFrameInfo, WKScriptMessage, WKScriptMessageHandler, WKUserContentController,
+};
+
+use crate::{
+  ipc::{self, IpcDispatcher, IpcFrame},
+  WryWebView,
+};
 
 pub const IPC_MESSAGE_HANDLER_NAME: &str = "ipc";
 
 pub struct WryWebViewDelegateIvars {
   pub controller: Retained<WKUserContentController>,
-  pub ipc_handler: Box<dyn Fn(Request<String>)>,
+  pub dispatcher: IpcDispatcher,
+  pub webview: Weak<WryWebView>,
 }
 
 declare_class!(
@@ -50,7 +57,7 @@ declare_class!(
         #[cfg(feature = "tracing")]
         let _span = tracing::info_span!(parent: None, "wry::ipc::handle").entered();
 
-        let ipc_handler = &this.ivars().ipc_handler;
+        let dispatcher = &this.ivars().dispatcher;
         let body = msg.body();
         let is_string = Retained::cast::<NSObject>(body.clone()).isKindOfClass(NSString::class());
         if is_string {
@@ -67,7 +74,39 @@ declare_class!(
             CStr::from_ptr(url_utf8).to_str(),
             CStr::from_ptr(js_utf8).to_str(),
           ) {
-            ipc_handler(Request::builder().uri(url).body(js.to_string()).unwrap());
+            let security_origin = frame_info.securityOrigin();
+            let protocol = security_origin.protocol().to_string();
+            let origin = match security_origin.port() {
+              _ if protocol.is_empty() => "null".to_string(),
+              0 => format!("{protocol}://{}", security_origin.host()),
+              port => format!("{protocol}://{}:{port}", security_origin.host()),
+            };
+            let frame = IpcFrame {
+              url: url.to_string(),
+              origin,
+              is_main_frame: frame_info.isMainFrame(),
+            };
+
+            // the replies of `invoke` calls are evaluated in the frame that sent them,
+            // which needs content worlds, available since macOS 11 and iOS 14
+            let content_world = AnyClass::get("WKContentWorld");
+            if content_world.is_none() && !frame.is_main_frame && ipc::is_invoke(js) {
+              #[cfg(feature = "tracing")]
+              tracing::warn!("IPC invoke calls are not supported in subframes on this OS version");
+              return;
+            }
+
+            if let Some(reply) = dispatcher.dispatch(frame.request(js.to_string())) {
+              if let Some(webview) = this.ivars().webview.load() {
+                let reply = NSString::from_str(&reply);
+                match content_world {
+                  Some(content_world) => {
+                    evaluate_in_frame(&webview, &reply, &frame_info, content_world)
+                  }
+                  None => webview.evaluateJavaScript_completionHandler(&reply, None),
+                }
+              }
+            }
             return;
           }
         }
@@ -79,16 +118,36 @@ declare_class!(
   }
 );
 
+/// Evaluates `js` in the page world of `frame`.
+unsafe fn evaluate_in_frame(
+  webview: &WryWebView,
+  js: &NSString,
+  frame: &WKFrameInfo,
+  content_world: &AnyClass,
+) {
+  let page_world: Retained<AnyObject> = msg_send_id![content_world, pageWorld];
+  let completion_handler: Option<&block2::Block<dyn Fn(*mut AnyObject, *mut NSError)>> = None;
+  let () = msg_send![
+    webview,
+    evaluateJavaScript: js,
+    inFrame: frame,
+    inContentWorld: &*page_world,
+    completionHandler: completion_handler
+  ];
+}
+
 impl WryWebViewDelegate {
   pub fn new(
     controller: Retained<WKUserContentController>,
-    ipc_handler: Box<dyn Fn(Request<String>)>,
+    dispatcher: IpcDispatcher,
+    webview: &WryWebView,
     mtm: MainThreadMarker,
   ) -> Retained<Self> {
     let delegate = mtm
       .alloc::<WryWebViewDelegate>()
       .set_ivars(WryWebViewDelegateIvars {
-        ipc_handler,
+        dispatcher,
+        webview: Weak::from(webview),
         controller,
       });
 
@@ -88,7 +88,10 @@ use crate::{
   },
 };
 
-use crate::{Error, Rect, RequestAsyncResponder, Result, WebViewAttributes, RGBA};
+use crate::{
+  custom_protocol_url::UrlStyle, ipc::IpcDispatcher, Error, Rect, RequestAsyncResponder, Result,
+  WebViewAttributes, RGBA,
+};
 
 use http::Request;
 
@@ -397,12 +400,12 @@ impl InnerWebView {
       }
 
       // Message handler
-      let ipc_handler_delegate = if let Some(ipc_handler) = attributes.ipc_handler {
-        let delegate = WryWebViewDelegate::new(manager.clone(), ipc_handler, mtm);
-        Some(delegate)
-      } else {
-        None
-      };
+      let ipc_policy = attributes
+        .ipc_policy
+        .map(|policy| policy.resolve(UrlStyle::Custom));
+      let ipc_handler_delegate =
+        IpcDispatcher::new(attributes.ipc_handler, attributes.ipc_router, ipc_policy)
+          .map(|dispatcher| WryWebViewDelegate::new(manager.clone(), dispatcher, &webview, mtm));
 
       // Document title changed handler
       let document_title_changed_observer =
@@ -476,11 +479,10 @@ impl InnerWebView {
       };
 
       // Initialize scripts
-      w.init(
-r#"Object.defineProperty(window, 'ipc', {
-  value: Object.freeze({postMessage: function(s) {window.webkit.messageHandlers.ipc.postMessage(s);}})
-});"#,
-      );
+      w.init(&crate::ipc::ipc_script(
+        "function(s) {window.webkit.messageHandlers.ipc.postMessage(s);}",
+        false,
+      ));
       for js in attributes.initialization_scripts {
         w.init(&js);
       }
@@ -545,6 +547,10 @@ r#"Object.defineProperty(window, 'ipc', {
     url_from_webview(&self.webview)
   }
 
+  pub fn custom_protocol_url_style(&self) -> UrlStyle {
+    UrlStyle::Custom
+  }
+
   pub fn eval(&self, js: &str, callback: Option<impl Fn(String) + Send + 'static>) -> Result<()> {
     if let Some(scripts) = &mut *self.pending_scripts.lock().unwrap() {
       scripts.push(js.into());
@@ -963,6 +969,7 @@ pub fn platform_webview_version() -> Result<String> {
 impl Drop for InnerWebView {
   fn drop(&mut self) {
     WEBVIEW_IDS.lock().unwrap().remove(&self.id);
+    crate::custom_protocol_redirect::forget(&self.id);
 
     // We need to drop handler closures here
     unsafe {